@@ -603,7 +603,7 @@ impl Renderer {
                 glyphs.resize(positioned_glyph.glyph_index as usize + 1, 0)
             }
 
-            let subpixel = (positioned_glyph.subpixel_x / SUBPIXEL_GRANULARITY).round() as u8;
+            let subpixel = positioned_glyph.subpixel_bucket(SUBPIXEL_GRANULARITY) as u8;
             glyphs[positioned_glyph.glyph_index as usize] |= 1 << subpixel
         }
 
@@ -706,7 +706,7 @@ impl Renderer {
             let glyph_index = positioned_glyph.glyph_index;
             let glyph_rect = glyph_store.outlines.glyph_subpixel_bounds(glyph_index, point_size);
 
-            let subpixel = (positioned_glyph.subpixel_x / SUBPIXEL_GRANULARITY).round() as u8;
+            let subpixel = positioned_glyph.subpixel_bucket(SUBPIXEL_GRANULARITY) as u8;
 
             let glyph_rect_i = glyph_rect.round_out();
             let glyph_size_i = glyph_rect_i.size();
@@ -12,6 +12,9 @@
 //!
 //! Consulting this table is typically the first step when rendering some text.
 
+use std::cmp;
+use std::ops::RangeInclusive;
+
 /// A consecutive series of Unicode codepoints.
 #[derive(Clone, Copy, Debug)]
 pub struct CodepointRange {
@@ -25,6 +28,9 @@ pub struct CodepointRange {
 #[derive(Clone, Debug)]
 pub struct CodepointRanges {
     /// Consecutive series of codepoints.
+    ///
+    /// The methods of `CodepointRanges` keep these sorted, with adjacent and overlapping series
+    /// coalesced into one.
     pub ranges: Vec<CodepointRange>,
 }
 
@@ -66,7 +72,7 @@ impl CodepointRanges {
         for &ch in chars {
             match ranges.last_mut() {
                 Some(ref mut range) if range.end == ch as u32 => continue,
-                Some(ref mut range) if range.end == ch as u32 + 1 => {
+                Some(ref mut range) if range.end + 1 == ch as u32 => {
                     range.end += 1;
                     continue
                 }
@@ -79,6 +85,55 @@ impl CodepointRanges {
             ranges: ranges,
         }
     }
+
+    /// Adds a single character.
+    #[inline]
+    pub fn add_char(&mut self, ch: char) {
+        self.add_range(CodepointRange::new(ch as u32, ch as u32))
+    }
+
+    /// Adds every character in the given range.
+    #[inline]
+    pub fn add_char_range(&mut self, chars: RangeInclusive<char>) {
+        self.add_range(CodepointRange::new(*chars.start() as u32, *chars.end() as u32))
+    }
+
+    /// Adds every codepoint in the given range, merging it with the series that it overlaps or
+    /// touches.
+    ///
+    /// Empty ranges, whose end is before their start, are ignored.
+    pub fn add_range(&mut self, range: CodepointRange) {
+        if range.end < range.start {
+            return
+        }
+
+        // Find the series that overlap or touch the new range.
+        let first = match self.ranges.binary_search_by(|existing| {
+            existing.end.saturating_add(1).cmp(&range.start)
+        }) {
+            Ok(index) | Err(index) => index,
+        };
+        let mut last = first;
+        while last < self.ranges.len() && self.ranges[last].start <= range.end.saturating_add(1) {
+            last += 1
+        }
+
+        if first == last {
+            self.ranges.insert(first, range);
+            return
+        }
+        let merged = CodepointRange::new(cmp::min(range.start, self.ranges[first].start),
+                                         cmp::max(range.end, self.ranges[last - 1].end));
+        self.ranges.drain((first + 1)..last);
+        self.ranges[first] = merged
+    }
+
+    /// Adds every codepoint in `other`.
+    pub fn merge(&mut self, other: &CodepointRanges) {
+        for &range in &other.ranges {
+            self.add_range(range)
+        }
+    }
 }
 
 /// An iterator over all codepoints in a range.
@@ -21,13 +21,18 @@ use tables::cvt;
 use tables::cmap::{self, CmapTable};
 use tables::fpgm;
 use tables::glyf::{self, GlyfTable};
+use tables::gsub::{self, GsubTable};
 use tables::head::{self, HeadTable};
 use tables::hhea::{self, HheaTable};
 use tables::hmtx::{self, HmtxTable};
 use tables::kern::{self, KernTable};
 use tables::loca::{self, LocaTable};
+use tables::maxp::{self, MaxpTable};
 use tables::os_2::{self, Os2Table};
+use tables::post::{self, PostTable};
 use tables::prep;
+use tables::vhea::{self, VheaTable};
+use tables::vmtx::{self, VmtxTable};
 use util::Jump;
 
 const OTTO: u32 = ((b'O' as u32) << 24) |
@@ -35,10 +40,11 @@ const OTTO: u32 = ((b'O' as u32) << 24) |
                   ((b'T' as u32) << 8)  |
                    (b'O' as u32);
 
-pub const KNOWN_TABLE_COUNT: usize = 12;
+pub const KNOWN_TABLE_COUNT: usize = 17;
 
 pub static KNOWN_TABLES: [u32; KNOWN_TABLE_COUNT] = [
     cff::TAG,
+    gsub::TAG,
     os_2::TAG,
     cmap::TAG,
     cvt::TAG,
@@ -49,22 +55,31 @@ pub static KNOWN_TABLES: [u32; KNOWN_TABLE_COUNT] = [
     hmtx::TAG,
     kern::TAG,
     loca::TAG,
+    maxp::TAG,
+    post::TAG,
     prep::TAG,
+    vhea::TAG,
+    vmtx::TAG,
 ];
 
 // This must agree with the above.
 const TABLE_INDEX_CFF:  usize = 0;
-const TABLE_INDEX_OS_2: usize = 1;
-const TABLE_INDEX_CMAP: usize = 2;
-const TABLE_INDEX_CVT:  usize = 3;
-const TABLE_INDEX_FPGM: usize = 4;
-const TABLE_INDEX_GLYF: usize = 5;
-const TABLE_INDEX_HEAD: usize = 6;
-const TABLE_INDEX_HHEA: usize = 7;
-const TABLE_INDEX_HMTX: usize = 8;
-const TABLE_INDEX_KERN: usize = 9;
-const TABLE_INDEX_LOCA: usize = 10;
-const TABLE_INDEX_PREP: usize = 11;
+const TABLE_INDEX_GSUB: usize = 1;
+const TABLE_INDEX_OS_2: usize = 2;
+const TABLE_INDEX_CMAP: usize = 3;
+const TABLE_INDEX_CVT:  usize = 4;
+const TABLE_INDEX_FPGM: usize = 5;
+const TABLE_INDEX_GLYF: usize = 6;
+const TABLE_INDEX_HEAD: usize = 7;
+const TABLE_INDEX_HHEA: usize = 8;
+const TABLE_INDEX_HMTX: usize = 9;
+const TABLE_INDEX_KERN: usize = 10;
+const TABLE_INDEX_LOCA: usize = 11;
+const TABLE_INDEX_MAXP: usize = 12;
+const TABLE_INDEX_POST: usize = 13;
+const TABLE_INDEX_PREP: usize = 14;
+const TABLE_INDEX_VHEA: usize = 15;
+const TABLE_INDEX_VMTX: usize = 16;
 
 pub static SFNT_VERSIONS: [u32; 3] = [
     0x10000,
@@ -84,8 +99,13 @@ pub struct FontTables<'a> {
     // Optional tables.
     pub cff: Option<CffTable<'a>>,
     pub glyf: Option<GlyfTable<'a>>,
+    pub gsub: Option<GsubTable<'a>>,
     pub loca: Option<LocaTable<'a>>,
     pub kern: Option<KernTable<'a>>,
+    pub maxp: Option<MaxpTable>,
+    pub post: Option<PostTable>,
+    pub vhea: Option<VheaTable>,
+    pub vmtx: Option<VmtxTable<'a>>,
 
     // Optional tables that need no parsing.
     pub cvt: Option<FontTable<'a>>,
@@ -159,8 +179,13 @@ impl<'a> Font<'a> {
 
             cff: cff_table,
             glyf: tables[TABLE_INDEX_GLYF].map(GlyfTable::new),
+            gsub: tables[TABLE_INDEX_GSUB].and_then(|table| GsubTable::new(table).ok()),
             loca: loca_table,
             kern: tables[TABLE_INDEX_KERN].and_then(|table| KernTable::new(table).ok()),
+            maxp: tables[TABLE_INDEX_MAXP].and_then(|table| MaxpTable::new(table).ok()),
+            post: tables[TABLE_INDEX_POST].and_then(|table| PostTable::new(table).ok()),
+            vhea: tables[TABLE_INDEX_VHEA].and_then(|table| VheaTable::new(table).ok()),
+            vmtx: tables[TABLE_INDEX_VMTX].map(VmtxTable::new),
 
             cvt: tables[TABLE_INDEX_CVT],
             fpgm: tables[TABLE_INDEX_FPGM],
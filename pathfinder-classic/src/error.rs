@@ -43,6 +43,8 @@ pub enum FontError {
     UnsupportedHheaVersion,
     /// We don't support the declared version of the font's OS/2 and Windows table.
     UnsupportedOs2Version,
+    /// We don't support the declared version of the font's vertical metrics.
+    UnsupportedVheaVersion,
     /// A required table is missing.
     RequiredTableMissing,
     /// An integer in a CFF DICT was not found.
@@ -122,6 +124,23 @@ pub enum GlyphStoreCreationError {
     FontError(FontError),
     /// An error occurred when uploading the outlines to the GPU.
     GlError(GlError),
+    /// Glyphs couldn't be added because the outlines are shared with another glyph store. See
+    /// `GlyphStore::from_shared_outlines()`.
+    OutlinesShared,
+}
+
+/// An error in laying out text. See `Typesetter::try_add_text()`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TypesetterError {
+    /// The font's character map could not be read to map the text to glyphs.
+    GlyphMappingFailed(FontError),
+    /// The metrics of a glyph that the typesetter needs could not be read from the font.
+    MetricsMissing(FontError),
+    /// The bidi levels passed to `Typesetter::try_add_text_with_levels()` didn't have one entry
+    /// per byte of the string.
+    LevelCountMismatch,
+    /// No fonts were passed to `Typesetter::try_add_text_with_fallback()`.
+    NoFonts,
 }
 
 /// An error in construction of a hinter.
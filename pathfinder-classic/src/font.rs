@@ -19,7 +19,9 @@ use containers::woff;
 use error::FontError;
 use euclid::Point2D;
 use outline::GlyphBounds;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tables::hmtx::HorizontalMetrics;
+use tables::vmtx::VerticalMetrics;
 
 /// A handle to a font backed by a byte buffer containing the contents of the file (`.ttf`,
 /// `.otf`), etc.
@@ -28,8 +30,12 @@ use tables::hmtx::HorizontalMetrics;
 pub struct Font<'a> {
     pub bytes: &'a [u8],
     tables: FontTables<'a>,
+    id: usize,
 }
 
+/// The ID of the next font to be created. See `Font::id()`.
+static NEXT_FONT_ID: AtomicUsize = AtomicUsize::new(0);
+
 #[doc(hidden)]
 #[derive(Clone, Copy, Debug)]
 pub struct FontTable<'a> {
@@ -42,6 +48,7 @@ impl<'a> Font<'a> {
         Font {
             bytes: bytes,
             tables: tables,
+            id: NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed),
         }
     }
 
@@ -91,6 +98,15 @@ impl<'a> Font<'a> {
         self.tables.cmap.glyph_mapping_for_codepoint_ranges(codepoint_ranges)
     }
 
+    /// Returns a number identifying this font, for keying data cached per font.
+    ///
+    /// Every font created gets a different ID, even one loaded again from the same bytes, so an
+    /// ID is never shared with a font that has since been freed.
+    #[inline]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
     /// Calls the given callback for each point in the supplied glyph's contour.
     ///
     /// This function is the primary method for accessing a glyph's outline.
@@ -152,6 +168,19 @@ impl<'a> Font<'a> {
         self.tables.head.units_per_em
     }
 
+    /// Returns the number of glyphs in the font. Glyph IDs run from zero up to, but not including,
+    /// this number.
+    ///
+    /// This comes from the `maxp` table. If the font has none, the number of glyphs with their own
+    /// horizontal metrics is returned instead.
+    #[inline]
+    pub fn num_glyphs(&self) -> u16 {
+        match self.tables.maxp {
+            None => self.tables.hhea.number_of_h_metrics,
+            Some(ref maxp) => maxp.num_glyphs,
+        }
+    }
+
     /// Returns the horizontal metrics for the glyph with the given ID.
     ///
     /// Horizontal metrics are important for text shaping, as they specify the number of units to
@@ -161,6 +190,59 @@ impl<'a> Font<'a> {
         self.tables.hmtx.metrics_for_glyph(&self.tables.hhea, glyph_id)
     }
 
+    /// Returns the advance width of the glyph with the given ID in font units.
+    ///
+    /// This is the `advance_width` of `metrics_for_glyph()`, but doesn't read the rest of the
+    /// metrics.
+    #[inline]
+    pub fn advance_for_glyph(&self, glyph_id: u16) -> Result<u16, FontError> {
+        self.tables.hmtx.advance_for_glyph(&self.tables.hhea, glyph_id)
+    }
+
+    /// Returns the advance widths of the glyphs with the given IDs in font units, in the same
+    /// order.
+    ///
+    /// Fails if any of the advances can't be read.
+    pub fn advances_for_glyphs(&self, glyph_ids: &[u16]) -> Result<Vec<u16>, FontError> {
+        let (hmtx, hhea) = (&self.tables.hmtx, &self.tables.hhea);
+        glyph_ids.iter().map(|&glyph_id| hmtx.advance_for_glyph(hhea, glyph_id)).collect()
+    }
+
+    /// Returns the vertical metrics for the glyph with the given ID.
+    ///
+    /// These are used when typesetting vertical text. If the font has no vertical metrics, the
+    /// advance height is synthesized from `font.ascender() - font.descender()`, and the top-side
+    /// bearing places the top of that box at the ascender.
+    pub fn vertical_metrics_for_glyph(&self, glyph_id: u16)
+                                      -> Result<VerticalMetrics, FontError> {
+        if let (&Some(ref vhea), Some(vmtx)) = (&self.tables.vhea, self.tables.vmtx) {
+            return vmtx.metrics_for_glyph(vhea, glyph_id)
+        }
+
+        let top = self.glyph_bounds(glyph_id).map(|bounds| bounds.top).unwrap_or(0);
+        Ok(VerticalMetrics {
+            advance_height: (self.ascender() as i32 - self.descender() as i32) as u16,
+            tsb: (self.ascender() as i32 - top) as i16,
+        })
+    }
+
+    /// Returns true if the font declares that all of its glyphs have the same advance width.
+    #[inline]
+    pub fn is_fixed_pitch(&self) -> bool {
+        match self.tables.post {
+            None => false,
+            Some(ref post) => post.is_fixed_pitch,
+        }
+    }
+
+    /// Returns true if the font has a `kern` table.
+    ///
+    /// If this is false, `kerning_for_glyph_pair()` always returns zero.
+    #[inline]
+    pub fn has_kerning(&self) -> bool {
+        self.tables.kern.is_some()
+    }
+
     /// Returns the kerning between the given two glyph IDs in font units.
     ///
     /// Positive values move glyphs farther apart; negative values move glyphs closer together.
@@ -174,6 +256,79 @@ impl<'a> Font<'a> {
         }
     }
 
+    /// Returns the lookups of the font's `GSUB` table that the given OpenType features enable, in
+    /// the order that they apply, each with the value of the feature that enabled it. Pass the
+    /// result to `substitute_glyph()`.
+    ///
+    /// Each feature is a tag, such as `shaper::feature_tag(b"smcp")`, and a value. A value of
+    /// zero disables the feature, and a later entry for a tag overrides an earlier one. The result
+    /// is empty if the font has no `GSUB` table.
+    #[inline]
+    pub fn substitution_lookups(&self, features: &[(u32, u32)]) -> Vec<(u16, u32)> {
+        self.substitution_lookups_in(None, features)
+    }
+
+    /// Like `substitution_lookups()`, but only finds features among those that the font lists for
+    /// the given script and language, each a tag such as `shaper::feature_tag(b"cyrl")` and
+    /// `shaper::feature_tag(b"SRB ")`.
+    ///
+    /// Scripts and languages that the font doesn't list fall back to its defaults. See
+    /// `GsubTable::lookups_for_feature_in_language()`.
+    #[inline]
+    pub fn substitution_lookups_for_language(&self,
+                                             script: u32,
+                                             language: u32,
+                                             features: &[(u32, u32)])
+                                             -> Vec<(u16, u32)> {
+        self.substitution_lookups_in(Some((script, language)), features)
+    }
+
+    fn substitution_lookups_in(&self, language: Option<(u32, u32)>, features: &[(u32, u32)])
+                               -> Vec<(u16, u32)> {
+        let gsub = match self.tables.gsub {
+            None => return vec![],
+            Some(gsub) => gsub,
+        };
+
+        let mut lookups = vec![];
+        for (feature_index, &(tag, value)) in features.iter().enumerate() {
+            let overridden = features[(feature_index + 1)..].iter().any(|&(later_tag, _)| {
+                later_tag == tag
+            });
+            if value == 0 || overridden {
+                continue
+            }
+            let lookup_indices = match language {
+                None => gsub.lookups_for_feature(tag),
+                Some((script, language)) => {
+                    gsub.lookups_for_feature_in_language(script, language, tag)
+                }
+            };
+            let lookup_indices = lookup_indices.unwrap_or_default();
+            lookups.extend(lookup_indices.into_iter().map(|lookup_index| (lookup_index, value)))
+        }
+
+        // Lookups apply in the order of the lookup list, whichever feature they belong to.
+        lookups.sort_by_key(|&(lookup_index, _)| lookup_index);
+        lookups.dedup_by_key(|&mut (lookup_index, _)| lookup_index);
+        lookups
+    }
+
+    /// Returns the glyph that the given `GSUB` lookups, as returned by `substitution_lookups()`,
+    /// substitute for the glyph with the given ID.
+    ///
+    /// Only lookups that replace one glyph with another are applied; ligatures and other
+    /// substitutions that change the number of glyphs are skipped.
+    pub fn substitute_glyph(&self, lookups: &[(u16, u32)], glyph_id: u16) -> u16 {
+        let gsub = match self.tables.gsub {
+            None => return glyph_id,
+            Some(gsub) => gsub,
+        };
+        lookups.iter().fold(glyph_id, |glyph_id, &(lookup_index, value)| {
+            gsub.substitute(lookup_index, value, glyph_id).unwrap_or(glyph_id)
+        })
+    }
+
     /// Returns the distance from the baseline to the top of the text box in font units.
     ///
     /// The following expression computes the baseline-to-baseline height:
@@ -201,6 +356,75 @@ impl<'a> Font<'a> {
         self.tables.os_2.typo_line_gap
     }
 
+    /// Returns the distance from the center line to the right edge of a column of vertical text
+    /// in font units.
+    ///
+    /// If the font has no vertical metrics, this is half an em.
+    ///
+    /// The following expression computes the center-to-center distance between columns:
+    /// `font.vertical_ascender() - font.vertical_descender() + font.vertical_line_gap()`.
+    #[inline]
+    pub fn vertical_ascender(&self) -> i16 {
+        match self.tables.vhea {
+            None => (self.units_per_em() / 2) as i16,
+            Some(ref vhea) => vhea.ascender,
+        }
+    }
+
+    /// Returns the distance from the center line to the left edge of a column of vertical text
+    /// in font units.
+    ///
+    /// If the font has no vertical metrics, this is minus half an em.
+    ///
+    /// The following expression computes the center-to-center distance between columns:
+    /// `font.vertical_ascender() - font.vertical_descender() + font.vertical_line_gap()`.
+    #[inline]
+    pub fn vertical_descender(&self) -> i16 {
+        match self.tables.vhea {
+            None => -((self.units_per_em() / 2) as i16),
+            Some(ref vhea) => vhea.descender,
+        }
+    }
+
+    /// Returns the recommended extra gap between columns of vertical text in font units.
+    ///
+    /// The following expression computes the center-to-center distance between columns:
+    /// `font.vertical_ascender() - font.vertical_descender() + font.vertical_line_gap()`.
+    #[inline]
+    pub fn vertical_line_gap(&self) -> i16 {
+        match self.tables.vhea {
+            None => 0,
+            Some(ref vhea) => vhea.line_gap,
+        }
+    }
+
+    /// Returns the recommended placement of an underline in pixels at the given point size.
+    ///
+    /// If the font has no `post` table, this falls back to a line a twentieth of an em thick
+    /// whose top is a tenth of an em below the baseline.
+    pub fn underline_metrics(&self, point_size: f32) -> DecorationMetrics {
+        let units_per_em = self.units_per_em() as f32;
+        let (position, thickness) = match self.tables.post {
+            Some(ref post) => (post.underline_position as f32, post.underline_thickness as f32),
+            None => (-units_per_em / 10.0, units_per_em / 20.0),
+        };
+
+        DecorationMetrics {
+            position: position * point_size / units_per_em,
+            thickness: thickness * point_size / units_per_em,
+        }
+    }
+
+    /// Returns the recommended placement of a strikeout line in pixels at the given point size.
+    pub fn strikeout_metrics(&self, point_size: f32) -> DecorationMetrics {
+        let units_per_em = self.units_per_em() as f32;
+        let os_2 = &self.tables.os_2;
+        DecorationMetrics {
+            position: os_2.strikeout_position as f32 * point_size / units_per_em,
+            thickness: os_2.strikeout_size as f32 * point_size / units_per_em,
+        }
+    }
+
     /// Returns the Control Value Table of the font.
     #[inline]
     pub fn control_value_table(&self) -> &[u8] {
@@ -229,6 +453,17 @@ impl<'a> Font<'a> {
     }
 }
 
+/// The placement of a line drawn through or under text, in pixels.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DecorationMetrics {
+    /// The distance from the baseline up to the top of the line.
+    ///
+    /// Lines whose tops are below the baseline, as underlines usually are, have negative positions.
+    pub position: f32,
+    /// The thickness of the line.
+    pub thickness: f32,
+}
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Point {
     /// Where the point is located in glyph space.
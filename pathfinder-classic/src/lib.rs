@@ -83,9 +83,19 @@ extern crate gl;
 #[cfg(test)]
 extern crate memmap;
 extern crate num_traits;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serde-serialization")]
+extern crate serde;
+#[cfg(feature = "serde-serialization")]
+#[macro_use]
+extern crate serde_derive;
+extern crate unicode_segmentation;
 #[cfg(test)]
 #[macro_use]
 extern crate quickcheck;
+#[cfg(all(test, feature = "serde-serialization"))]
+extern crate serde_json;
 #[cfg(test)]
 extern crate test;
 
@@ -101,6 +111,7 @@ pub mod shaper;
 pub mod typesetter;
 
 mod containers;
+mod mirror;
 mod rect_packer;
 mod tables;
 mod util;
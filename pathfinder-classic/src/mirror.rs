@@ -0,0 +1,131 @@
+// Copyright 2017 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Bidi mirroring of characters in right-to-left text.
+
+/// Pairs of characters with the `Bidi_Mirroring_Glyph` property, sorted by the first character.
+///
+/// This covers the paired brackets, quotation marks, and mathematical relations listed in
+/// `BidiMirroring.txt`; characters that mirror without a counterpart are left out, since they have
+/// to be mirrored by the font. Both characters of each pair have the same UTF-8 length.
+static MIRRORED_CHARS: [(char, char); 276] = [
+    ('\u{0028}', '\u{0029}'), ('\u{0029}', '\u{0028}'), ('\u{003c}', '\u{003e}'),
+    ('\u{003e}', '\u{003c}'), ('\u{005b}', '\u{005d}'), ('\u{005d}', '\u{005b}'),
+    ('\u{007b}', '\u{007d}'), ('\u{007d}', '\u{007b}'), ('\u{00ab}', '\u{00bb}'),
+    ('\u{00bb}', '\u{00ab}'), ('\u{0f3a}', '\u{0f3b}'), ('\u{0f3b}', '\u{0f3a}'),
+    ('\u{0f3c}', '\u{0f3d}'), ('\u{0f3d}', '\u{0f3c}'), ('\u{169b}', '\u{169c}'),
+    ('\u{169c}', '\u{169b}'), ('\u{2039}', '\u{203a}'), ('\u{203a}', '\u{2039}'),
+    ('\u{2045}', '\u{2046}'), ('\u{2046}', '\u{2045}'), ('\u{207d}', '\u{207e}'),
+    ('\u{207e}', '\u{207d}'), ('\u{208d}', '\u{208e}'), ('\u{208e}', '\u{208d}'),
+    ('\u{2208}', '\u{220b}'), ('\u{2209}', '\u{220c}'), ('\u{220a}', '\u{220d}'),
+    ('\u{220b}', '\u{2208}'), ('\u{220c}', '\u{2209}'), ('\u{220d}', '\u{220a}'),
+    ('\u{2215}', '\u{29f5}'), ('\u{223c}', '\u{223d}'), ('\u{223d}', '\u{223c}'),
+    ('\u{2243}', '\u{22cd}'), ('\u{2252}', '\u{2253}'), ('\u{2253}', '\u{2252}'),
+    ('\u{2254}', '\u{2255}'), ('\u{2255}', '\u{2254}'), ('\u{2264}', '\u{2265}'),
+    ('\u{2265}', '\u{2264}'), ('\u{2266}', '\u{2267}'), ('\u{2267}', '\u{2266}'),
+    ('\u{2268}', '\u{2269}'), ('\u{2269}', '\u{2268}'), ('\u{226a}', '\u{226b}'),
+    ('\u{226b}', '\u{226a}'), ('\u{226e}', '\u{226f}'), ('\u{226f}', '\u{226e}'),
+    ('\u{2270}', '\u{2271}'), ('\u{2271}', '\u{2270}'), ('\u{2272}', '\u{2273}'),
+    ('\u{2273}', '\u{2272}'), ('\u{2274}', '\u{2275}'), ('\u{2275}', '\u{2274}'),
+    ('\u{2276}', '\u{2277}'), ('\u{2277}', '\u{2276}'), ('\u{2278}', '\u{2279}'),
+    ('\u{2279}', '\u{2278}'), ('\u{227a}', '\u{227b}'), ('\u{227b}', '\u{227a}'),
+    ('\u{227c}', '\u{227d}'), ('\u{227d}', '\u{227c}'), ('\u{227e}', '\u{227f}'),
+    ('\u{227f}', '\u{227e}'), ('\u{2280}', '\u{2281}'), ('\u{2281}', '\u{2280}'),
+    ('\u{2282}', '\u{2283}'), ('\u{2283}', '\u{2282}'), ('\u{2284}', '\u{2285}'),
+    ('\u{2285}', '\u{2284}'), ('\u{2286}', '\u{2287}'), ('\u{2287}', '\u{2286}'),
+    ('\u{2288}', '\u{2289}'), ('\u{2289}', '\u{2288}'), ('\u{228a}', '\u{228b}'),
+    ('\u{228b}', '\u{228a}'), ('\u{228f}', '\u{2290}'), ('\u{2290}', '\u{228f}'),
+    ('\u{2291}', '\u{2292}'), ('\u{2292}', '\u{2291}'), ('\u{2298}', '\u{29b8}'),
+    ('\u{22a2}', '\u{22a3}'), ('\u{22a3}', '\u{22a2}'), ('\u{22a6}', '\u{2ade}'),
+    ('\u{22a8}', '\u{2ae4}'), ('\u{22a9}', '\u{2ae3}'), ('\u{22ab}', '\u{2ae5}'),
+    ('\u{22b0}', '\u{22b1}'), ('\u{22b1}', '\u{22b0}'), ('\u{22b2}', '\u{22b3}'),
+    ('\u{22b3}', '\u{22b2}'), ('\u{22b4}', '\u{22b5}'), ('\u{22b5}', '\u{22b4}'),
+    ('\u{22b6}', '\u{22b7}'), ('\u{22b7}', '\u{22b6}'), ('\u{22c9}', '\u{22ca}'),
+    ('\u{22ca}', '\u{22c9}'), ('\u{22cb}', '\u{22cc}'), ('\u{22cc}', '\u{22cb}'),
+    ('\u{22cd}', '\u{2243}'), ('\u{22d0}', '\u{22d1}'), ('\u{22d1}', '\u{22d0}'),
+    ('\u{22d6}', '\u{22d7}'), ('\u{22d7}', '\u{22d6}'), ('\u{22d8}', '\u{22d9}'),
+    ('\u{22d9}', '\u{22d8}'), ('\u{22da}', '\u{22db}'), ('\u{22db}', '\u{22da}'),
+    ('\u{22dc}', '\u{22dd}'), ('\u{22dd}', '\u{22dc}'), ('\u{22de}', '\u{22df}'),
+    ('\u{22df}', '\u{22de}'), ('\u{22e0}', '\u{22e1}'), ('\u{22e1}', '\u{22e0}'),
+    ('\u{22e2}', '\u{22e3}'), ('\u{22e3}', '\u{22e2}'), ('\u{22e4}', '\u{22e5}'),
+    ('\u{22e5}', '\u{22e4}'), ('\u{22e6}', '\u{22e7}'), ('\u{22e7}', '\u{22e6}'),
+    ('\u{22e8}', '\u{22e9}'), ('\u{22e9}', '\u{22e8}'), ('\u{22ea}', '\u{22eb}'),
+    ('\u{22eb}', '\u{22ea}'), ('\u{22ec}', '\u{22ed}'), ('\u{22ed}', '\u{22ec}'),
+    ('\u{22f0}', '\u{22f1}'), ('\u{22f1}', '\u{22f0}'), ('\u{2308}', '\u{2309}'),
+    ('\u{2309}', '\u{2308}'), ('\u{230a}', '\u{230b}'), ('\u{230b}', '\u{230a}'),
+    ('\u{2329}', '\u{232a}'), ('\u{232a}', '\u{2329}'), ('\u{2768}', '\u{2769}'),
+    ('\u{2769}', '\u{2768}'), ('\u{276a}', '\u{276b}'), ('\u{276b}', '\u{276a}'),
+    ('\u{276c}', '\u{276d}'), ('\u{276d}', '\u{276c}'), ('\u{276e}', '\u{276f}'),
+    ('\u{276f}', '\u{276e}'), ('\u{2770}', '\u{2771}'), ('\u{2771}', '\u{2770}'),
+    ('\u{2772}', '\u{2773}'), ('\u{2773}', '\u{2772}'), ('\u{2774}', '\u{2775}'),
+    ('\u{2775}', '\u{2774}'), ('\u{27c3}', '\u{27c4}'), ('\u{27c4}', '\u{27c3}'),
+    ('\u{27c5}', '\u{27c6}'), ('\u{27c6}', '\u{27c5}'), ('\u{27c8}', '\u{27c9}'),
+    ('\u{27c9}', '\u{27c8}'), ('\u{27d5}', '\u{27d6}'), ('\u{27d6}', '\u{27d5}'),
+    ('\u{27dd}', '\u{27de}'), ('\u{27de}', '\u{27dd}'), ('\u{27e2}', '\u{27e3}'),
+    ('\u{27e3}', '\u{27e2}'), ('\u{27e4}', '\u{27e5}'), ('\u{27e5}', '\u{27e4}'),
+    ('\u{27e6}', '\u{27e7}'), ('\u{27e7}', '\u{27e6}'), ('\u{27e8}', '\u{27e9}'),
+    ('\u{27e9}', '\u{27e8}'), ('\u{27ea}', '\u{27eb}'), ('\u{27eb}', '\u{27ea}'),
+    ('\u{27ec}', '\u{27ed}'), ('\u{27ed}', '\u{27ec}'), ('\u{27ee}', '\u{27ef}'),
+    ('\u{27ef}', '\u{27ee}'), ('\u{2983}', '\u{2984}'), ('\u{2984}', '\u{2983}'),
+    ('\u{2985}', '\u{2986}'), ('\u{2986}', '\u{2985}'), ('\u{2987}', '\u{2988}'),
+    ('\u{2988}', '\u{2987}'), ('\u{2989}', '\u{298a}'), ('\u{298a}', '\u{2989}'),
+    ('\u{298b}', '\u{298c}'), ('\u{298c}', '\u{298b}'), ('\u{298d}', '\u{2990}'),
+    ('\u{298e}', '\u{298f}'), ('\u{298f}', '\u{298e}'), ('\u{2990}', '\u{298d}'),
+    ('\u{2991}', '\u{2992}'), ('\u{2992}', '\u{2991}'), ('\u{2993}', '\u{2994}'),
+    ('\u{2994}', '\u{2993}'), ('\u{2995}', '\u{2996}'), ('\u{2996}', '\u{2995}'),
+    ('\u{2997}', '\u{2998}'), ('\u{2998}', '\u{2997}'), ('\u{29b8}', '\u{2298}'),
+    ('\u{29c0}', '\u{29c1}'), ('\u{29c1}', '\u{29c0}'), ('\u{29c4}', '\u{29c5}'),
+    ('\u{29c5}', '\u{29c4}'), ('\u{29cf}', '\u{29d0}'), ('\u{29d0}', '\u{29cf}'),
+    ('\u{29d1}', '\u{29d2}'), ('\u{29d2}', '\u{29d1}'), ('\u{29d4}', '\u{29d5}'),
+    ('\u{29d5}', '\u{29d4}'), ('\u{29d8}', '\u{29d9}'), ('\u{29d9}', '\u{29d8}'),
+    ('\u{29da}', '\u{29db}'), ('\u{29db}', '\u{29da}'), ('\u{29f5}', '\u{2215}'),
+    ('\u{29f8}', '\u{29f9}'), ('\u{29f9}', '\u{29f8}'), ('\u{29fc}', '\u{29fd}'),
+    ('\u{29fd}', '\u{29fc}'), ('\u{2ade}', '\u{22a6}'), ('\u{2ae3}', '\u{22a9}'),
+    ('\u{2ae4}', '\u{22a8}'), ('\u{2ae5}', '\u{22ab}'), ('\u{2e02}', '\u{2e03}'),
+    ('\u{2e03}', '\u{2e02}'), ('\u{2e04}', '\u{2e05}'), ('\u{2e05}', '\u{2e04}'),
+    ('\u{2e09}', '\u{2e0a}'), ('\u{2e0a}', '\u{2e09}'), ('\u{2e0c}', '\u{2e0d}'),
+    ('\u{2e0d}', '\u{2e0c}'), ('\u{2e1c}', '\u{2e1d}'), ('\u{2e1d}', '\u{2e1c}'),
+    ('\u{2e20}', '\u{2e21}'), ('\u{2e21}', '\u{2e20}'), ('\u{2e22}', '\u{2e23}'),
+    ('\u{2e23}', '\u{2e22}'), ('\u{2e24}', '\u{2e25}'), ('\u{2e25}', '\u{2e24}'),
+    ('\u{2e26}', '\u{2e27}'), ('\u{2e27}', '\u{2e26}'), ('\u{2e28}', '\u{2e29}'),
+    ('\u{2e29}', '\u{2e28}'), ('\u{3008}', '\u{3009}'), ('\u{3009}', '\u{3008}'),
+    ('\u{300a}', '\u{300b}'), ('\u{300b}', '\u{300a}'), ('\u{300c}', '\u{300d}'),
+    ('\u{300d}', '\u{300c}'), ('\u{300e}', '\u{300f}'), ('\u{300f}', '\u{300e}'),
+    ('\u{3010}', '\u{3011}'), ('\u{3011}', '\u{3010}'), ('\u{3014}', '\u{3015}'),
+    ('\u{3015}', '\u{3014}'), ('\u{3016}', '\u{3017}'), ('\u{3017}', '\u{3016}'),
+    ('\u{3018}', '\u{3019}'), ('\u{3019}', '\u{3018}'), ('\u{301a}', '\u{301b}'),
+    ('\u{301b}', '\u{301a}'), ('\u{fe59}', '\u{fe5a}'), ('\u{fe5a}', '\u{fe59}'),
+    ('\u{fe5b}', '\u{fe5c}'), ('\u{fe5c}', '\u{fe5b}'), ('\u{fe5d}', '\u{fe5e}'),
+    ('\u{fe5e}', '\u{fe5d}'), ('\u{fe64}', '\u{fe65}'), ('\u{fe65}', '\u{fe64}'),
+    ('\u{ff08}', '\u{ff09}'), ('\u{ff09}', '\u{ff08}'), ('\u{ff1c}', '\u{ff1e}'),
+    ('\u{ff1e}', '\u{ff1c}'), ('\u{ff3b}', '\u{ff3d}'), ('\u{ff3d}', '\u{ff3b}'),
+    ('\u{ff5b}', '\u{ff5d}'), ('\u{ff5d}', '\u{ff5b}'), ('\u{ff5f}', '\u{ff60}'),
+    ('\u{ff60}', '\u{ff5f}'), ('\u{ff62}', '\u{ff63}'), ('\u{ff63}', '\u{ff62}'),
+];
+
+/// Returns the character that the given one mirrors to in right-to-left text, if any.
+pub fn mirrored_char(ch: char) -> Option<char> {
+    MIRRORED_CHARS.binary_search_by(|&(from, _)| from.cmp(&ch))
+                  .ok()
+                  .map(|index| MIRRORED_CHARS[index].1)
+}
+
+/// Returns a copy of the string in which every character at an odd (right-to-left) bidi level in
+/// `levels`, which holds one level per byte, is replaced by its mirrored counterpart.
+///
+/// Because mirrored pairs have the same UTF-8 length, byte offsets into the string stay valid.
+pub fn mirror_right_to_left_chars(string: &str, levels: &[u8]) -> String {
+    string.char_indices().map(|(byte_index, ch)| {
+        match levels.get(byte_index) {
+            Some(&level) if level % 2 == 1 => mirrored_char(ch).unwrap_or(ch),
+            _ => ch,
+        }
+    }).collect()
+}
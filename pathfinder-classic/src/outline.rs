@@ -11,10 +11,12 @@
 //! Glyph vectors, uploaded in a resolution-independent manner to the GPU.
 
 use error::{FontError, GlError};
-use euclid::{Point2D, Size2D};
+use euclid::{Point2D, Rect, Size2D};
 use font::{Font, PointKind};
 use gl::types::{GLsizeiptr, GLuint};
 use gl;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 use std::mem;
 use std::os::raw::c_void;
 
@@ -29,6 +31,7 @@ pub struct OutlineBuilder {
     vertices: Vec<Vertex>,
     indices: Vec<u32>,
     descriptors: Vec<GlyphDescriptor>,
+    paths: GlyphPaths,
 }
 
 impl OutlineBuilder {
@@ -39,6 +42,7 @@ impl OutlineBuilder {
             vertices: vec![DUMMY_VERTEX],
             indices: vec![],
             descriptors: vec![],
+            paths: GlyphPaths::new(),
         }
     }
 
@@ -47,6 +51,7 @@ impl OutlineBuilder {
         let vertex_count = self.vertices.len();
         let index_count = self.indices.len();
         let descriptor_count = self.descriptors.len();
+        self.paths.commands.truncate(self.paths.command_ends.last().cloned().unwrap_or(0));
 
         PathBuilder {
             outline_builder: self,
@@ -58,85 +63,157 @@ impl OutlineBuilder {
         }
     }
 
+    /// Returns the commands that drew the path of the glyph with the given index, in font units.
+    ///
+    /// Panics if there is no glyph with that index.
+    #[inline]
+    pub fn glyph_path(&self, glyph_index: u16) -> Vec<PathCommand> {
+        self.paths.glyph_path(glyph_index)
+    }
+
     /// Adds a new glyph to the outline builder. Returns the glyph index, which is useful for later
     /// calls to `Atlas::pack_glyph()`.
     pub fn add_glyph(&mut self, font: &Font, glyph_id: u16) -> Result<u16, FontError> {
-        let glyph_index = self.descriptors.len() as u16;
-        let mut last_point_kind = PointKind::OnCurve;
-        let mut control_point_index = 0;
-        let mut control_points = [Point2D::zero(), Point2D::zero(), Point2D::zero()];
-        let mut path_builder = self.create_path();
+        let glyph_outline = try!(GlyphOutline::new(font, glyph_id));
+        Ok(self.add_glyph_outline(&glyph_outline))
+    }
 
-        try!(font.for_each_point(glyph_id, |point| {
-            control_points[control_point_index] = point.position;
-            control_point_index += 1;
+    /// Adds the given glyphs to the outline builder in order, and returns their glyph indices.
+    ///
+    /// With the `rayon` feature enabled, the outlines are read from the font in parallel. They are
+    /// still added in order, so the result is the same as that of calling `add_glyph()` for each
+    /// glyph in turn, including when a glyph can't be read: the glyphs before it are added, and
+    /// its error is returned.
+    pub fn add_glyphs(&mut self, font: &Font, glyph_ids: &[u16]) -> Result<Vec<u16>, FontError> {
+        let glyph_outlines = read_glyph_outlines(font, glyph_ids);
+        let mut glyph_indices = Vec::with_capacity(glyph_ids.len());
+        for glyph_outline in glyph_outlines {
+            let glyph_outline = try!(glyph_outline);
+            glyph_indices.push(self.add_glyph_outline(&glyph_outline))
+        }
+        Ok(glyph_indices)
+    }
 
-            if point.index_in_contour == 0 {
-                path_builder.move_to(&control_points[0]);
-                control_point_index = 0
-            } else if point.kind == PointKind::OnCurve {
-                match last_point_kind {
-                    PointKind::FirstCubicControl => {}
-                    PointKind::SecondCubicControl => {
-                        path_builder.cubic_curve_to(&control_points[0],
-                                                    &control_points[1],
-                                                    &control_points[2])
-                    }
-                    PointKind::QuadControl => {
-                        path_builder.quad_curve_to(&control_points[0], &control_points[1])
-                    }
-                    PointKind::OnCurve => path_builder.line_to(&control_points[0]),
-                }
+    /// Like `add_glyphs()`, but adds none of the glyphs if any of them can't be read, so that a
+    /// failed call leaves the builder as it was.
+    pub fn add_all_glyphs(&mut self, font: &Font, glyph_ids: &[u16])
+                          -> Result<Vec<u16>, FontError> {
+        let glyph_outlines: Vec<GlyphOutline> =
+            try!(read_glyph_outlines(font, glyph_ids).into_iter().collect());
+        Ok(glyph_outlines.iter()
+                         .map(|glyph_outline| self.add_glyph_outline(glyph_outline))
+                         .collect())
+    }
 
-                control_point_index = 0
+    /// Adds a glyph whose outline has already been read from its font.
+    fn add_glyph_outline(&mut self, glyph_outline: &GlyphOutline) -> u16 {
+        let glyph_index = self.descriptors.len() as u16;
+        let mut path_builder = self.create_path();
+        for command in &glyph_outline.commands {
+            match *command {
+                PathCommand::MoveTo(ref point) => path_builder.move_to(point),
+                PathCommand::LineTo(ref point) => path_builder.line_to(point),
+                PathCommand::QuadTo(ref p1, ref p2) => path_builder.quad_curve_to(p1, p2),
+                PathCommand::CurveTo(ref p1, ref p2, ref p3) => {
+                    path_builder.cubic_curve_to(p1, p2, p3)
+                }
+                PathCommand::Close => {}
             }
-
-            last_point_kind = point.kind
-        }));
-
-        let bounds = try!(font.glyph_bounds(glyph_id));
-        path_builder.finish(&bounds, font.units_per_em() as u32, glyph_id);
-
-        Ok(glyph_index)
+        }
+        path_builder.finish(&glyph_outline.bounds,
+                            glyph_outline.units_per_em,
+                            glyph_outline.glyph_id);
+        glyph_index
     }
 
     /// Uploads the outlines to the GPU.
-    pub fn create_buffers(self) -> Result<Outlines, GlError> {
-        // TODO(pcwalton): Try using `glMapBuffer` here. Requires precomputing contour types and
-        // counts.
-        unsafe {
+    ///
+    /// The builder keeps its contents, so more glyphs can be added to it later and uploaded to the
+    /// same buffers with `update_buffers()`.
+    pub fn create_buffers(&self) -> Result<Outlines, GlError> {
+        let mut outlines = unsafe {
             let (mut vertices, mut indices, mut descriptors) = (0, 0, 0);
             gl::GenBuffers(1, &mut vertices);
             gl::GenBuffers(1, &mut indices);
             gl::GenBuffers(1, &mut descriptors);
 
-            gl::BindBuffer(gl::ARRAY_BUFFER, vertices);
+            Outlines {
+                vertices_buffer: vertices,
+                indices_buffer: indices,
+                descriptors_buffer: descriptors,
+                descriptors: vec![],
+                paths: GlyphPaths::new(),
+                indices_count: 0,
+            }
+        };
+
+        try!(self.update_buffers(&mut outlines));
+        Ok(outlines)
+    }
+
+    /// Copies the outlines without uploading them to the GPU, for measuring glyphs where there's
+    /// no GL context.
+    ///
+    /// The outlines have no buffers, so they can't be rasterized, but `update_buffers()` still
+    /// copies glyphs added to the builder later into them. See `Outlines::is_uploaded()`.
+    pub fn create_cpu_outlines(&self) -> Outlines {
+        let mut outlines = Outlines {
+            vertices_buffer: 0,
+            indices_buffer: 0,
+            descriptors_buffer: 0,
+            descriptors: vec![],
+            paths: GlyphPaths::new(),
+            indices_count: 0,
+        };
+        self.copy_outlines(&mut outlines);
+        outlines
+    }
+
+    /// Replaces the contents of the buffers of the given outlines with the outlines in this
+    /// builder.
+    ///
+    /// The buffer names stay the same. This is intended to be used with outlines previously
+    /// created by this builder, after adding more glyphs to it; glyph indices already handed out
+    /// remain valid. Outlines that aren't uploaded only have the glyphs copied into them.
+    pub fn update_buffers(&self, outlines: &mut Outlines) -> Result<(), GlError> {
+        if !outlines.is_uploaded() {
+            self.copy_outlines(outlines);
+            return Ok(())
+        }
+
+        // TODO(pcwalton): Try using `glMapBuffer` here. Requires precomputing contour types and
+        // counts.
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, outlines.vertices_buffer);
             gl::BufferData(gl::ARRAY_BUFFER,
                            (self.vertices.len() * mem::size_of::<Vertex>()) as GLsizeiptr,
                            self.vertices.as_ptr() as *const Vertex as *const c_void,
                            gl::STATIC_DRAW);
 
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, indices);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, outlines.indices_buffer);
             gl::BufferData(gl::ELEMENT_ARRAY_BUFFER,
                            (self.indices.len() * mem::size_of::<u32>()) as GLsizeiptr,
                            self.indices.as_ptr() as *const u32 as *const c_void,
                            gl::STATIC_DRAW);
 
             let length = self.descriptors.len() * mem::size_of::<GlyphDescriptor>();
-            gl::BindBuffer(gl::UNIFORM_BUFFER, descriptors);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, outlines.descriptors_buffer);
             gl::BufferData(gl::UNIFORM_BUFFER,
                            length as GLsizeiptr,
                            self.descriptors.as_ptr() as *const GlyphDescriptor as *const c_void,
                            gl::STATIC_DRAW);
-
-            Ok(Outlines {
-                vertices_buffer: vertices,
-                indices_buffer: indices,
-                descriptors_buffer: descriptors,
-                descriptors: self.descriptors,
-                indices_count: self.indices.len(),
-            })
         }
+
+        self.copy_outlines(outlines);
+        Ok(())
+    }
+
+    /// Copies the CPU-side data of the outlines in this builder into the given outlines.
+    fn copy_outlines(&self, outlines: &mut Outlines) {
+        outlines.descriptors.clear();
+        outlines.descriptors.extend_from_slice(&self.descriptors);
+        outlines.paths.clone_from(&self.paths);
+        outlines.indices_count = self.indices.len();
     }
 }
 
@@ -146,11 +223,15 @@ pub struct Outlines {
     indices_buffer: GLuint,
     descriptors_buffer: GLuint,
     descriptors: Vec<GlyphDescriptor>,
+    paths: GlyphPaths,
     indices_count: usize,
 }
 
 impl Drop for Outlines {
     fn drop(&mut self) {
+        if !self.is_uploaded() {
+            return
+        }
         unsafe {
             gl::DeleteBuffers(1, &mut self.descriptors_buffer);
             gl::DeleteBuffers(1, &mut self.indices_buffer);
@@ -160,6 +241,14 @@ impl Drop for Outlines {
 }
 
 impl Outlines {
+    /// Returns true if these outlines were uploaded to the GPU by
+    /// `OutlineBuilder::create_buffers()`, or false if they're only on the CPU, from
+    /// `OutlineBuilder::create_cpu_outlines()`.
+    #[inline]
+    pub fn is_uploaded(&self) -> bool {
+        self.vertices_buffer != 0
+    }
+
     #[doc(hidden)]
     #[inline]
     pub fn vertices_buffer(&self) -> GLuint {
@@ -196,12 +285,51 @@ impl Outlines {
         self.descriptors[glyph_index as usize].bounds
     }
 
+    /// Returns the number of glyphs in these outlines. Valid glyph indices are those below it.
+    #[inline]
+    pub fn glyph_count(&self) -> usize {
+        self.descriptors.len()
+    }
+
     /// Returns the glyph rectangle in fractional pixels.
+    ///
+    /// Panics if there is no glyph with the given index. See `try_glyph_subpixel_bounds()`.
     #[inline]
     pub fn glyph_subpixel_bounds(&self, glyph_index: u16, point_size: f32) -> GlyphSubpixelBounds {
         self.descriptors[glyph_index as usize].subpixel_bounds(point_size)
     }
 
+    /// Like `glyph_subpixel_bounds()`, but returns `None` if there is no glyph with the given
+    /// index, as for a stale index from outlines that have since been replaced.
+    #[inline]
+    pub fn try_glyph_subpixel_bounds(&self, glyph_index: u16, point_size: f32)
+                                     -> Option<GlyphSubpixelBounds> {
+        self.descriptors.get(glyph_index as usize).map(|descriptor| {
+            descriptor.subpixel_bounds(point_size)
+        })
+    }
+
+    /// Returns the union of the fractional pixel rectangles of the glyphs with the given indices,
+    /// for example to size an atlas that will hold all of them.
+    ///
+    /// The rectangle's origin is its bottom left corner, as glyph bounds are y-up. If `indices` is
+    /// empty, a zero rectangle is returned.
+    pub fn subpixel_bounds_for_indices(&self, indices: &[u16], point_size: f32) -> Rect<f32> {
+        let mut indices = indices.iter();
+        let mut bounds = match indices.next() {
+            None => return Rect::zero(),
+            Some(&glyph_index) => self.glyph_subpixel_bounds(glyph_index, point_size),
+        };
+        for &glyph_index in indices {
+            let glyph_bounds = self.glyph_subpixel_bounds(glyph_index, point_size);
+            bounds.left = bounds.left.min(glyph_bounds.left);
+            bounds.bottom = bounds.bottom.min(glyph_bounds.bottom);
+            bounds.right = bounds.right.max(glyph_bounds.right);
+            bounds.top = bounds.top.max(glyph_bounds.top);
+        }
+        Rect::new(Point2D::new(bounds.left, bounds.bottom), bounds.size())
+    }
+
     /// Returns the ID of the glyph with the given index.
     #[inline]
     pub fn glyph_id(&self, glyph_index: u16) -> u16 {
@@ -213,6 +341,128 @@ impl Outlines {
     pub fn glyph_units_per_em(&self, glyph_index: u16) -> u32 {
         self.descriptors[glyph_index as usize].units_per_em
     }
+
+    /// Returns the commands that draw the path of the glyph with the given index, in font units,
+    /// for example to export it as SVG or hand it to another rasterizer.
+    ///
+    /// These are the commands that the glyph was built from, kept on the CPU. Panics if there is
+    /// no glyph with that index.
+    #[inline]
+    pub fn glyph_path(&self, glyph_index: u16) -> Vec<PathCommand> {
+        self.paths.glyph_path(glyph_index)
+    }
+}
+
+/// The outline of a glyph as read from its font, before it is added to an `OutlineBuilder`.
+struct GlyphOutline {
+    glyph_id: u16,
+    /// The commands that draw the glyph, without `PathCommand::Close`, which `PathBuilder` adds.
+    commands: Vec<PathCommand>,
+    bounds: GlyphBounds,
+    units_per_em: u32,
+}
+
+impl GlyphOutline {
+    fn new(font: &Font, glyph_id: u16) -> Result<GlyphOutline, FontError> {
+        let mut commands = vec![];
+        let mut last_point_kind = PointKind::OnCurve;
+        let mut control_point_index = 0;
+        let mut control_points = [Point2D::zero(), Point2D::zero(), Point2D::zero()];
+
+        try!(font.for_each_point(glyph_id, |point| {
+            control_points[control_point_index] = point.position;
+            control_point_index += 1;
+
+            if point.index_in_contour == 0 {
+                commands.push(PathCommand::MoveTo(control_points[0]));
+                control_point_index = 0
+            } else if point.kind == PointKind::OnCurve {
+                match last_point_kind {
+                    PointKind::FirstCubicControl => {}
+                    PointKind::SecondCubicControl => {
+                        commands.push(PathCommand::CurveTo(control_points[0],
+                                                           control_points[1],
+                                                           control_points[2]))
+                    }
+                    PointKind::QuadControl => {
+                        commands.push(PathCommand::QuadTo(control_points[0], control_points[1]))
+                    }
+                    PointKind::OnCurve => commands.push(PathCommand::LineTo(control_points[0])),
+                }
+
+                control_point_index = 0
+            }
+
+            last_point_kind = point.kind
+        }));
+
+        Ok(GlyphOutline {
+            glyph_id: glyph_id,
+            commands: commands,
+            bounds: try!(font.glyph_bounds(glyph_id)),
+            units_per_em: font.units_per_em() as u32,
+        })
+    }
+}
+
+/// Reads the outlines of the given glyphs from the font, in parallel.
+#[cfg(feature = "rayon")]
+fn read_glyph_outlines(font: &Font, glyph_ids: &[u16]) -> Vec<Result<GlyphOutline, FontError>> {
+    glyph_ids.par_iter().map(|&glyph_id| GlyphOutline::new(font, glyph_id)).collect()
+}
+
+/// Reads the outlines of the given glyphs from the font.
+#[cfg(not(feature = "rayon"))]
+fn read_glyph_outlines(font: &Font, glyph_ids: &[u16]) -> Vec<Result<GlyphOutline, FontError>> {
+    glyph_ids.iter().map(|&glyph_id| GlyphOutline::new(font, glyph_id)).collect()
+}
+
+/// A drawing command in the path of a glyph. Points are in font units, with y pointing up.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PathCommand {
+    /// Starts a new contour at the given point.
+    MoveTo(Point2D<i16>),
+    /// Draws a straight line to the given point.
+    LineTo(Point2D<i16>),
+    /// Draws a quadratic Bézier curve through the given control point to the given point.
+    QuadTo(Point2D<i16>, Point2D<i16>),
+    /// Draws a cubic Bézier curve through the given control points to the given point.
+    CurveTo(Point2D<i16>, Point2D<i16>, Point2D<i16>),
+    /// Closes the current contour with a straight line back to its start.
+    Close,
+}
+
+/// The path commands of every glyph in a set of outlines.
+#[derive(Clone, Debug)]
+struct GlyphPaths {
+    commands: Vec<PathCommand>,
+    /// The index in `commands` just past the last command of each glyph.
+    command_ends: Vec<usize>,
+}
+
+impl GlyphPaths {
+    #[inline]
+    fn new() -> GlyphPaths {
+        GlyphPaths {
+            commands: vec![],
+            command_ends: vec![],
+        }
+    }
+
+    fn glyph_path(&self, glyph_index: u16) -> Vec<PathCommand> {
+        let glyph_index = glyph_index as usize;
+        let start = if glyph_index == 0 { 0 } else { self.command_ends[glyph_index - 1] };
+        self.commands[start..self.command_ends[glyph_index]].to_vec()
+    }
+
+    /// Closes the current contour of the glyph being built, if it has one.
+    fn close_contour(&mut self) {
+        let glyph_start = self.command_ends.last().cloned().unwrap_or(0);
+        match self.commands[glyph_start..].last() {
+            None | Some(&PathCommand::Close) => {}
+            Some(_) => self.commands.push(PathCommand::Close),
+        }
+    }
 }
 
 #[doc(hidden)]
@@ -357,6 +607,8 @@ impl<'a> PathBuilder<'a> {
 
     /// Moves the pen to the given point.
     pub fn move_to(&mut self, point: &Point2D<i16>) {
+        self.outline_builder.paths.close_contour();
+        self.outline_builder.paths.commands.push(PathCommand::MoveTo(*point));
         self.add_point(point)
     }
 
@@ -368,6 +620,7 @@ impl<'a> PathBuilder<'a> {
             panic!("`line_to` must not be the first operation in a path")
         }
 
+        self.outline_builder.paths.commands.push(PathCommand::LineTo(*point));
         self.add_point(point);
 
         self.outline_builder.indices.extend_from_slice(&[
@@ -386,6 +639,7 @@ impl<'a> PathBuilder<'a> {
             panic!("`quad_curve_to` must not be the first operation in a path")
         }
 
+        self.outline_builder.paths.commands.push(PathCommand::QuadTo(*p1, *p2));
         self.add_point(p1);
         self.add_point(p2);
 
@@ -405,6 +659,7 @@ impl<'a> PathBuilder<'a> {
             panic!("`cubic_curve_to` must not be the first operation in a path")
         }
 
+        self.outline_builder.paths.commands.push(PathCommand::CurveTo(*p1, *p2, *p3));
         self.add_point(p1);
         self.add_point(p2);
         self.add_point(p3);
@@ -419,6 +674,11 @@ impl<'a> PathBuilder<'a> {
 
     /// Finishes the path.
     pub fn finish(self, bounds: &GlyphBounds, units_per_em: u32, glyph_id: u16) {
+        let paths = &mut self.outline_builder.paths;
+        paths.close_contour();
+        let command_count = paths.commands.len();
+        paths.command_ends.push(command_count);
+
         self.outline_builder.descriptors.push(GlyphDescriptor {
             bounds: *bounds,
             units_per_em: units_per_em,
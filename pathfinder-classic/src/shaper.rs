@@ -10,9 +10,11 @@
 
 //! A very basic text shaper for simple needs.
 //!
-//! Do not use this for international or high-quality text. This shaper does not do kerning,
-//! ligation, or advanced typography features (`GSUB`, `GPOS`, text morphing). Consider HarfBuzz or
-//! the system shaper instead.
+//! Do not use this for international or high-quality text. This shaper does only simple pair
+//! kerning from the `kern` table, and, for OpenType features requested with
+//! `shape_text_with_features()` or `shape_text_with_language()`, substitutions of one glyph for
+//! another from the `GSUB` table; it does not do ligation or other advanced typography features
+//! (`GPOS`, text morphing). Consider HarfBuzz or the system shaper instead.
 
 use charmap::GlyphMapping;
 use font::Font;
@@ -24,13 +26,110 @@ use font::Font;
 /// For proper operation, the given `glyph_mapping` must include all the glyphs necessary to render
 /// the string.
 pub fn shape_text(font: &Font, glyph_mapping: &GlyphMapping, string: &str) -> Vec<GlyphPos> {
-    let mut chars = string.chars().peekable();
+    shape_text_with_kerning(font, glyph_mapping, string, true)
+}
+
+/// The tag of the OpenType feature that turns kerning on or off.
+pub const KERN_FEATURE: u32 = ((b'k' as u32) << 24) |
+                              ((b'e' as u32) << 16) |
+                              ((b'r' as u32) << 8)  |
+                               (b'n' as u32);
+
+/// The tag of the OpenType feature that selects the forms of glyphs localized for a language.
+pub const LOCALIZED_FORMS_FEATURE: u32 = ((b'l' as u32) << 24) |
+                                         ((b'o' as u32) << 16) |
+                                         ((b'c' as u32) << 8)  |
+                                          (b'l' as u32);
+
+/// Returns the tag of the OpenType feature with the given four-letter name, such as `b"smcp"`,
+/// for use with `shape_text_with_features()`. Script and language tags, such as `b"cyrl"` and
+/// `b"SRB "`, are made the same way.
+#[inline]
+pub fn feature_tag(name: &[u8; 4]) -> u32 {
+    ((name[0] as u32) << 24) | ((name[1] as u32) << 16) | ((name[2] as u32) << 8) | (name[3] as u32)
+}
+
+/// Like `shape_text()`, but kerning from the font's `kern` table is applied to the advances only if
+/// `kerning` is true.
+pub fn shape_text_with_kerning(font: &Font,
+                               glyph_mapping: &GlyphMapping,
+                               string: &str,
+                               kerning: bool)
+                               -> Vec<GlyphPos> {
+    shape(font, glyph_mapping, string, kerning, &[])
+}
+
+/// Like `shape_text()`, but applies the given OpenType features, each a tag from
+/// `feature_tag()` and a value.
+///
+/// A value of zero turns a feature off, and other values turn it on; for features that offer a
+/// choice of alternate glyphs, such as `salt`, the value picks the alternate, counting from one.
+/// A later entry for a tag overrides an earlier one. Kerning is on unless the `kern` feature is
+/// turned off. Only substitutions of one glyph for another are supported, so features such as
+/// `smcp` and `onum` work, but ligatures are never formed, and turning `liga` off has no effect.
+pub fn shape_text_with_features(font: &Font,
+                                glyph_mapping: &GlyphMapping,
+                                string: &str,
+                                features: &[(u32, u32)])
+                                -> Vec<GlyphPos> {
+    let kerning = features.iter()
+                          .rev()
+                          .find(|&&(tag, _)| tag == KERN_FEATURE)
+                          .map_or(true, |&(_, value)| value != 0);
+    let lookups = font.substitution_lookups(features);
+    shape(font, glyph_mapping, string, kerning, &lookups)
+}
+
+/// Like `shape_text_with_features()`, but applies the features that the font lists for the given
+/// script and language, each a tag from `feature_tag()`, such as `feature_tag(b"cyrl")` and
+/// `feature_tag(b"SRB ")`, for Serbian.
+///
+/// The `locl` feature is turned on unless `features` turns it off, so that glyphs take the forms
+/// preferred in the language. If the font doesn't list the script, its default script is used,
+/// and a language that it doesn't list, such as `feature_tag(b"dflt")`, gets the script's
+/// default features.
+pub fn shape_text_with_language(font: &Font,
+                                glyph_mapping: &GlyphMapping,
+                                string: &str,
+                                script: u32,
+                                language: u32,
+                                features: &[(u32, u32)])
+                                -> Vec<GlyphPos> {
+    let kerning = features.iter()
+                          .rev()
+                          .find(|&&(tag, _)| tag == KERN_FEATURE)
+                          .map_or(true, |&(_, value)| value != 0);
+    let mut language_features = Vec::with_capacity(features.len() + 1);
+    language_features.push((LOCALIZED_FORMS_FEATURE, 1));
+    language_features.extend_from_slice(features);
+    let lookups = font.substitution_lookups_for_language(script, language, &language_features);
+    shape(font, glyph_mapping, string, kerning, &lookups)
+}
+
+/// Shapes the given text, substituting glyphs with the given `GSUB` lookups and kerning them if
+/// `kerning` is true.
+fn shape(font: &Font,
+         glyph_mapping: &GlyphMapping,
+         string: &str,
+         kerning: bool,
+         lookups: &[(u16, u32)])
+         -> Vec<GlyphPos> {
+    let glyph_for = |ch: char| {
+        let glyph_id = glyph_mapping.glyph_for(ch as u32).unwrap_or(0);
+        if lookups.is_empty() {
+            glyph_id
+        } else {
+            font.substitute_glyph(lookups, glyph_id)
+        }
+    };
+
+    let mut chars = string.char_indices().peekable();
     let mut next_glyph_id = None;
     let mut result = vec![];
 
-    while let Some(ch) = chars.next() {
+    while let Some((byte_index, ch)) = chars.next() {
         let glyph_id = match next_glyph_id.take() {
-            None => glyph_mapping.glyph_for(ch as u32).unwrap_or(0),
+            None => glyph_for(ch),
             Some(next_glyph_id) => next_glyph_id,
         };
 
@@ -39,15 +138,20 @@ pub fn shape_text(font: &Font, glyph_mapping: &GlyphMapping, string: &str) -> Ve
             Ok(metrics) => metrics.advance_width as i16,
         };
 
-        if let Some(&next_char) = chars.peek() {
-            let next_glyph = glyph_mapping.glyph_for(next_char as u32).unwrap_or(0);
+        if let Some(&(_, next_char)) = chars.peek() {
+            let next_glyph = glyph_for(next_char);
             next_glyph_id = Some(next_glyph);
-            advance += font.kerning_for_glyph_pair(glyph_id, next_glyph)
+            if kerning {
+                advance += font.kerning_for_glyph_pair(glyph_id, next_glyph)
+            }
         }
 
         result.push(GlyphPos {
             glyph_id: glyph_id,
             advance: advance,
+            x_offset: 0,
+            y_offset: 0,
+            cluster: byte_index as u32,
         })
     }
 
@@ -61,5 +165,18 @@ pub struct GlyphPos {
     pub glyph_id: u16,
     /// The amount to move the cursor forward *after* emitting this glyph.
     pub advance: i16,
+    /// How far to the right of the cursor to draw this glyph, in font units.
+    ///
+    /// Shapers that position combining marks use this and `y_offset` to place them on their base
+    /// glyphs. This shaper doesn't, so both are always zero.
+    pub x_offset: i16,
+    /// How far above the cursor to draw this glyph, in font units.
+    pub y_offset: i16,
+    /// The byte offset in the string of the first character that produced this glyph.
+    ///
+    /// Glyphs with the same cluster came from the same characters and must be kept together when
+    /// selecting or moving through text. This shaper produces one glyph per character, so every
+    /// glyph is currently a cluster of its own.
+    pub cluster: u32,
 }
 
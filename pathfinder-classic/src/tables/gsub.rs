@@ -0,0 +1,331 @@
+// Copyright 2017 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The glyph substitution table.
+//!
+//! Only lookups that replace one glyph with another (single and alternate substitutions) are
+//! supported, so that each character still produces exactly one glyph.
+
+use byteorder::{BigEndian, ReadBytesExt};
+use error::FontError;
+use font::FontTable;
+use std::mem;
+use util::Jump;
+
+pub const TAG: u32 = ((b'G' as u32) << 24) |
+                      ((b'S' as u32) << 16) |
+                      ((b'U' as u32) << 8)  |
+                       (b'B' as u32);
+
+const LOOKUP_TYPE_SINGLE: u16 = 1;
+const LOOKUP_TYPE_ALTERNATE: u16 = 3;
+const LOOKUP_TYPE_EXTENSION: u16 = 7;
+
+/// The tag of the script whose features apply to scripts that a font doesn't list.
+const DEFAULT_SCRIPT_TAG: u32 = ((b'D' as u32) << 24) |
+                                 ((b'F' as u32) << 16) |
+                                 ((b'L' as u32) << 8)  |
+                                  (b'T' as u32);
+
+/// The feature index that means that a language system has no required feature.
+const NO_REQUIRED_FEATURE: u16 = 0xffff;
+
+#[derive(Clone, Copy)]
+pub struct GsubTable<'a> {
+    script_list: &'a [u8],
+    feature_list: &'a [u8],
+    lookup_list: &'a [u8],
+}
+
+impl<'a> GsubTable<'a> {
+    pub fn new(table: FontTable) -> Result<GsubTable, FontError> {
+        let mut reader = table.bytes;
+        let major_version = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        if major_version != 1 {
+            return Err(FontError::UnsupportedVersion)
+        }
+
+        let _minor_version = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        let script_list_offset = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        let feature_list_offset = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        let lookup_list_offset = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        Ok(GsubTable {
+            script_list: try!(subtable(table.bytes, script_list_offset)),
+            feature_list: try!(subtable(table.bytes, feature_list_offset)),
+            lookup_list: try!(subtable(table.bytes, lookup_list_offset)),
+        })
+    }
+
+    /// Returns the indices of the lookups of every feature with the given tag.
+    ///
+    /// Scripts and languages are not distinguished: the feature's lookups are found wherever it
+    /// is defined. See `lookups_for_feature_in_language()`.
+    pub fn lookups_for_feature(&self, feature_tag: u32) -> Result<Vec<u16>, FontError> {
+        let mut reader = self.feature_list;
+        let feature_count = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+
+        let mut lookup_indices = vec![];
+        for _ in 0..feature_count {
+            let tag = try!(reader.read_u32::<BigEndian>().map_err(FontError::eof));
+            let feature_offset = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+            if tag != feature_tag {
+                continue
+            }
+
+            try!(self.push_feature_lookups(feature_offset, &mut lookup_indices))
+        }
+        Ok(lookup_indices)
+    }
+
+    /// Like `lookups_for_feature()`, but only finds the feature among those of the given language
+    /// system of the given script.
+    ///
+    /// If the font doesn't list the script, the features of its default script (`DFLT`) are used,
+    /// or, failing that, the features of every script. A language that the script doesn't list
+    /// gets the script's default features.
+    pub fn lookups_for_feature_in_language(&self,
+                                           script_tag: u32,
+                                           language_tag: u32,
+                                           feature_tag: u32)
+                                           -> Result<Vec<u16>, FontError> {
+        let script = match try!(self.script(script_tag)) {
+            Some(script) => script,
+            None => match try!(self.script(DEFAULT_SCRIPT_TAG)) {
+                Some(script) => script,
+                None => return self.lookups_for_feature(feature_tag),
+            },
+        };
+
+        let mut reader = script;
+        let default_language_offset = try!(reader.read_u16::<BigEndian>()
+                                                 .map_err(FontError::eof));
+        let language_count = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        let mut language_offset = default_language_offset;
+        for _ in 0..language_count {
+            let tag = try!(reader.read_u32::<BigEndian>().map_err(FontError::eof));
+            let offset = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+            if tag == language_tag {
+                language_offset = offset;
+                break
+            }
+        }
+        if language_offset == 0 {
+            return Ok(vec![])
+        }
+
+        let mut language_reader = try!(subtable(script, language_offset));
+        let _lookup_order = try!(language_reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        let required_feature_index = try!(language_reader.read_u16::<BigEndian>()
+                                                         .map_err(FontError::eof));
+        let feature_index_count = try!(language_reader.read_u16::<BigEndian>()
+                                                      .map_err(FontError::eof));
+        let mut feature_indices = Vec::with_capacity(feature_index_count as usize + 1);
+        if required_feature_index != NO_REQUIRED_FEATURE {
+            feature_indices.push(required_feature_index)
+        }
+        for _ in 0..feature_index_count {
+            feature_indices.push(try!(language_reader.read_u16::<BigEndian>()
+                                                     .map_err(FontError::eof)))
+        }
+
+        let mut lookup_indices = vec![];
+        for feature_index in feature_indices {
+            // Each feature record is a tag followed by an offset.
+            let mut feature_record_reader = self.feature_list;
+            try!(feature_record_reader.jump(mem::size_of::<u16>() +
+                                            feature_index as usize * (mem::size_of::<u32>() +
+                                                                      mem::size_of::<u16>()))
+                                      .map_err(FontError::eof));
+            let tag = try!(feature_record_reader.read_u32::<BigEndian>().map_err(FontError::eof));
+            let feature_offset = try!(feature_record_reader.read_u16::<BigEndian>()
+                                                           .map_err(FontError::eof));
+            if tag == feature_tag {
+                try!(self.push_feature_lookups(feature_offset, &mut lookup_indices))
+            }
+        }
+        Ok(lookup_indices)
+    }
+
+    /// Returns the script table with the given tag, if the font has one.
+    fn script(&self, script_tag: u32) -> Result<Option<&'a [u8]>, FontError> {
+        let mut reader = self.script_list;
+        let script_count = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        for _ in 0..script_count {
+            let tag = try!(reader.read_u32::<BigEndian>().map_err(FontError::eof));
+            let script_offset = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+            if tag == script_tag {
+                return subtable(self.script_list, script_offset).map(Some)
+            }
+        }
+        Ok(None)
+    }
+
+    /// Appends the indices of the lookups of the feature at the given offset in the feature list.
+    fn push_feature_lookups(&self, feature_offset: u16, lookup_indices: &mut Vec<u16>)
+                            -> Result<(), FontError> {
+        let mut feature_reader = try!(subtable(self.feature_list, feature_offset));
+        let _feature_params = try!(feature_reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        let lookup_index_count = try!(feature_reader.read_u16::<BigEndian>()
+                                                    .map_err(FontError::eof));
+        for _ in 0..lookup_index_count {
+            lookup_indices.push(try!(feature_reader.read_u16::<BigEndian>()
+                                                   .map_err(FontError::eof)))
+        }
+        Ok(())
+    }
+
+    /// Applies the lookup with the given index to the given glyph, returning the glyph that
+    /// replaces it.
+    ///
+    /// `alternate` chooses among the glyphs offered by an alternate substitution, counting from
+    /// one. The glyph is returned unchanged if the lookup doesn't cover it or is of an
+    /// unsupported type.
+    pub fn substitute(&self, lookup_index: u16, alternate: u32, glyph_id: u16)
+                      -> Result<u16, FontError> {
+        let mut reader = self.lookup_list;
+        let lookup_count = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        if lookup_index >= lookup_count {
+            return Err(FontError::Failed)
+        }
+        try!(reader.jump(lookup_index as usize * mem::size_of::<u16>()).map_err(FontError::eof));
+        let lookup_offset = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+
+        let lookup = try!(subtable(self.lookup_list, lookup_offset));
+        let mut lookup_reader = lookup;
+        let lookup_type = try!(lookup_reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        let _lookup_flag = try!(lookup_reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        let subtable_count = try!(lookup_reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        for _ in 0..subtable_count {
+            let subtable_offset = try!(lookup_reader.read_u16::<BigEndian>()
+                                                    .map_err(FontError::eof));
+            let mut substitution_table = try!(subtable(lookup, subtable_offset));
+            let mut substitution_type = lookup_type;
+
+            // Extension subtables point to a subtable of another type with a 32-bit offset.
+            if lookup_type == LOOKUP_TYPE_EXTENSION {
+                let mut extension_reader = substitution_table;
+                let _format = try!(extension_reader.read_u16::<BigEndian>()
+                                                   .map_err(FontError::eof));
+                substitution_type = try!(extension_reader.read_u16::<BigEndian>()
+                                                         .map_err(FontError::eof));
+                let extension_offset = try!(extension_reader.read_u32::<BigEndian>()
+                                                            .map_err(FontError::eof));
+                try!(substitution_table.jump(extension_offset as usize).map_err(FontError::eof));
+            }
+
+            if let Some(substitute) = try!(substitute_in_subtable(substitution_type,
+                                                                  substitution_table,
+                                                                  alternate,
+                                                                  glyph_id)) {
+                return Ok(substitute)
+            }
+        }
+
+        Ok(glyph_id)
+    }
+}
+
+/// Applies a single substitution subtable of the given type to the given glyph. Returns `None`
+/// if the subtable doesn't cover the glyph, so that the next subtable of the lookup is tried.
+fn substitute_in_subtable(lookup_type: u16, table: &[u8], alternate: u32, glyph_id: u16)
+                          -> Result<Option<u16>, FontError> {
+    if lookup_type != LOOKUP_TYPE_SINGLE && lookup_type != LOOKUP_TYPE_ALTERNATE {
+        return Ok(None)
+    }
+
+    let mut reader = table;
+    let format = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+    let coverage_offset = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+    let coverage = try!(subtable(table, coverage_offset));
+    let coverage_index = match try!(coverage_index(coverage, glyph_id)) {
+        None => return Ok(None),
+        Some(coverage_index) => coverage_index as usize,
+    };
+
+    match (lookup_type, format) {
+        (LOOKUP_TYPE_SINGLE, 1) => {
+            let delta = try!(reader.read_i16::<BigEndian>().map_err(FontError::eof));
+            Ok(Some(glyph_id.wrapping_add(delta as u16)))
+        }
+        (LOOKUP_TYPE_SINGLE, 2) => {
+            let glyph_count = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+            if coverage_index >= glyph_count as usize {
+                return Err(FontError::UnexpectedEof)
+            }
+            try!(reader.jump(coverage_index * mem::size_of::<u16>()).map_err(FontError::eof));
+            Ok(Some(try!(reader.read_u16::<BigEndian>().map_err(FontError::eof))))
+        }
+        (LOOKUP_TYPE_ALTERNATE, 1) => {
+            let alternate_set_count = try!(reader.read_u16::<BigEndian>()
+                                                 .map_err(FontError::eof));
+            if coverage_index >= alternate_set_count as usize {
+                return Err(FontError::UnexpectedEof)
+            }
+            try!(reader.jump(coverage_index * mem::size_of::<u16>()).map_err(FontError::eof));
+            let alternate_set_offset = try!(reader.read_u16::<BigEndian>()
+                                                  .map_err(FontError::eof));
+
+            let mut alternate_set_reader = try!(subtable(table, alternate_set_offset));
+            let glyph_count = try!(alternate_set_reader.read_u16::<BigEndian>()
+                                                       .map_err(FontError::eof));
+            if alternate == 0 || alternate > glyph_count as u32 {
+                return Ok(None)
+            }
+            try!(alternate_set_reader.jump((alternate - 1) as usize * mem::size_of::<u16>())
+                                     .map_err(FontError::eof));
+            Ok(Some(try!(alternate_set_reader.read_u16::<BigEndian>().map_err(FontError::eof))))
+        }
+        _ => Err(FontError::UnknownFormat),
+    }
+}
+
+/// Returns the index of the given glyph in a coverage table, or `None` if it isn't covered.
+fn coverage_index(coverage: &[u8], glyph_id: u16) -> Result<Option<u16>, FontError> {
+    let mut reader = coverage;
+    let format = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+    let count = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+    let record_size = match format {
+        1 => mem::size_of::<u16>(),
+        2 => mem::size_of::<[u16; 3]>(),
+        _ => return Err(FontError::UnknownFormat),
+    };
+
+    // Both formats are sorted by glyph ID, so binary search them.
+    let (mut low, mut high) = (0, count as usize);
+    while low < high {
+        let mid = (low + high) / 2;
+        let mut record_reader = reader;
+        try!(record_reader.jump(mid * record_size).map_err(FontError::eof));
+        let start = try!(record_reader.read_u16::<BigEndian>().map_err(FontError::eof));
+        let (end, start_coverage_index) = if format == 1 {
+            (start, mid as u16)
+        } else {
+            (try!(record_reader.read_u16::<BigEndian>().map_err(FontError::eof)),
+             try!(record_reader.read_u16::<BigEndian>().map_err(FontError::eof)))
+        };
+
+        if glyph_id < start {
+            high = mid
+        } else if glyph_id > end {
+            low = mid + 1
+        } else {
+            return Ok(Some(start_coverage_index + (glyph_id - start)))
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns the part of a table starting at the given offset.
+fn subtable(table: &[u8], offset: u16) -> Result<&[u8], FontError> {
+    let mut reader = table;
+    try!(reader.jump(offset as usize).map_err(FontError::eof));
+    Ok(reader)
+}
@@ -11,6 +11,7 @@
 use byteorder::{BigEndian, ReadBytesExt};
 use error::FontError;
 use font::FontTable;
+use std::cmp;
 use std::mem;
 use tables::hhea::HheaTable;
 use util::Jump;
@@ -56,6 +57,22 @@ impl<'a> HmtxTable<'a> {
             lsb: lsb,
         })
     }
+
+    /// Reads just the advance width of the given glyph, which is cheaper than reading all of its
+    /// metrics.
+    pub fn advance_for_glyph(&self, hhea_table: &HheaTable, glyph_id: u16)
+                             -> Result<u16, FontError> {
+        // Glyphs past the last long metric share its advance width.
+        if hhea_table.number_of_h_metrics == 0 {
+            return Err(FontError::Failed)
+        }
+        let metric_index = cmp::min(glyph_id, hhea_table.number_of_h_metrics - 1);
+
+        let mut reader = self.table.bytes;
+        try!(reader.jump(mem::size_of::<u16>() * 2 * metric_index as usize)
+                   .map_err(FontError::eof));
+        reader.read_u16::<BigEndian>().map_err(FontError::eof)
+    }
 }
 
 #[derive(Clone, Copy, Default, Debug)]
@@ -0,0 +1,41 @@
+// Copyright 2017 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use byteorder::{BigEndian, ReadBytesExt};
+use error::FontError;
+use font::FontTable;
+use std::mem;
+use util::Jump;
+
+pub const TAG: u32 = ((b'm' as u32) << 24) |
+                      ((b'a' as u32) << 16) |
+                      ((b'x' as u32) << 8)  |
+                       (b'p' as u32);
+
+#[derive(Clone, Debug)]
+pub struct MaxpTable {
+    pub num_glyphs: u16,
+}
+
+impl MaxpTable {
+    pub fn new(table: FontTable) -> Result<MaxpTable, FontError> {
+        let mut reader = table.bytes;
+
+        // Skip the version. Both version 0.5, used by CFF fonts, and version 1.0 begin with the
+        // number of glyphs.
+        try!(reader.jump(mem::size_of::<u32>()).map_err(FontError::eof));
+
+        let num_glyphs = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+
+        Ok(MaxpTable {
+            num_glyphs: num_glyphs,
+        })
+    }
+}
@@ -35,10 +35,15 @@ pub mod prep {
 pub mod cff;
 pub mod cmap;
 pub mod glyf;
+pub mod gsub;
 pub mod head;
 pub mod hhea;
 pub mod hmtx;
 pub mod kern;
 pub mod loca;
+pub mod maxp;
 pub mod os_2;
+pub mod post;
+pub mod vhea;
+pub mod vmtx;
 
@@ -21,6 +21,8 @@ pub const TAG: u32 = ((b'O' as u32) << 24) |
 
 #[derive(Clone, Debug)]
 pub struct Os2Table {
+    pub strikeout_size: i16,
+    pub strikeout_position: i16,
     pub typo_ascender: i16,
     pub typo_descender: i16,
     pub typo_line_gap: i16,
@@ -34,8 +36,14 @@ impl Os2Table {
         // Postel's law and hope for the best.
         let version = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
 
+        // Skip to the strikeout metrics.
+        try!(reader.jump(mem::size_of::<u16>() * 12).map_err(FontError::eof));
+
+        let strikeout_size = try!(reader.read_i16::<BigEndian>().map_err(FontError::eof));
+        let strikeout_position = try!(reader.read_i16::<BigEndian>().map_err(FontError::eof));
+
         // Skip to the line gap.
-        try!(reader.jump(mem::size_of::<u16>() * 15).map_err(FontError::eof));
+        try!(reader.jump(mem::size_of::<u16>()).map_err(FontError::eof));
         try!(reader.jump(10).map_err(FontError::eof));
         if version == 0 {
             try!(reader.jump(mem::size_of::<u32>() * 2).map_err(FontError::eof));
@@ -50,6 +58,8 @@ impl Os2Table {
         let typo_line_gap = try!(reader.read_i16::<BigEndian>().map_err(FontError::eof));
 
         Ok(Os2Table {
+            strikeout_size: strikeout_size,
+            strikeout_position: strikeout_position,
             typo_ascender: typo_ascender,
             typo_descender: typo_descender,
             typo_line_gap: typo_line_gap,
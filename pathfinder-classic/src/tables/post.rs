@@ -0,0 +1,46 @@
+// Copyright 2017 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use byteorder::{BigEndian, ReadBytesExt};
+use error::FontError;
+use font::FontTable;
+use std::mem;
+use util::Jump;
+
+pub const TAG: u32 = ((b'p' as u32) << 24) |
+                      ((b'o' as u32) << 16) |
+                      ((b's' as u32) << 8)  |
+                       (b't' as u32);
+
+#[derive(Clone, Debug)]
+pub struct PostTable {
+    pub underline_position: i16,
+    pub underline_thickness: i16,
+    pub is_fixed_pitch: bool,
+}
+
+impl PostTable {
+    pub fn new(table: FontTable) -> Result<PostTable, FontError> {
+        let mut reader = table.bytes;
+
+        // Skip the version and italic angle, which don't affect the fields we read.
+        try!(reader.jump(mem::size_of::<u32>() * 2).map_err(FontError::eof));
+
+        let underline_position = try!(reader.read_i16::<BigEndian>().map_err(FontError::eof));
+        let underline_thickness = try!(reader.read_i16::<BigEndian>().map_err(FontError::eof));
+        let is_fixed_pitch = try!(reader.read_u32::<BigEndian>().map_err(FontError::eof));
+
+        Ok(PostTable {
+            underline_position: underline_position,
+            underline_thickness: underline_thickness,
+            is_fixed_pitch: is_fixed_pitch != 0,
+        })
+    }
+}
@@ -0,0 +1,56 @@
+// Copyright 2017 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use byteorder::{BigEndian, ReadBytesExt};
+use error::FontError;
+use font::FontTable;
+use std::mem;
+use util::Jump;
+
+pub const TAG: u32 = ((b'v' as u32) << 24) |
+                      ((b'h' as u32) << 16) |
+                      ((b'e' as u32) << 8)  |
+                       (b'a' as u32);
+
+#[derive(Clone, Debug)]
+pub struct VheaTable {
+    pub ascender: i16,
+    pub descender: i16,
+    pub line_gap: i16,
+    pub number_of_v_metrics: u16,
+}
+
+impl VheaTable {
+    pub fn new(table: FontTable) -> Result<VheaTable, FontError> {
+        let mut reader = table.bytes;
+
+        // Check the version. Versions 1.0 and 1.1 differ only in how the metrics are interpreted.
+        let version = try!(reader.read_u32::<BigEndian>().map_err(FontError::eof));
+        if version != 0x00010000 && version != 0x00011000 {
+            return Err(FontError::UnsupportedVheaVersion)
+        }
+
+        // Read the width-related metrics.
+        let ascender = try!(reader.read_i16::<BigEndian>().map_err(FontError::eof));
+        let descender = try!(reader.read_i16::<BigEndian>().map_err(FontError::eof));
+        let line_gap = try!(reader.read_i16::<BigEndian>().map_err(FontError::eof));
+
+        // Read the number of `vmtx` entries.
+        try!(reader.jump(mem::size_of::<u16>() * 12).map_err(FontError::eof));
+        let number_of_v_metrics = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+
+        Ok(VheaTable {
+            ascender: ascender,
+            descender: descender,
+            line_gap: line_gap,
+            number_of_v_metrics: number_of_v_metrics,
+        })
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2017 The Servo Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use byteorder::{BigEndian, ReadBytesExt};
+use error::FontError;
+use font::FontTable;
+use std::mem;
+use tables::vhea::VheaTable;
+use util::Jump;
+
+pub const TAG: u32 = ((b'v' as u32) << 24) |
+                      ((b'm' as u32) << 16) |
+                      ((b't' as u32) << 8)  |
+                       (b'x' as u32);
+
+#[derive(Clone, Copy)]
+pub struct VmtxTable<'a> {
+    table: FontTable<'a>,
+}
+
+impl<'a> VmtxTable<'a> {
+    pub fn new(table: FontTable) -> VmtxTable {
+        VmtxTable {
+            table: table,
+        }
+    }
+
+    pub fn metrics_for_glyph(&self, vhea_table: &VheaTable, glyph_id: u16)
+                             -> Result<VerticalMetrics, FontError> {
+        let mut reader = self.table.bytes;
+        if vhea_table.number_of_v_metrics == 0 {
+            return Err(FontError::Failed)
+        }
+
+        // Read the advance height.
+        let advance_height;
+        if glyph_id < vhea_table.number_of_v_metrics {
+            try!(reader.jump(mem::size_of::<u16>() * 2 * glyph_id as usize).map_err(FontError::eof));
+            advance_height = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof))
+        } else {
+            try!(reader.jump(mem::size_of::<u16>() * 2 *
+                             (vhea_table.number_of_v_metrics - 1) as usize).map_err(FontError::eof));
+            advance_height = try!(reader.read_u16::<BigEndian>().map_err(FontError::eof));
+            // Skip the last long metric's top-side bearing, then index the trailing array.
+            try!(reader.jump(mem::size_of::<i16>() *
+                             (glyph_id - vhea_table.number_of_v_metrics + 1) as usize)
+                       .map_err(FontError::eof));
+        }
+
+        // Read the top-side bearing.
+        let tsb = try!(reader.read_i16::<BigEndian>().map_err(FontError::eof));
+
+        Ok(VerticalMetrics {
+            advance_height: advance_height,
+            tsb: tsb,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct VerticalMetrics {
+    pub advance_height: u16,
+    pub tsb: i16,
+}
@@ -0,0 +1,45 @@
+/* Any copyright is dedicated to the Public Domain.
+ * http://creativecommons.org/publicdomain/zero/1.0/ */
+
+use charmap::{CodepointRange, CodepointRanges};
+
+fn bounds(codepoint_ranges: &CodepointRanges) -> Vec<(u32, u32)> {
+    codepoint_ranges.ranges.iter().map(|range| (range.start, range.end)).collect()
+}
+
+#[test]
+fn sorted_chars_are_coalesced() {
+    let chars: Vec<char> = "abcdffhi".chars().collect();
+    let codepoint_ranges = CodepointRanges::from_sorted_chars(&chars);
+    assert_eq!(bounds(&codepoint_ranges), [(0x61, 0x64), (0x66, 0x66), (0x68, 0x69)]);
+}
+
+#[test]
+fn added_ranges_stay_sorted_and_coalesced() {
+    let mut codepoint_ranges = CodepointRanges::empty();
+    codepoint_ranges.add_char('m');
+    codepoint_ranges.add_char('a');
+    codepoint_ranges.add_char_range('x'..='z');
+    assert_eq!(bounds(&codepoint_ranges), [(0x61, 0x61), (0x6d, 0x6d), (0x78, 0x7a)]);
+
+    // Touching series merge, as do overlapping ones.
+    codepoint_ranges.add_char('b');
+    codepoint_ranges.add_char_range('l'..='y');
+    assert_eq!(bounds(&codepoint_ranges), [(0x61, 0x62), (0x6c, 0x7a)]);
+
+    // A range that spans several series swallows them.
+    codepoint_ranges.add_range(CodepointRange::new(0x60, 0x7b));
+    assert_eq!(bounds(&codepoint_ranges), [(0x60, 0x7b)]);
+
+    // Empty ranges are ignored.
+    codepoint_ranges.add_range(CodepointRange::new(0x100, 0xff));
+    assert_eq!(bounds(&codepoint_ranges), [(0x60, 0x7b)]);
+}
+
+#[test]
+fn merged_ranges_cover_both_sets() {
+    let mut a = CodepointRanges::from_sorted_chars(&['a', 'b', 'x']);
+    let b = CodepointRanges::from_sorted_chars(&['c', 'q', 'y', 'z']);
+    a.merge(&b);
+    assert_eq!(bounds(&a), [(0x61, 0x63), (0x71, 0x71), (0x78, 0x7a)]);
+}
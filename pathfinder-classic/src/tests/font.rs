@@ -0,0 +1,18 @@
+/* Any copyright is dedicated to the Public Domain.
+ * http://creativecommons.org/publicdomain/zero/1.0/ */
+
+use font::Font;
+use memmap::{Mmap, Protection};
+
+static TEST_FONT_PATH: &'static str = "resources/tests/nimbus-sans/NimbusSanL-Regu.ttf";
+
+#[test]
+fn fonts_loaded_again_get_new_ids() {
+    let file = Mmap::open_path(TEST_FONT_PATH, Protection::Read).expect("Couldn't open test font");
+    let mut buffer = vec![];
+    unsafe {
+        let first_id = Font::new(file.as_slice(), &mut buffer).unwrap().id();
+        let font = Font::new(file.as_slice(), &mut buffer).unwrap();
+        assert!(font.id() != first_id);
+    }
+}
@@ -9,5 +9,9 @@
 // except according to those terms.
 
 mod buffers;
+mod charmap;
+mod font;
+mod outline;
 mod rect_packer;
+mod typesetter;
 
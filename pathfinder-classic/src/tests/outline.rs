@@ -0,0 +1,70 @@
+/* Any copyright is dedicated to the Public Domain.
+ * http://creativecommons.org/publicdomain/zero/1.0/ */
+
+use charmap::CodepointRange;
+use font::Font;
+use memmap::{Mmap, Protection};
+use outline::{OutlineBuilder, PathCommand};
+
+static TEST_FONT_PATH: &'static str = "resources/tests/nimbus-sans/NimbusSanL-Regu.ttf";
+
+#[test]
+fn glyph_paths_close_every_contour() {
+    let file = Mmap::open_path(TEST_FONT_PATH, Protection::Read).expect("Couldn't open test font");
+    let mut buffer = vec![];
+    unsafe {
+        let font = Font::new(file.as_slice(), &mut buffer).unwrap();
+        let codepoint_ranges = [CodepointRange::new(' ' as u32, 'o' as u32)];
+        let glyph_mapping = font.glyph_mapping_for_codepoint_ranges(&codepoint_ranges).unwrap();
+
+        let mut outline_builder = OutlineBuilder::new();
+        let space = outline_builder.add_glyph(&font, glyph_mapping.glyph_for(' ' as u32).unwrap())
+                                   .unwrap();
+        let o = outline_builder.add_glyph(&font, glyph_mapping.glyph_for('o' as u32).unwrap())
+                               .unwrap();
+        assert_eq!(outline_builder.glyph_path(space), vec![]);
+
+        // An "o" has an outer and an inner contour.
+        let path = outline_builder.glyph_path(o);
+        let move_count = path.iter().filter(|command| {
+            match **command {
+                PathCommand::MoveTo(_) => true,
+                _ => false,
+            }
+        }).count();
+        assert_eq!(move_count, 2);
+        assert_eq!(path.iter().filter(|&&command| command == PathCommand::Close).count(), 2);
+        match path[0] {
+            PathCommand::MoveTo(_) => {}
+            _ => panic!("Path doesn't start with a move"),
+        }
+        assert_eq!(*path.last().unwrap(), PathCommand::Close);
+        assert!(path.iter().any(|command| match *command {
+            PathCommand::QuadTo(..) | PathCommand::CurveTo(..) => true,
+            _ => false,
+        }));
+    }
+}
+
+#[test]
+fn adding_glyphs_together_matches_adding_them_one_by_one() {
+    let file = Mmap::open_path(TEST_FONT_PATH, Protection::Read).expect("Couldn't open test font");
+    let mut buffer = vec![];
+    unsafe {
+        let font = Font::new(file.as_slice(), &mut buffer).unwrap();
+        let glyph_ids: Vec<u16> = (0..font.num_glyphs()).collect();
+
+        let mut outline_builder = OutlineBuilder::new();
+        for &glyph_id in &glyph_ids {
+            outline_builder.add_glyph(&font, glyph_id).unwrap();
+        }
+        let mut batch_outline_builder = OutlineBuilder::new();
+        let glyph_indices = batch_outline_builder.add_glyphs(&font, &glyph_ids).unwrap();
+
+        assert_eq!(glyph_indices, (0..font.num_glyphs()).collect::<Vec<u16>>());
+        for &glyph_index in &glyph_indices {
+            assert_eq!(batch_outline_builder.glyph_path(glyph_index),
+                       outline_builder.glyph_path(glyph_index));
+        }
+    }
+}
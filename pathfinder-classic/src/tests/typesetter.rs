@@ -0,0 +1,2356 @@
+/* Any copyright is dedicated to the Public Domain.
+ * http://creativecommons.org/publicdomain/zero/1.0/ */
+
+use charmap::CodepointRange;
+use error::{GlyphStoreCreationError, TypesetterError};
+use euclid::{Matrix2D, Point2D, Rect, Size2D};
+use font::Font;
+use memmap::{Mmap, Protection};
+use shaper::{self, feature_tag};
+use std::collections::HashMap;
+use typesetter::{Alignment, BreakPolicy, Direction, LineSpacing, OverflowPolicy, Typesetter};
+use typesetter::{TypesetterBuilder, TypesetterLayout};
+use typesetter::{GlyphInstances, GlyphPosition, GlyphSource, GlyphVertex, Hyphenator, LineBox};
+use typesetter::{GlyphStore, LineMetrics, NotdefPolicy, PenSnapping, TabAlign, TabStop};
+use typesetter::{LineBreakStrategy, PositionedGlyph, ShapedGlyph, WhitespaceMode, WritingMode};
+
+static TEST_FONT_PATH: &'static str = "resources/tests/nimbus-sans/NimbusSanL-Regu.ttf";
+static MONOSPACE_TEST_FONT_PATH: &'static str =
+    "resources/tests/dejavu-sans-mono/DejaVuSansMono.ttf";
+
+fn with_font<F>(path: &str, callback: F) where F: FnOnce(&Font) {
+    let file = Mmap::open_path(path, Protection::Read).expect("Couldn't open test font");
+    let mut buffer = vec![];
+    unsafe {
+        let font = Font::new(file.as_slice(), &mut buffer).unwrap();
+        callback(&font)
+    }
+}
+
+fn with_test_font<F>(callback: F) where F: FnOnce(&Font) {
+    with_font(TEST_FONT_PATH, callback)
+}
+
+fn line_spacing(font: &Font, point_size: f32) -> f32 {
+    (font.ascender() as f32 - font.descender() as f32 + font.line_gap() as f32) * point_size /
+        font.units_per_em() as f32
+}
+
+#[test]
+fn newlines_force_line_breaks() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "a\nb\r\n\nc");
+
+        let positions = typesetter.glyph_positions();
+        assert_eq!(positions.len(), 3);
+        assert!(positions.iter().all(|position| position.x == 0.0));
+
+        let spacing = line_spacing(font, 16.0);
+        assert!((positions[1].y - positions[0].y - spacing).abs() < 0.001);
+        assert!((positions[2].y - positions[1].y - spacing * 2.0).abs() < 0.001);
+    })
+}
+
+#[test]
+fn right_to_left_text_starts_at_right_edge() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_direction(Direction::RightToLeft);
+        typesetter.add_text(font, 16.0, "ab");
+
+        let positions = typesetter.glyph_positions();
+        assert_eq!(positions.len(), 2);
+        assert!(positions[0].x < 1000.0);
+        assert!(positions[1].x < positions[0].x);
+    })
+}
+
+#[test]
+fn justified_lines_fill_page_except_last() {
+    with_test_font(|font| {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let mut left = Typesetter::new(100.0, font, 16.0);
+        left.add_text(font, 16.0, text);
+        let mut justified = Typesetter::new(100.0, font, 16.0);
+        justified.set_alignment(Alignment::Justify);
+        justified.add_text(font, 16.0, text);
+
+        let (left, justified) = (left.glyph_positions(), justified.glyph_positions());
+        let first_line_end = left.iter().position(|position| position.y != left[0].y).unwrap();
+        assert_eq!(left[0].x, justified[0].x);
+        assert!(justified[first_line_end - 1].x > left[first_line_end - 1].x);
+
+        let last = left.len() - 1;
+        assert_eq!(left[last].x, justified[last].x);
+    })
+}
+
+#[test]
+fn justification_limits_bound_the_spaces_between_words() {
+    with_test_font(|font| {
+        // The first line would have to stretch its one space across most of the page.
+        let mut typesetter = Typesetter::new(300.0, font, 16.0);
+        typesetter.set_alignment(Alignment::Justify);
+        typesetter.set_justification_limits(1.0, 2.0);
+        typesetter.add_text(font, 16.0, "a b ccccccccccccccccccccccccccccccccccccccccccc");
+        let mut left = Typesetter::new(300.0, font, 16.0);
+        left.add_text(font, 16.0, "a b ccccccccccccccccccccccccccccccccccccccccccc");
+        assert_eq!(typesetter.glyph_positions()[1].x, left.glyph_positions()[1].x);
+
+        // Squeezing the spaces makes room for the last word, and the line is squeezed to fit even
+        // though it ends the paragraph.
+        let text = "the quick brown fox";
+        let page_width = typesetter.measure_text(font, 16.0, text) - 2.0;
+        let mut squeezed = Typesetter::new(page_width, font, 16.0);
+        squeezed.set_alignment(Alignment::Justify);
+        squeezed.set_justification_limits(0.5, 2.0);
+        squeezed.add_text(font, 16.0, "the quick brown");
+        squeezed.add_text(font, 16.0, "fox");
+        assert_eq!(squeezed.line_count(), 1);
+        let last = squeezed.glyph_positions().len() - 1;
+        let right = squeezed.glyph_positions()[last].x + squeezed.glyph_advances()[last];
+        assert!((right - page_width).abs() < 0.01);
+
+        let mut whole = Typesetter::new(page_width, font, 16.0);
+        whole.set_alignment(Alignment::Justify);
+        whole.set_justification_limits(0.5, 2.0);
+        whole.add_text(font, 16.0, text);
+        let xs = |typesetter: &Typesetter| -> Vec<f32> {
+            typesetter.glyph_positions().iter().map(|position| position.x).collect()
+        };
+        assert_eq!(xs(&squeezed), xs(&whole));
+
+        squeezed.set_justification_limits(1.0, 2.0);
+        squeezed.reflow();
+        assert_eq!(squeezed.line_count(), 2);
+    })
+}
+
+#[test]
+fn right_aligned_lines_move_right() {
+    with_test_font(|font| {
+        let mut left = Typesetter::new(1000.0, font, 16.0);
+        left.add_text(font, 16.0, "hello world");
+        let mut right = Typesetter::new(1000.0, font, 16.0);
+        right.set_alignment(Alignment::Right);
+        right.add_text(font, 16.0, "hello");
+        right.add_text(font, 16.0, "world");
+
+        let (left, right) = (left.glyph_positions(), right.glyph_positions());
+        let offset = right[0].x - left[0].x;
+        assert!(offset > 900.0);
+        assert!(left.iter().zip(right.iter()).all(|(a, b)| (b.x - a.x - offset).abs() < 0.001));
+    })
+}
+
+#[test]
+fn line_spacing_can_be_overridden() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_line_spacing(LineSpacing::Multiple(1.5));
+        typesetter.add_text(font, 16.0, "a\nb");
+        typesetter.set_line_spacing(LineSpacing::Fixed(10.0));
+        typesetter.add_text(font, 16.0, "\nc");
+
+        let positions = typesetter.glyph_positions();
+        let spacing = line_spacing(font, 16.0);
+        assert!((positions[1].y - positions[0].y - spacing * 1.5).abs() < 0.001);
+        assert!((positions[2].y - positions[1].y - 10.0).abs() < 0.001);
+    })
+}
+
+#[test]
+fn letter_spacing_applies_within_words_only() {
+    with_test_font(|font| {
+        let mut normal = Typesetter::new(1000.0, font, 16.0);
+        normal.add_text(font, 16.0, "ab cd");
+        let mut tracked = Typesetter::new(1000.0, font, 16.0);
+        tracked.set_letter_spacing(2.0);
+        tracked.add_text(font, 16.0, "ab cd");
+
+        let (normal, tracked) = (normal.glyph_positions(), tracked.glyph_positions());
+        let offsets: Vec<f32> = normal.iter().zip(tracked).map(|(a, b)| b.x - a.x).collect();
+        assert_eq!(offsets, vec![0.0, 2.0, 2.0, 4.0]);
+    })
+}
+
+#[test]
+fn hit_testing_finds_glyphs_and_carets() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "ab cd\nef");
+
+        let positions = typesetter.glyph_positions().to_vec();
+        let baseline = positions[0].y;
+        assert_eq!(typesetter.glyph_at_point(Point2D::new(positions[1].x + 0.1, baseline)),
+                   Some(1));
+        assert_eq!(typesetter.glyph_at_point(Point2D::new(positions[0].x + 0.1, -100.0)),
+                   Some(0));
+        assert_eq!(typesetter.glyph_at_point(Point2D::new(positions[4].x + 0.1, 1000.0)),
+                   Some(4));
+        assert_eq!(typesetter.glyph_at_point(Point2D::new(900.0, baseline)), None);
+
+        // Just past the end of "ab", in the gap before "cd".
+        let gap_x = positions[2].x - 0.1;
+        assert_eq!(typesetter.glyph_at_point(Point2D::new(gap_x, baseline)), None);
+        assert_eq!(typesetter.caret_index_at_point(Point2D::new(gap_x, baseline)), 2);
+        assert_eq!(typesetter.caret_index_at_point(Point2D::new(positions[2].x + 0.1, baseline)),
+                   2);
+        assert_eq!(typesetter.caret_index_at_point(Point2D::new(900.0, baseline)), 4);
+        assert_eq!(typesetter.caret_index_at_point(Point2D::new(-10.0, 1000.0)), 4);
+    })
+}
+
+#[test]
+fn carets_and_breaks_respect_grapheme_clusters() {
+    with_test_font(|font| {
+        let text = "a\u{1f469}\u{200d}\u{1f467}b";
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, text);
+
+        // The emoji sequence is one cluster, so the caret goes before or after all of it.
+        let positions = typesetter.glyph_positions().to_vec();
+        let clusters: Vec<u32> = positions.iter().map(|position| position.cluster).collect();
+        assert_eq!(clusters, [0, 1, 1, 1, 12]);
+        let baseline = positions[0].y;
+        assert_eq!(typesetter.caret_index_at_point(Point2D::new(positions[1].x + 0.1, baseline)),
+                   1);
+        assert_eq!(typesetter.caret_index_at_point(Point2D::new(positions[4].x - 0.1, baseline)),
+                   4);
+
+        // Words split anywhere still keep the sequence together.
+        let mut narrow_typesetter = Typesetter::new(1.0, font, 16.0);
+        narrow_typesetter.set_break_policy(BreakPolicy::Anywhere);
+        narrow_typesetter.add_text(font, 16.0, text);
+        let line_starts: Vec<usize> = narrow_typesetter.lines()
+                                                       .map(|line| line.glyph_start)
+                                                       .collect();
+        assert_eq!(line_starts, [0, 1, 4]);
+    })
+}
+
+#[test]
+fn lines_follow_line_breaks() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "ab cd\n\nef\n");
+
+        let lines: Vec<_> = typesetter.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines.iter().map(|line| line.glyph_positions.len()).collect::<Vec<_>>(),
+                   vec![4, 0, 2, 0]);
+        assert_eq!(lines[2].glyph_start, 4);
+        assert_eq!(lines[0].left, 0.0);
+        assert!(lines[0].right > lines[2].right);
+        assert!(lines.windows(2).all(|pair| pair[0].baseline < pair[1].baseline));
+    })
+}
+
+#[test]
+fn clear_restores_initial_state() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "the quick brown fox jumps over the lazy dog");
+        let expected = typesetter.glyph_positions().to_vec();
+
+        typesetter.clear();
+        assert!(typesetter.glyph_positions().is_empty());
+        assert_eq!(typesetter.lines().count(), 1);
+
+        typesetter.add_text(font, 16.0, "the quick brown fox jumps over the lazy dog");
+        let actual = typesetter.glyph_positions();
+        assert!(expected.iter().zip(actual).all(|(a, b)| a.position() == b.position()));
+
+        typesetter.reset_with(font, 32.0);
+        typesetter.add_text(font, 16.0, "the");
+        assert!(typesetter.glyph_positions()[0].y > expected[0].y);
+    })
+}
+
+#[test]
+fn reflow_matches_fresh_layout() {
+    with_test_font(|font| {
+        let text = "the quick brown fox\njumps over the lazy dog";
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, text);
+        assert_eq!(typesetter.lines().count(), 2);
+
+        typesetter.set_page_width(100.0);
+        typesetter.reflow();
+        assert!(typesetter.lines().count() > 2);
+
+        let mut fresh = Typesetter::new(100.0, font, 16.0);
+        fresh.add_text(font, 16.0, text);
+        assert_eq!(typesetter.lines().count(), fresh.lines().count());
+        assert!(typesetter.glyph_positions().iter().zip(fresh.glyph_positions()).all(|(a, b)| {
+            a.position() == b.position() && a.glyph_id == b.glyph_id
+        }));
+    })
+}
+
+#[test]
+fn tabs_advance_to_tab_stops() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(150.0, font, 16.0);
+        typesetter.set_tab_width(100.0);
+        typesetter.add_text(font, 16.0, "a\tb\nabc\tb\na\t\tb");
+
+        let positions = typesetter.glyph_positions();
+        assert_eq!(positions.len(), 8);
+        assert_eq!(positions[1].x, 100.0);
+        assert_eq!(positions[5].x, 100.0);
+
+        // The second tab stop is past the end of the line, so the second tab wraps.
+        assert_eq!(positions[7].x, 100.0);
+        assert!(positions[7].y > positions[6].y);
+        assert_eq!(typesetter.lines().count(), 4);
+    })
+}
+
+#[test]
+fn right_and_decimal_tab_stops_line_up_columns() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_tab_stops(&[
+            TabStop { position: 400.0, alignment: TabAlign::Decimal },
+            TabStop { position: 200.0, alignment: TabAlign::Right },
+        ]);
+        assert_eq!(typesetter.tab_stops()[0].position, 200.0);
+        typesetter.add_text(font, 16.0, "Tea\t2\t3.25\nBiscuits\t12\t10.5 each\nCake\t\t4");
+
+        let positions = typesetter.glyph_positions();
+        let advances = typesetter.glyph_advances();
+        let right = |glyph_index: usize| positions[glyph_index].x + advances[glyph_index];
+
+        // "2" and "12" end at the right stop, and the decimal points are at the decimal stop.
+        assert!((right(3) - 200.0).abs() < 0.001);
+        assert!((positions[5].x - 400.0).abs() < 0.001);
+        assert!((right(17) - 200.0).abs() < 0.001);
+        assert!((positions[20].x - 400.0).abs() < 0.001);
+
+        // Text after the decimal point carries on to the right of the stop, and a number
+        // without one ends at the stop.
+        assert!(positions[22].x > 400.0);
+        assert!((right(30) - 400.0).abs() < 0.001);
+        assert_eq!(typesetter.lines().count(), 3);
+
+        let xs: Vec<f32> = positions.iter().map(|position| position.x).collect();
+        let mut reflowed = typesetter.clone();
+        reflowed.reflow();
+        let reflowed_xs: Vec<f32> = reflowed.glyph_positions()
+                                            .iter()
+                                            .map(|position| position.x)
+                                            .collect();
+        assert_eq!(reflowed_xs, xs);
+    })
+}
+
+#[test]
+fn preserved_spaces_each_advance_by_one_space() {
+    with_font(MONOSPACE_TEST_FONT_PATH, |font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_whitespace_mode(WhitespaceMode::Preserve);
+        typesetter.add_text(font, 16.0, "a   b\n  c");
+
+        let glyph_id = typesetter.glyph_positions()[0].glyph_id;
+        let advance = font.advance_for_glyph(glyph_id).unwrap() as f32 * 16.0 /
+            font.units_per_em() as f32;
+        let x_positions = |typesetter: &Typesetter| -> Vec<f32> {
+            typesetter.glyph_positions().iter().map(|position| position.x).collect()
+        };
+        assert_eq!(x_positions(&typesetter), vec![0.0, advance * 4.0, advance * 2.0]);
+
+        // The spaces are kept when the text is laid out again.
+        typesetter.set_page_width(500.0);
+        typesetter.reflow();
+        assert_eq!(x_positions(&typesetter), vec![0.0, advance * 4.0, advance * 2.0]);
+
+        let mut collapsed = Typesetter::new(1000.0, font, 16.0);
+        collapsed.add_text(font, 16.0, "a   b\n  c");
+        assert_eq!(x_positions(&collapsed), vec![0.0, advance * 2.0, 0.0]);
+    })
+}
+
+#[test]
+fn glyphs_are_found_by_grid_cell() {
+    with_font(MONOSPACE_TEST_FONT_PATH, |font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_whitespace_mode(WhitespaceMode::Preserve);
+        typesetter.add_text(font, 16.0, "ab  c\n  d");
+
+        let glyph_id = |line_index, column| {
+            typesetter.glyph_at_cell(line_index, column).map(|position| position.glyph_id)
+        };
+        let glyph_ids: Vec<u16> = typesetter.glyph_positions()
+                                            .iter()
+                                            .map(|position| position.glyph_id)
+                                            .collect();
+        assert_eq!(glyph_id(0, 0), Some(glyph_ids[0]));
+        assert_eq!(glyph_id(0, 1), Some(glyph_ids[1]));
+        assert_eq!(glyph_id(0, 2), None);
+        assert_eq!(glyph_id(0, 4), Some(glyph_ids[2]));
+        assert_eq!(glyph_id(0, 5), None);
+        assert_eq!(glyph_id(1, 2), Some(glyph_ids[3]));
+        assert_eq!(glyph_id(2, 0), None);
+    })
+}
+
+#[test]
+fn grid_cells_keep_the_margins_of_their_lines() {
+    with_font(MONOSPACE_TEST_FONT_PATH, |font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "ab\n");
+        typesetter.set_margins(100.0, 0.0);
+        typesetter.add_text(font, 16.0, "cd\n");
+        typesetter.set_direction(Direction::RightToLeft);
+        typesetter.set_page_width(500.0);
+        typesetter.add_text(font, 16.0, "ef");
+
+        // Each line's cells are counted from where that line started.
+        let glyph_ids: Vec<u16> = typesetter.glyph_positions()
+                                            .iter()
+                                            .map(|position| position.glyph_id)
+                                            .collect();
+        let glyph_id = |line_index, column| {
+            typesetter.glyph_at_cell(line_index, column).map(|position| position.glyph_id)
+        };
+        assert_eq!(glyph_id(0, 0), Some(glyph_ids[0]));
+        assert_eq!(glyph_id(0, 1), Some(glyph_ids[1]));
+        assert_eq!(glyph_id(1, 0), Some(glyph_ids[2]));
+        assert_eq!(glyph_id(1, 1), Some(glyph_ids[3]));
+        assert_eq!(glyph_id(2, 0), Some(glyph_ids[4]));
+        assert_eq!(glyph_id(2, 1), Some(glyph_ids[5]));
+        assert_eq!(glyph_id(2, 2), None);
+    })
+}
+
+#[test]
+fn cursor_position_follows_the_last_word() {
+    with_font(MONOSPACE_TEST_FONT_PATH, |font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "ab\ncd");
+        let glyph_id = typesetter.glyph_positions()[0].glyph_id;
+        let advance = font.advance_for_glyph(glyph_id).unwrap() as f32 * 16.0 /
+            font.units_per_em() as f32;
+        let baseline = typesetter.glyph_positions()[2].y;
+        assert_eq!(typesetter.cursor_position(), Point2D::new(advance * 3.0, baseline));
+
+        // Centering moves the pen along with the line.
+        let mut centered = Typesetter::new(1000.0, font, 16.0);
+        centered.set_alignment(Alignment::Center);
+        centered.add_text(font, 16.0, "ab");
+        let expected_x = centered.glyph_positions()[1].x + advance * 2.0;
+        assert_eq!(centered.cursor_position().x, expected_x);
+    })
+}
+
+#[test]
+fn drop_caps_span_and_indent_lines() {
+    with_test_font(|font| {
+        let text = "orem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor";
+        let check = |typesetter: &Typesetter| {
+            let lines: Vec<_> = typesetter.lines().collect();
+            assert!(lines.len() > 3);
+
+            // The drop cap sits on the third baseline without pushing the first one down.
+            let drop_cap = lines[0].glyph_positions[0];
+            assert_eq!(drop_cap.x, 0.0);
+            assert_eq!(drop_cap.y, lines[2].baseline);
+            assert_eq!(lines[0].baseline, Typesetter::new(150.0, font, 16.0).cursor_position().y);
+
+            let indent = lines[0].glyph_positions[1].x;
+            assert!(indent > 0.0);
+            assert_eq!(lines[1].glyph_positions[0].x, indent);
+            assert_eq!(lines[2].glyph_positions[0].x, indent);
+            assert_eq!(lines[3].glyph_positions[0].x, 0.0);
+        };
+
+        let mut typesetter = Typesetter::new(150.0, font, 16.0);
+        typesetter.add_drop_cap(font, 48.0, "L", 3);
+        typesetter.add_text(font, 16.0, text);
+        check(&typesetter);
+
+        typesetter.set_page_width(140.0);
+        typesetter.reflow();
+        check(&typesetter);
+    })
+}
+
+#[test]
+fn restoring_a_snapshot_removes_later_text() {
+    with_test_font(|font| {
+        let positions = |typesetter: &Typesetter| -> Vec<(u16, f32, f32)> {
+            typesetter.glyph_positions()
+                      .iter()
+                      .map(|position| (position.glyph_id, position.x, position.y))
+                      .collect()
+        };
+
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        typesetter.set_alignment(Alignment::Center);
+        typesetter.add_text(font, 16.0, "the quick");
+        let (before, cursor) = (positions(&typesetter), typesetter.cursor_position());
+        let state = typesetter.snapshot();
+
+        typesetter.add_text(font, 16.0, "brown fox jumps over the lazy dog");
+        let after = positions(&typesetter);
+        assert!(typesetter.line_count() > 1);
+
+        typesetter.restore(state.clone());
+        assert_eq!(positions(&typesetter), before);
+        assert_eq!(typesetter.cursor_position(), cursor);
+        assert_eq!(typesetter.line_count(), 1);
+
+        typesetter.add_text(font, 16.0, "brown fox jumps over the lazy dog");
+        assert_eq!(positions(&typesetter), after);
+        typesetter.restore(state);
+        assert_eq!(positions(&typesetter), before);
+    })
+}
+
+#[test]
+fn optimal_fit_evens_out_lines() {
+    with_test_font(|font| {
+        let text = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod \
+                    tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, \
+                    quis nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo";
+        let lay_out = |strategy: LineBreakStrategy| {
+            let mut typesetter = TypesetterBuilder::new(220.0).line_break_strategy(strategy)
+                                                              .build(font, 16.0);
+            typesetter.add_text(font, 16.0, text);
+            typesetter
+        };
+        let slack = |typesetter: &Typesetter| -> Vec<f32> {
+            typesetter.lines().map(|line| 220.0 - line.right).collect()
+        };
+        let worst_slack = |typesetter: &Typesetter| {
+            let slack = slack(typesetter);
+            slack[..(slack.len() - 1)].iter().cloned().fold(0.0, f32::max)
+        };
+
+        let (greedy, mut optimal) = (lay_out(LineBreakStrategy::Greedy),
+                                     lay_out(LineBreakStrategy::OptimalFit));
+        assert_eq!(greedy.glyph_positions().len(), optimal.glyph_positions().len());
+        assert!(slack(&optimal).iter().all(|&slack| slack >= 0.0));
+        assert!(slack(&optimal) != slack(&greedy));
+        assert!(worst_slack(&optimal) < worst_slack(&greedy));
+
+        let before = slack(&optimal);
+        optimal.reflow();
+        assert_eq!(slack(&optimal), before);
+    })
+}
+
+#[test]
+fn balanced_lines_keep_the_line_count() {
+    with_test_font(|font| {
+        let text = "Click here to save your changes before closing";
+        let mut greedy = Typesetter::new(1000.0, font, 16.0);
+        let width = greedy.measure_text(font, 16.0, "Click here to save your changes before") + 1.0;
+        greedy.set_page_width(width);
+        greedy.add_text(font, 16.0, text);
+        let balanced = |text| {
+            let mut typesetter =
+                TypesetterBuilder::new(width).line_break_strategy(LineBreakStrategy::Balanced)
+                                             .build(font, 16.0);
+            typesetter.add_text(font, 16.0, text);
+            typesetter
+        };
+        let line_widths = |typesetter: &Typesetter| -> Vec<f32> {
+            typesetter.lines().map(|line| line.right - line.left).collect()
+        };
+
+        let mut typesetter = balanced(text);
+        assert_eq!(greedy.line_count(), 2);
+        assert_eq!(typesetter.line_count(), 2);
+        assert_eq!(typesetter.glyph_positions().len(), greedy.glyph_positions().len());
+        let (greedy_widths, balanced_widths) = (line_widths(&greedy), line_widths(&typesetter));
+        assert!((balanced_widths[0] - balanced_widths[1]).abs() <
+                (greedy_widths[0] - greedy_widths[1]).abs());
+        assert!(balanced_widths[0] < greedy_widths[0]);
+
+        typesetter.reflow();
+        assert_eq!(line_widths(&typesetter), balanced_widths);
+        assert_eq!(balanced("Save").line_count(), 1);
+    })
+}
+
+#[test]
+fn indents_apply_to_first_and_continuation_lines() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        typesetter.set_first_line_indent(10.0);
+        typesetter.set_hanging_indent(30.0);
+        typesetter.add_text(font, 16.0, "the quick brown fox jumps\nover the lazy dog");
+
+        let lines: Vec<_> = typesetter.lines().collect();
+        assert!(lines.len() > 3);
+        assert_eq!(lines[0].glyph_positions[0].x, 10.0);
+        assert_eq!(lines[1].glyph_positions[0].x, 30.0);
+
+        // "thequickbrownfoxjumps" is 21 glyphs long.
+        let paragraph = lines.iter().find(|line| line.glyph_start == 21).unwrap();
+        assert_eq!(paragraph.glyph_positions[0].x, 10.0);
+        assert!(lines.iter().all(|line| line.right <= 100.0));
+    })
+}
+
+#[test]
+fn kerning_does_not_affect_fonts_without_kern_table() {
+    // The test font has no `kern` table, so turning kerning off must not change anything.
+    with_test_font(|font| {
+        let mut kerned = Typesetter::new(1000.0, font, 16.0);
+        kerned.add_text(font, 16.0, "AV To");
+
+        let mut unkerned = Typesetter::new(1000.0, font, 16.0);
+        unkerned.set_kerning(false);
+        assert!(!unkerned.kerning());
+        unkerned.add_text(font, 16.0, "AV To");
+
+        assert!(kerned.glyph_positions().iter().zip(unkerned.glyph_positions()).all(|(a, b)| {
+            a.position() == b.position()
+        }));
+    })
+}
+
+#[test]
+fn taller_runs_push_the_baseline_down() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "a");
+        typesetter.add_text(font, 32.0, "b");
+        typesetter.add_text(font, 16.0, "c\nd");
+
+        let positions = typesetter.glyph_positions();
+        assert_eq!(positions[0].y, positions[1].y);
+        assert_eq!(positions[1].y, positions[2].y);
+        let ascent = font.ascender() as f32 * 32.0 / font.units_per_em() as f32;
+        assert!((positions[0].y - ascent).abs() < 0.001);
+
+        // The 32-point "b" descends further than the 16-point text that ends the line.
+        let extra_descent = -font.descender() as f32 * 16.0 / font.units_per_em() as f32;
+        let spacing = line_spacing(font, 16.0) + extra_descent;
+        assert!((positions[3].y - positions[2].y - spacing).abs() < 0.001);
+    })
+}
+
+#[test]
+fn max_lines_truncates_with_ellipsis() {
+    with_test_font(|font| {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        typesetter.set_max_lines(Some(2));
+        typesetter.add_text(font, 16.0, text);
+        typesetter.add_text(font, 16.0, "again");
+
+        assert!(typesetter.was_truncated());
+        let lines: Vec<_> = typesetter.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].right <= 100.0);
+
+        let ellipsis_glyph_id = font.glyph_mapping_for_codepoint_ranges(&[
+            CodepointRange::new(0x2026, 0x2026)
+        ]).unwrap().glyph_for(0x2026).unwrap();
+        assert_eq!(lines[1].glyph_positions.last().unwrap().glyph_id, ellipsis_glyph_id);
+
+        // Text that fits is left alone.
+        typesetter.clear();
+        typesetter.add_text(font, 16.0, "the quick\nbrown fox\n");
+        assert!(!typesetter.was_truncated());
+        assert_eq!(typesetter.glyph_positions().len(), 16);
+    })
+}
+
+#[test]
+fn break_policy_controls_breaks_within_words() {
+    with_test_font(|font| {
+        let text = "abcdefghijklm-nopqrstuvwxyz";
+        let mut typesetter = Typesetter::new(150.0, font, 16.0);
+        typesetter.add_text(font, 16.0, text);
+        assert_eq!(typesetter.lines().count(), 1);
+
+        typesetter.clear();
+        typesetter.set_break_policy(BreakPolicy::Punctuation);
+        typesetter.add_text(font, 16.0, text);
+        let glyph_counts: Vec<_> = typesetter.lines()
+                                             .map(|line| line.glyph_positions.len())
+                                             .collect();
+        assert_eq!(glyph_counts, vec![14, 13]);
+
+        typesetter.clear();
+        typesetter.set_break_policy(BreakPolicy::Anywhere);
+        typesetter.set_page_width(50.0);
+        typesetter.add_text(font, 16.0, text);
+        assert!(typesetter.lines().count() > 2);
+        assert!(typesetter.lines().all(|line| line.right <= 50.0));
+        assert_eq!(typesetter.glyph_positions().len(), 27);
+
+        // Words split to fit are put back together when there is room.
+        typesetter.set_page_width(1000.0);
+        typesetter.reflow();
+        assert_eq!(typesetter.lines().count(), 1);
+    })
+}
+
+#[test]
+fn measure_text_matches_layout() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_letter_spacing(1.0);
+        let width = typesetter.measure_text(font, 16.0, "  hello\tworld ");
+        assert!(typesetter.glyph_positions().is_empty());
+        assert_eq!(typesetter.try_measure_text(font, 16.0, "hello world"), Ok(width));
+
+        typesetter.add_text(font, 16.0, "hello world");
+        let line = typesetter.line(0).unwrap();
+        assert!((line.right - line.left - width).abs() < 0.001);
+    })
+}
+
+#[test]
+fn shaped_words_match_layout() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_letter_spacing(1.0);
+        let words = typesetter.shape_words(font, 16.0, " hello\tworld");
+        assert!(typesetter.glyph_positions().is_empty());
+        assert_eq!(words.iter().map(|word| word.byte_range.clone()).collect::<Vec<_>>(),
+                   [1..6, 7..12]);
+
+        // Each word's glyphs sit where the typesetter would put them, relative to the word start.
+        typesetter.add_text(font, 16.0, "hello world");
+        let laid_out_positions = typesetter.glyph_positions();
+        let word_start = laid_out_positions[5].x;
+        for (glyph, laid_out_glyph) in words[1].glyph_positions.iter()
+                                               .zip(&laid_out_positions[5..]) {
+            assert_eq!(glyph.glyph_id, laid_out_glyph.glyph_id);
+            assert!((glyph.x - (laid_out_glyph.x - word_start)).abs() < 0.001);
+            assert_eq!(glyph.y, 0.0);
+        }
+
+        let line = typesetter.line(0).unwrap();
+        let space = line.right - line.left - words[0].advance - words[1].advance;
+        assert!((word_start - line.left - words[0].advance - space).abs() < 0.001);
+
+        // Letter spacing isn't added after a hyphen that the word may be broken at.
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_letter_spacing(4.0);
+        typesetter.set_break_policy(BreakPolicy::Punctuation);
+        let words = typesetter.try_shape_words(font, 16.0, "self-made").unwrap();
+        typesetter.add_text(font, 16.0, "self-made");
+        let laid_out_positions = typesetter.glyph_positions();
+        assert_eq!(words[0].glyph_positions.len(), laid_out_positions.len());
+        for (glyph, laid_out_glyph) in words[0].glyph_positions.iter().zip(laid_out_positions) {
+            assert!((glyph.x - (laid_out_glyph.x - laid_out_positions[0].x)).abs() < 0.001);
+        }
+        let line = typesetter.line(0).unwrap();
+        assert!((words[0].advance - (line.right - line.left)).abs() < 0.001);
+    })
+}
+
+#[test]
+fn fitted_point_sizes_fill_the_box() {
+    with_test_font(|font| {
+        let text = "Headline that must fit";
+        let typesetter = Typesetter::new(1000.0, font, 16.0);
+        let point_size = typesetter.fit_point_size(font, text, 200.0, 80.0, 4.0, 200.0);
+        assert!(point_size > 4.0 && point_size < 200.0);
+
+        // The text fits in the box at the chosen size, but not at a slightly larger one.
+        let fits = |point_size: f32| {
+            let mut fitted = Typesetter::new(200.0, font, point_size);
+            fitted.add_text(font, point_size, text);
+            let line_metrics = fitted.line_metrics();
+            let last_line = &line_metrics[line_metrics.len() - 1];
+            let height = last_line.baseline + last_line.descent -
+                (line_metrics[0].baseline - line_metrics[0].ascent);
+            height <= 80.0 && line_metrics.iter().all(|line| line.right <= 200.001)
+        };
+        assert!(fits(point_size));
+        assert!(!fits(point_size * 1.01));
+        assert!(typesetter.glyph_positions().is_empty());
+    })
+}
+
+#[test]
+fn line_relative_positions_are_measured_from_each_baseline() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "ab\ncd");
+        typesetter.set_baseline_shift(3.0);
+        typesetter.add_text(font, 16.0, "e");
+
+        let positions = typesetter.positions_line_relative();
+        let line_indices: Vec<usize> = positions.iter()
+                                                .map(|&(line_index, _)| line_index)
+                                                .collect();
+        assert_eq!(line_indices, vec![0, 0, 1, 1, 1]);
+        for (glyph_index, &(_, ref position)) in positions[..4].iter().enumerate() {
+            assert_eq!(position.y, 0.0);
+            assert_eq!(position.x, typesetter.glyph_positions()[glyph_index].x);
+        }
+        assert_eq!(positions[4].1.y, -3.0);
+    })
+}
+
+#[test]
+fn notdef_policies_skip_or_report_missing_characters() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "a\u{4e2d} b");
+        assert_eq!(typesetter.glyph_positions()[1].glyph_id, 0);
+        assert!(typesetter.missing_codepoints().is_empty());
+
+        typesetter.clear();
+        typesetter.set_notdef_policy(NotdefPolicy::Report);
+        typesetter.add_text(font, 16.0, "a\u{4e2d} b\u{2603}\u{4e2d}");
+        assert_eq!(typesetter.glyph_positions().len(), 5);
+        assert_eq!(typesetter.missing_codepoints(), &['\u{2603}', '\u{4e2d}']);
+
+        typesetter.clear();
+        typesetter.set_notdef_policy(NotdefPolicy::Skip);
+        typesetter.add_text(font, 16.0, "a\u{4e2d} \u{2603} b");
+        let positions = typesetter.glyph_positions();
+        assert_eq!(positions.len(), 2);
+        assert!(positions.iter().all(|position| position.glyph_id != 0));
+        assert_eq!(typesetter.missing_codepoints(), &['\u{2603}', '\u{4e2d}']);
+    })
+}
+
+#[test]
+fn fallback_fonts_fill_in_missing_characters() {
+    with_test_font(|font| {
+        with_font(MONOSPACE_TEST_FONT_PATH, |fallback_font| {
+            let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+            typesetter.add_text_with_fallback(&[font, fallback_font], 16.0, "a\u{2603}b c");
+
+            // Only the snowman comes from the fallback font, and it stays in the same word.
+            let positions = typesetter.glyph_positions();
+            let advances = typesetter.glyph_advances();
+            assert_eq!(positions.len(), 4);
+            assert!(positions.iter().all(|position| position.glyph_id != 0));
+            assert_eq!(positions[1].x, positions[0].x + advances[0]);
+            assert_eq!(positions[2].x, positions[1].x + advances[1]);
+            assert!(positions[3].x > positions[2].x + advances[2]);
+            assert!(positions.iter().all(|position| position.y == positions[0].y));
+
+            // The line is as tall as the taller font.
+            let mut fallback_typesetter = Typesetter::new(1000.0, fallback_font, 16.0);
+            fallback_typesetter.add_text(fallback_font, 16.0, "\u{2603}");
+            let mut primary_typesetter = Typesetter::new(1000.0, font, 16.0);
+            primary_typesetter.add_text(font, 16.0, "a");
+            let ascent = fallback_typesetter.line_metrics()[0].ascent
+                                            .max(primary_typesetter.line_metrics()[0].ascent);
+            assert_eq!(typesetter.line_metrics()[0].ascent, ascent);
+
+            // Glyph sources refer to the whole string, which is one piece of text however many
+            // fonts it is laid out in.
+            let sources: Vec<GlyphSource> = (0..4).map(|glyph_index| {
+                typesetter.glyph_source(glyph_index).unwrap()
+            }).collect();
+            assert_eq!(sources.iter().map(|source| source.byte_range.clone()).collect::<Vec<_>>(),
+                       vec![0..1, 1..4, 4..5, 6..7]);
+            assert!(sources.iter().all(|source| source.text_index == 0));
+            assert_eq!(positions[3].cluster, 6);
+            typesetter.add_text(font, 16.0, " d");
+            assert_eq!(typesetter.glyph_source(4).unwrap(),
+                       GlyphSource { text_index: 1, byte_range: 1..2 });
+
+            // Removing the last text removes all of it, and removing the text before it removes
+            // every font's glyphs.
+            assert!(typesetter.pop_last_run());
+            assert_eq!(typesetter.glyph_positions().len(), 4);
+            assert!(typesetter.pop_last_run());
+            assert!(typesetter.glyph_positions().is_empty());
+            typesetter.add_text_with_fallback(&[font, fallback_font], 16.0, "a\u{2603}b c");
+
+            // Without any fonts, nothing is laid out.
+            assert_eq!(typesetter.try_add_text_with_fallback(&[], 16.0, " d"),
+                       Err(TypesetterError::NoFonts));
+            assert_eq!(typesetter.glyph_positions().len(), 4);
+        })
+    })
+}
+
+#[test]
+fn word_ranges_keep_broken_words_whole() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "self-made  men,\tco\u{ad}operate");
+        assert_eq!(typesetter.word_ranges(), vec![(0, 9), (9, 13), (13, 22)]);
+
+        // Breaking the words across lines doesn't split them.
+        let width = typesetter.measure_text(font, 16.0, "self-");
+        let mut narrow = Typesetter::new(width, font, 16.0);
+        narrow.add_text(font, 16.0, "self-made co\u{ad}operate");
+        assert!(narrow.line_count() > 2);
+        let glyph_count = narrow.glyph_positions().len();
+        assert_eq!(narrow.word_ranges(), vec![(0, 9), (9, glyph_count)]);
+    })
+}
+
+#[test]
+fn glyph_advances_lead_to_the_next_glyph() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_letter_spacing(1.5);
+        typesetter.add_text(font, 16.0, "Wave");
+        let positions = typesetter.glyph_positions();
+        let advances = typesetter.glyph_advances();
+        assert_eq!(advances.len(), positions.len());
+        for (glyph_index, advance) in advances[..(advances.len() - 1)].iter().enumerate() {
+            let next_x = positions[glyph_index].x + advance + 1.5;
+            assert!((positions[glyph_index + 1].x - next_x).abs() < 0.001);
+        }
+    })
+}
+
+#[test]
+fn space_advances_match_the_gaps_between_words() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_word_spacing(2.0);
+        let space_advance = typesetter.space_advance(font, 16.0);
+        assert_eq!(typesetter.try_space_advance(font, 16.0), Ok(space_advance));
+        let words_width = typesetter.measure_text(font, 16.0, "a") +
+            typesetter.measure_text(font, 16.0, "b");
+        let width = typesetter.measure_text(font, 16.0, "a b");
+        assert!(space_advance > 2.0);
+        assert!((width - words_width - space_advance).abs() < 0.001);
+    })
+}
+
+#[test]
+fn space_advances_follow_point_sizes_and_writing_modes() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        let horizontal_advance = typesetter.space_advance(font, 16.0);
+        assert!((typesetter.space_advance(font, 32.0) - horizontal_advance * 2.0).abs() < 0.001);
+
+        // These go through the space glyph remembered for the font by the first call.
+        for _ in 0..3 {
+            typesetter.add_text(font, 16.0, "a ");
+        }
+        let positions = typesetter.glyph_positions();
+        let glyph_advance = typesetter.glyph_advances()[0];
+        assert!((positions[1].x - positions[0].x - glyph_advance - horizontal_advance).abs() <
+                0.001);
+
+        typesetter.set_writing_mode(WritingMode::VerticalRightToLeft);
+        let vertical_advance = typesetter.space_advance(font, 16.0);
+        assert!((vertical_advance - horizontal_advance).abs() > 0.001);
+        typesetter.set_writing_mode(WritingMode::Horizontal);
+        assert_eq!(typesetter.space_advance(font, 16.0), horizontal_advance);
+    })
+}
+
+#[test]
+fn features_substitute_glyphs() {
+    with_font(MONOSPACE_TEST_FONT_PATH, |font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "\u{a1}a");
+        typesetter.add_text_with_features(font, 16.0, " \u{a1}a", &[(feature_tag(b"case"), 1)]);
+        typesetter.add_text_with_features(font, 16.0, " \u{a1}a", &[(feature_tag(b"case"), 0)]);
+
+        // The font's case-sensitive forms include one for the inverted exclamation mark only.
+        let glyph_ids: Vec<u16> = typesetter.glyph_positions()
+                                            .iter()
+                                            .map(|position| position.glyph_id)
+                                            .collect();
+        assert_eq!(glyph_ids.len(), 6);
+        assert!(glyph_ids[2] != glyph_ids[0]);
+        assert_eq!(glyph_ids[3], glyph_ids[1]);
+        assert_eq!(&glyph_ids[4..], &glyph_ids[..2]);
+    })
+}
+
+#[test]
+fn languages_select_localized_forms() {
+    with_font(MONOSPACE_TEST_FONT_PATH, |font| {
+        // DejaVu Sans Mono has a Serbian form of the Cyrillic letter be.
+        let glyph_ids = |language: &[u8; 4]| -> Vec<u16> {
+            let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+            typesetter.add_text_with_language(font,
+                                              16.0,
+                                              "\u{431}\u{434}",
+                                              feature_tag(b"cyrl"),
+                                              feature_tag(language));
+            typesetter.glyph_positions().iter().map(|position| position.glyph_id).collect()
+        };
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "\u{431}\u{434}");
+        let default_glyph_ids: Vec<u16> = typesetter.glyph_positions()
+                                                    .iter()
+                                                    .map(|position| position.glyph_id)
+                                                    .collect();
+
+        let serbian_glyph_ids = glyph_ids(b"SRB ");
+        assert!(serbian_glyph_ids[0] != default_glyph_ids[0]);
+        assert_eq!(serbian_glyph_ids[1], default_glyph_ids[1]);
+        assert_eq!(glyph_ids(b"RUS "), default_glyph_ids);
+    })
+}
+
+#[test]
+fn substituted_glyphs_get_their_own_advances() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "imi");
+        let positions = typesetter.glyph_positions().to_vec();
+        let (i_glyph_id, m_glyph_id) = (positions[0].glyph_id, positions[1].glyph_id);
+
+        let mut substitutions = HashMap::new();
+        substitutions.insert(i_glyph_id, m_glyph_id);
+        let mut substituted = Typesetter::new(1000.0, font, 16.0);
+        substituted.add_text_with_substitutions(font, 16.0, "ii", &substitutions);
+        let substituted_positions = substituted.glyph_positions();
+        assert!(substituted_positions.iter().all(|position| position.glyph_id == m_glyph_id));
+        let m_advance = positions[2].x - positions[1].x;
+        assert!((substituted_positions[1].x - substituted_positions[0].x - m_advance).abs() <
+                0.001);
+    })
+}
+
+#[test]
+fn builder_configures_typesetter() {
+    with_test_font(|font| {
+        let text = "the quick brown fox\tjumps over the lazy dog";
+        let mut built = TypesetterBuilder::new(100.0).direction(Direction::RightToLeft)
+                                                     .alignment(Alignment::Center)
+                                                     .line_spacing(LineSpacing::Fixed(20.0))
+                                                     .break_policy(BreakPolicy::Anywhere)
+                                                     .tab_width(30.0)
+                                                     .first_line_indent(5.0)
+                                                     .build(font, 16.0);
+        built.add_text(font, 16.0, text);
+
+        let mut configured = Typesetter::new(100.0, font, 16.0);
+        configured.set_direction(Direction::RightToLeft);
+        configured.set_alignment(Alignment::Center);
+        configured.set_line_spacing(LineSpacing::Fixed(20.0));
+        configured.set_break_policy(BreakPolicy::Anywhere);
+        configured.set_tab_width(30.0);
+        configured.set_first_line_indent(5.0);
+        configured.add_text(font, 16.0, text);
+
+        assert_eq!(built.alignment(), Alignment::Center);
+        assert_eq!(built.glyph_positions().len(), configured.glyph_positions().len());
+        assert!(built.glyph_positions().iter().zip(configured.glyph_positions()).all(|(a, b)| {
+            a.position() == b.position()
+        }));
+    })
+}
+
+#[test]
+fn vertical_text_stacks_glyphs_in_columns() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(60.0, font, 16.0);
+        typesetter.set_writing_mode(WritingMode::VerticalRightToLeft);
+        typesetter.add_text(font, 16.0, "ab cd");
+
+        // Each word is more than half as tall as a column, so they go in separate columns.
+        let positions = typesetter.glyph_positions();
+        assert_eq!(positions.len(), 4);
+        assert!(positions[1].y > positions[0].y);
+        assert!(positions[2].y < positions[1].y);
+        assert!(positions[2].x < positions[0].x - 10.0);
+        assert!(positions.iter().all(|position| position.x < 0.0));
+        assert_eq!(typesetter.lines().count(), 2);
+
+        let mut reflowed = typesetter.clone();
+        reflowed.set_page_width(1000.0);
+        reflowed.reflow();
+        assert_eq!(reflowed.lines().count(), 1);
+        assert_eq!(reflowed.glyph_positions()[0].position(), positions[0].position());
+    })
+}
+
+#[test]
+fn caret_rects_stay_at_end_of_wrapped_lines() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(30.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "ab cd\ne");
+
+        let positions = typesetter.glyph_positions().to_vec();
+        let caret_rects = typesetter.caret_rects();
+        assert_eq!(positions.len(), 5);
+        assert_eq!(caret_rects.len(), 6);
+        assert!(caret_rects.iter().all(|rect| rect.size.width > 0.0 && rect.size.height > 0.0));
+
+        // The caret before "c" stays at the end of the first line, after "b".
+        let center_x = |rect: &Rect<f32>| rect.origin.x + rect.size.width * 0.5;
+        assert_eq!(center_x(&caret_rects[0]), positions[0].x);
+        assert!(center_x(&caret_rects[2]) > positions[1].x);
+        assert_eq!(caret_rects[2].origin.y, caret_rects[0].origin.y);
+        assert!(caret_rects[3].origin.y > caret_rects[2].origin.y);
+
+        // After the forced line break, the caret moves to the start of the new line.
+        assert!(caret_rects[4].origin.y > caret_rects[3].origin.y);
+        assert_eq!(center_x(&caret_rects[4]), positions[4].x);
+        assert!(center_x(&caret_rects[5]) > positions[4].x);
+    })
+}
+
+#[test]
+fn caret_rects_span_their_lines() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "ab\ncd");
+        typesetter.add_text(font, 32.0, " ef");
+
+        // Every caret spans the line it is on, which is as tall as its largest text.
+        let line_metrics = typesetter.line_metrics();
+        let caret_rects = typesetter.caret_rects();
+        for (caret_index, caret_rect) in caret_rects.iter().enumerate() {
+            let line = &line_metrics[if caret_index < 2 { 0 } else { 1 }];
+            assert_eq!(caret_rect.origin.y, line.baseline - line.ascent);
+            assert_eq!(caret_rect.size.height, line.ascent + line.descent);
+        }
+        assert!(caret_rects[2].size.height > caret_rects[0].size.height);
+    })
+}
+
+#[test]
+fn caret_rects_follow_aligned_empty_lines() {
+    with_test_font(|font| {
+        for &alignment in &[Alignment::Center, Alignment::Right] {
+            let mut typesetter = Typesetter::new(200.0, font, 16.0);
+            typesetter.set_alignment(alignment);
+            typesetter.add_text(font, 16.0, "ab\n");
+            let cursor_x = typesetter.cursor_position().x;
+            assert!(cursor_x > 0.0);
+
+            let caret_rects = typesetter.caret_rects();
+            let caret_rect = caret_rects.last().unwrap();
+            assert_eq!(caret_rect.origin.x + caret_rect.size.width * 0.5, cursor_x);
+        }
+    })
+}
+
+#[test]
+fn pen_snapping_rounds_glyph_positions() {
+    with_test_font(|font| {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let mut exact = Typesetter::new(100.0, font, 15.0);
+        exact.set_alignment(Alignment::Justify);
+        exact.add_text(font, 15.0, text);
+        assert!(exact.glyph_positions().iter().any(|position| position.x.fract() != 0.0));
+
+        let mut snapped = exact.clone();
+        snapped.set_pen_snapping(PenSnapping::Integer);
+        snapped.reflow();
+        assert!(snapped.glyph_positions().iter().all(|position| position.x.fract() == 0.0));
+
+        snapped.set_pen_snapping(PenSnapping::Subpixel(0.25));
+        snapped.reflow();
+        assert!(snapped.glyph_positions().iter().all(|position| (position.x * 4.0).fract() == 0.0));
+
+        // Rounding never accumulates, so no glyph moves by more than half a pixel.
+        snapped.set_alignment(Alignment::Left);
+        snapped.set_pen_snapping(PenSnapping::Integer);
+        snapped.reflow();
+        exact.set_alignment(Alignment::Left);
+        exact.reflow();
+        assert!(exact.glyph_positions().iter().zip(snapped.glyph_positions()).all(|(a, b)| {
+            (a.x - b.x).abs() <= 0.5 && a.y == b.y
+        }));
+    })
+}
+
+#[test]
+fn glyph_sources_map_back_to_text() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, " h\u{e9}llo\tw\u{f6}rld");
+        typesetter.add_text(font, 16.0, "x");
+
+        let source = |text_index, byte_range| Some(GlyphSource {
+            text_index: text_index,
+            byte_range: byte_range,
+        });
+        assert_eq!(typesetter.glyph_positions().len(), 11);
+        assert_eq!(typesetter.glyph_source(0), source(0, 1..2));
+        assert_eq!(typesetter.glyph_source(1), source(0, 2..4));
+        assert_eq!(typesetter.glyph_source(5), source(0, 8..9));
+        assert_eq!(typesetter.glyph_source(10), source(1, 0..1));
+        assert_eq!(typesetter.glyph_source(11), None);
+
+        // A combining mark shares the byte range of the grapheme cluster it is part of.
+        typesetter.add_text(font, 16.0, " e\u{301}x");
+        assert_eq!(typesetter.glyph_positions().len(), 14);
+        assert_eq!(typesetter.glyph_source(11), source(2, 1..4));
+        assert_eq!(typesetter.glyph_source(12), source(2, 1..4));
+        assert_eq!(typesetter.glyph_source(13), source(2, 4..5));
+
+        typesetter.reflow();
+        assert_eq!(typesetter.glyph_source(1), source(0, 2..4));
+        assert_eq!(typesetter.glyph_source(12), source(2, 1..4));
+    })
+}
+
+#[test]
+fn pop_last_run_rewinds_across_wraps() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        typesetter.set_alignment(Alignment::Justify);
+        typesetter.add_text(font, 16.0, "the quick ");
+        let before = typesetter.layout();
+
+        // This text wraps and justifies the line that it started on.
+        typesetter.add_text(font, 16.0, "brown fox jumps over the lazy dog");
+        assert!(typesetter.lines().count() > 1);
+        assert!(typesetter.pop_last_run());
+
+        let after = typesetter.layout();
+        assert_eq!(after.cursor, before.cursor);
+        assert_eq!(after.glyph_positions.len(), before.glyph_positions.len());
+        assert!(after.glyph_positions.iter().zip(before.glyph_positions.iter()).all(|(a, b)| {
+            a.position() == b.position()
+        }));
+        assert_eq!(typesetter.lines().count(), 1);
+
+        // Text added afterward is laid out as though the removed text had never been added.
+        typesetter.add_text(font, 16.0, "red fox");
+        let mut fresh = Typesetter::new(100.0, font, 16.0);
+        fresh.set_alignment(Alignment::Justify);
+        fresh.add_text(font, 16.0, "the quick ");
+        fresh.add_text(font, 16.0, "red fox");
+        assert_eq!(typesetter.glyph_positions().len(), fresh.glyph_positions().len());
+        assert!(typesetter.glyph_positions().iter().zip(fresh.glyph_positions()).all(|(a, b)| {
+            a.position() == b.position()
+        }));
+
+        typesetter.reflow();
+        assert!(typesetter.pop_last_run());
+        assert!(typesetter.pop_last_run());
+        assert!(!typesetter.pop_last_run());
+        assert!(typesetter.glyph_positions().is_empty());
+    })
+}
+
+#[test]
+fn margins_bound_lines() {
+    with_test_font(|font| {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let mut typesetter = Typesetter::new(120.0, font, 16.0);
+        typesetter.set_margins(10.0, 10.0);
+        typesetter.set_first_line_indent(5.0);
+        typesetter.add_text(font, 16.0, text);
+
+        // The margins narrow the page to the width of the text in an unindented 100-pixel page.
+        let mut unmargined = Typesetter::new(100.0, font, 16.0);
+        unmargined.set_first_line_indent(5.0);
+        unmargined.add_text(font, 16.0, text);
+        assert_eq!(typesetter.glyph_positions()[0].x, 15.0);
+        assert_eq!(typesetter.lines().count(), unmargined.lines().count());
+        assert!(typesetter.glyph_positions().iter().zip(unmargined.glyph_positions()).all(|(a, b)| {
+            a.x == b.x + 10.0 && a.y == b.y
+        }));
+
+        typesetter.set_alignment(Alignment::Right);
+        typesetter.reflow();
+        unmargined.set_alignment(Alignment::Right);
+        unmargined.reflow();
+        assert!(typesetter.lines().zip(unmargined.lines()).all(|(a, b)| {
+            (a.right - b.right - 10.0).abs() < 0.001
+        }));
+    })
+}
+
+#[test]
+fn styles_are_carried_to_glyph_positions() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(30.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "ab ");
+        typesetter.add_text_with_style(font, 16.0, "cd ef", 7);
+
+        let styles: Vec<u32> = typesetter.glyph_positions()
+                                         .iter()
+                                         .map(|position| position.style)
+                                         .collect();
+        assert_eq!(styles, [0, 0, 7, 7, 7, 7]);
+
+        typesetter.set_page_width(1000.0);
+        typesetter.reflow();
+        assert_eq!(typesetter.glyph_positions()[5].style, 7);
+    })
+}
+
+#[test]
+fn clipped_words_stop_at_the_margin() {
+    with_test_font(|font| {
+        let lay_out = |overflow_policy| {
+            let mut typesetter = TypesetterBuilder::new(30.0).overflow_policy(overflow_policy)
+                                                             .build(font, 16.0);
+            typesetter.add_text(font, 16.0, "a wwwwwwww b");
+            typesetter
+        };
+
+        let (allowed, clipped) = (lay_out(OverflowPolicy::Allow), lay_out(OverflowPolicy::Clip));
+        assert_eq!(allowed.glyph_positions().len(), 10);
+        assert!(allowed.line(1).unwrap().right > 30.0);
+
+        assert!(clipped.glyph_positions().len() < 10);
+        assert_eq!(clipped.line_count(), 3);
+        for line in clipped.lines() {
+            assert!(line.right <= 30.0);
+        }
+        assert_eq!(clipped.line(2).unwrap().glyph_positions.len(), 1);
+    })
+}
+
+#[test]
+fn top_left_corners_are_above_the_baseline() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "Top");
+        let position = typesetter.glyph_positions()[0];
+        let ascent = typesetter.line_metrics()[0].ascent;
+        assert_eq!(position.top_left(ascent), Point2D::new(position.x, 0.0));
+        assert_eq!(position.top_left(0.0), position.position());
+    })
+}
+
+#[test]
+fn glyph_scale_enlarges_words_on_the_same_baseline() {
+    with_test_font(|font| {
+        let mut plain_typesetter = Typesetter::new(1000.0, font, 16.0);
+        plain_typesetter.add_text(font, 16.0, "very");
+        let plain_width = plain_typesetter.measure_text(font, 16.0, "very");
+
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "so ");
+        typesetter.set_glyph_scale(2.0);
+        typesetter.add_text(font, 16.0, "very");
+        assert!((typesetter.measure_text(font, 16.0, "very") - plain_width * 2.0).abs() < 0.001);
+
+        let positions = typesetter.glyph_positions();
+        assert_eq!(positions.len(), 6);
+        assert_eq!((positions[0].scale, positions[2].scale), (1.0, 2.0));
+        assert_eq!(positions[2].y, positions[0].y);
+        assert_eq!(positions[2].glyph_id, plain_typesetter.glyph_positions()[0].glyph_id);
+        let scaled_width = typesetter.line(0).unwrap().right - positions[2].x;
+        assert!((scaled_width - plain_width * 2.0).abs() < 0.001);
+
+        let line_metrics = typesetter.line_metrics();
+        assert_eq!(line_metrics[0].ascent, plain_typesetter.line_metrics()[0].ascent * 2.0);
+    })
+}
+
+#[test]
+fn initial_metrics_match_the_initial_font() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        let pixels_per_unit = 16.0 / font.units_per_em() as f32;
+        assert_eq!(typesetter.initial_ascent(), font.ascender() as f32 * pixels_per_unit);
+        assert_eq!(typesetter.initial_descent(), -font.descender() as f32 * pixels_per_unit);
+        assert_eq!(typesetter.initial_line_gap(), font.line_gap() as f32 * pixels_per_unit);
+
+        typesetter.add_text(font, 16.0, "one\ntwo");
+        let metrics = typesetter.line_metrics();
+        assert_eq!(metrics[0].baseline, typesetter.initial_ascent());
+        assert!((metrics[1].baseline - metrics[0].baseline - typesetter.line_height()).abs() <
+                0.001);
+    })
+}
+
+#[test]
+fn line_metrics_follow_the_tallest_font() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "small ");
+        typesetter.add_text(font, 32.0, "large\nsmall");
+
+        let pixels_per_unit = 32.0 / font.units_per_em() as f32;
+        let metrics: Vec<LineMetrics> = typesetter.line_metrics();
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0].ascent, font.ascender() as f32 * pixels_per_unit);
+        assert_eq!(metrics[0].descent, -font.descender() as f32 * pixels_per_unit);
+        assert_eq!(metrics[0].line_gap, font.line_gap() as f32 * pixels_per_unit);
+        assert_eq!(metrics[0].baseline, typesetter.glyph_positions()[0].y);
+        assert!(metrics[0].right > metrics[0].left);
+        assert!(metrics[1].baseline > metrics[0].baseline);
+    })
+}
+
+#[test]
+fn content_height_reaches_the_bottom_of_the_last_line() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "the quick brown fox jumps over the lazy dog");
+        let metrics = typesetter.line_metrics();
+        assert!(metrics.len() > 2);
+        let last_line = metrics.last().unwrap();
+        assert_eq!(typesetter.content_height(), last_line.baseline + last_line.descent);
+
+        // A larger font on the last line deepens it.
+        typesetter.add_text(font, 32.0, " end");
+        let last_line = *typesetter.line_metrics().last().unwrap();
+        assert_eq!(last_line.descent, -font.descender() as f32 * 32.0 / font.units_per_em() as f32);
+        assert_eq!(typesetter.content_height(), last_line.baseline + last_line.descent);
+    })
+}
+
+#[test]
+fn soft_hyphens_show_only_at_line_breaks() {
+    with_test_font(|font| {
+        let text = "hyphen\u{ad}ation";
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, text);
+        assert_eq!(typesetter.glyph_positions().len(), 11);
+        assert_eq!(typesetter.lines().count(), 1);
+        let hyphenated_width = typesetter.measure_text(font, 16.0, "hyphen-");
+        let width = typesetter.measure_text(font, 16.0, text);
+
+        // Leave room for "hyphen-" but not the whole word.
+        typesetter.set_page_width((hyphenated_width + width) * 0.5);
+        typesetter.reflow();
+        assert_eq!(typesetter.lines().count(), 2);
+        let first_line = typesetter.line(0).unwrap();
+        assert_eq!(first_line.glyph_positions.len(), 7);
+        assert!(first_line.right <= typesetter.page_width());
+        assert_eq!(typesetter.glyph_source(6).unwrap().byte_range, 6..8);
+
+        typesetter.set_page_width(1000.0);
+        typesetter.reflow();
+        assert_eq!(typesetter.glyph_positions().len(), 11);
+    })
+}
+
+struct TestHyphenator;
+
+impl Hyphenator for TestHyphenator {
+    fn break_points(&self, word: &str) -> Vec<usize> {
+        word.match_indices("ation").map(|(byte_index, _)| byte_index).collect()
+    }
+}
+
+#[test]
+fn hyphenators_break_words_that_do_not_fit() {
+    with_test_font(|font| {
+        let text = "hyphenation";
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text_with_hyphenator(font, 16.0, text, &TestHyphenator);
+        assert_eq!(typesetter.glyph_positions().len(), 11);
+        assert_eq!(typesetter.lines().count(), 1);
+        let hyphenated_width = typesetter.measure_text(font, 16.0, "hyphen-");
+        let width = typesetter.measure_text(font, 16.0, text);
+
+        let mut typesetter = Typesetter::new((hyphenated_width + width) * 0.5, font, 16.0);
+        typesetter.add_text_with_hyphenator(font, 16.0, text, &TestHyphenator);
+        assert_eq!(typesetter.lines().count(), 2);
+        assert_eq!(typesetter.line(0).unwrap().glyph_positions.len(), 7);
+
+        // The break is remembered by `reflow()`.
+        typesetter.set_page_width(1000.0);
+        typesetter.reflow();
+        assert_eq!(typesetter.glyph_positions().len(), 11);
+        typesetter.set_page_width((hyphenated_width + width) * 0.5);
+        typesetter.reflow();
+        assert_eq!(typesetter.lines().count(), 2);
+    })
+}
+
+#[test]
+fn fixed_pitch_text_matches_shaped_text() {
+    with_test_font(|font| assert!(!font.is_fixed_pitch()));
+    with_font(MONOSPACE_TEST_FONT_PATH, |font| {
+        assert!(font.is_fixed_pitch());
+
+        let text = "fn main() { x += 1; }";
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, text);
+
+        let codepoint_ranges = [CodepointRange::new(' ' as u32, '~' as u32)];
+        let glyph_mapping = font.glyph_mapping_for_codepoint_ranges(&codepoint_ranges).unwrap();
+        let pixels_per_unit = 16.0 / font.units_per_em() as f32;
+        let mut x = 0.0;
+        let mut positions = typesetter.glyph_positions().iter();
+        for (glyph_pos, ch) in shaper::shape_text(font, &glyph_mapping, text).iter()
+                                                                            .zip(text.chars()) {
+            if ch != ' ' {
+                let position = positions.next().unwrap();
+                assert_eq!(position.glyph_id, glyph_pos.glyph_id);
+                assert_eq!(position.x, x);
+            }
+            x += glyph_pos.advance as f32 * pixels_per_unit;
+        }
+        assert!(positions.next().is_none());
+    })
+}
+
+#[test]
+fn relayout_from_keeps_earlier_lines() {
+    with_test_font(|font| {
+        let xs = |typesetter: &Typesetter| -> Vec<f32> {
+            typesetter.glyph_positions().iter().map(|position| position.x).collect()
+        };
+
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "first line\n");
+        typesetter.add_text(font, 16.0, "second line\n");
+        typesetter.add_text(font, 16.0, "third line");
+        let positions = xs(&typesetter);
+
+        // Nothing changed, so nothing moves.
+        typesetter.relayout_from(12);
+        assert_eq!(xs(&typesetter), positions);
+
+        let third_line_start = typesetter.line(2).unwrap().glyph_start;
+        typesetter.set_alignment(Alignment::Center);
+        typesetter.relayout_from(third_line_start + 2);
+        assert_eq!(&xs(&typesetter)[..third_line_start], &positions[..third_line_start]);
+
+        let mut centered_typesetter = Typesetter::new(1000.0, font, 16.0);
+        centered_typesetter.set_alignment(Alignment::Center);
+        centered_typesetter.add_text(font, 16.0, "third line");
+        assert_eq!(&xs(&typesetter)[third_line_start..], &xs(&centered_typesetter)[..]);
+        assert_eq!(typesetter.lines().count(), 3);
+
+        typesetter.relayout_from(0);
+        assert!(typesetter.glyph_positions()[0].x > positions[0]);
+    })
+}
+
+#[test]
+fn glyph_clusters_are_byte_offsets() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "a\u{e9} b");
+        typesetter.add_text(font, 16.0, "c");
+
+        let clusters: Vec<u32> = typesetter.glyph_positions()
+                                           .iter()
+                                           .map(|position| position.cluster)
+                                           .collect();
+        assert_eq!(clusters, [0, 1, 4, 0]);
+    })
+}
+
+#[test]
+fn try_add_text_lays_out_like_add_text() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "some text");
+        let mut fallible_typesetter = Typesetter::new(1000.0, font, 16.0);
+        assert_eq!(fallible_typesetter.try_add_text(font, 16.0, "some text"), Ok(()));
+
+        let xs = |typesetter: &Typesetter| -> Vec<f32> {
+            typesetter.glyph_positions().iter().map(|position| position.x).collect()
+        };
+        assert_eq!(xs(&fallible_typesetter), xs(&typesetter));
+    })
+}
+
+#[test]
+fn baseline_shift_moves_glyphs_but_not_the_cursor() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "x");
+        typesetter.set_baseline_shift(4.0);
+        typesetter.add_text(font, 10.0, "2");
+        typesetter.set_baseline_shift(-3.0);
+        typesetter.add_text(font, 10.0, "3");
+        typesetter.set_baseline_shift(0.0);
+        typesetter.add_text(font, 16.0, "y");
+
+        let positions = typesetter.glyph_positions();
+        assert_eq!(positions[1].y, positions[0].y - 4.0);
+        assert_eq!(positions[2].y, positions[0].y + 3.0);
+        assert_eq!(positions[3].y, positions[0].y);
+
+        // A large shift makes the line taller.
+        let mut shifted_typesetter = Typesetter::new(1000.0, font, 16.0);
+        shifted_typesetter.add_text(font, 16.0, "x");
+        shifted_typesetter.set_baseline_shift(40.0);
+        shifted_typesetter.add_text(font, 16.0, "2");
+        let shifted_positions = shifted_typesetter.glyph_positions();
+        assert!(shifted_positions[0].y > positions[0].y);
+        assert!(shifted_positions[1].y > 0.0);
+        assert!(shifted_typesetter.line_metrics()[0].ascent > typesetter.line_metrics()[0].ascent);
+    })
+}
+
+#[test]
+fn line_count_includes_blank_lines() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        assert_eq!(typesetter.line_count(), 1);
+        typesetter.add_text(font, 16.0, "a\n\nb\n");
+        assert_eq!(typesetter.line_count(), 4);
+        assert_eq!(typesetter.line_count(), typesetter.lines().count());
+    })
+}
+
+#[test]
+fn lines_past_the_page_height_start_new_pages() {
+    with_test_font(|font| {
+        let page_height = line_spacing(font, 16.0) * 2.5;
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_page_height(Some(page_height));
+        typesetter.add_text(font, 16.0, "a\nb\nc\nd\ne");
+
+        let positions = typesetter.glyph_positions();
+        let pages: Vec<u32> = positions.iter().map(|position| position.page).collect();
+        assert_eq!(pages, [0, 0, 1, 1, 2]);
+        assert_eq!(typesetter.page_count(), 3);
+        for position in positions {
+            let page_y = position.y - position.page as f32 * page_height;
+            assert!(page_y > 0.0 && page_y < page_height);
+        }
+        assert_eq!(positions[2].y - page_height, positions[0].y);
+        assert_eq!(positions[3].y - positions[2].y, positions[1].y - positions[0].y);
+    })
+}
+
+#[test]
+fn fonts_report_their_glyph_count() {
+    with_test_font(|font| assert_eq!(font.num_glyphs(), 685));
+    with_font(MONOSPACE_TEST_FONT_PATH, |font| assert_eq!(font.num_glyphs(), 3377));
+}
+
+#[test]
+fn overlapping_lines_hit_the_later_line_first() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_line_spacing(LineSpacing::Fixed(4.0));
+        typesetter.add_text(font, 16.0, "abcdef\nab");
+
+        let positions = typesetter.glyph_positions().to_vec();
+        let (first_line, second_line) = (typesetter.line(0).unwrap(), typesetter.line(1).unwrap());
+        assert!(second_line.baseline - first_line.baseline < 16.0);
+
+        // Between the baselines, both lines contain the point.
+        let y = (first_line.baseline + second_line.baseline) * 0.5;
+        let point = Point2D::new(positions[0].x + 1.0, y);
+        assert_eq!(typesetter.glyph_at_point(point), Some(6));
+        assert_eq!(typesetter.caret_index_at_point(point), 6);
+
+        // Past the end of the later line, the earlier line is hit.
+        let point = Point2D::new(positions[4].x + 1.0, y);
+        assert_eq!(typesetter.glyph_at_point(point), Some(4));
+    })
+}
+
+#[test]
+fn device_scale_scales_glyph_rects_but_not_layout() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "Hi");
+        assert!(typesetter.glyph_positions()[1].x > 0.0);
+
+        // Glyph positions stay in logical pixels; only the device rectangles scale.
+        let units_per_em = font.units_per_em();
+        for position in typesetter.glyph_positions() {
+            let bounds = font.glyph_bounds(position.glyph_id).unwrap();
+            let rect = position.device_rect(&bounds, units_per_em, 16.0, 1.0, 0.25);
+            let doubled_rect = position.device_rect(&bounds, units_per_em, 16.0, 2.0, 0.25);
+            assert_eq!(doubled_rect.size.width * doubled_rect.size.height,
+                       4.0 * rect.size.width * rect.size.height);
+            assert!((rect.origin.x - position.x).abs() <= 0.125);
+            assert!((doubled_rect.origin.x - position.x * 2.0).abs() <= 0.125);
+        }
+    })
+}
+
+#[test]
+fn wrapped_text_gets_an_underline_segment_per_line() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(120.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "plain ");
+        typesetter.add_text(font, 16.0, "underlined words that wrap");
+
+        let underline = font.underline_metrics(16.0);
+        assert!(underline.position < 0.0 && underline.thickness > 0.0);
+
+        let line_metrics = typesetter.line_metrics();
+        assert!(line_metrics.len() > 1);
+        let rects = typesetter.decoration_rects(1, &underline);
+        assert_eq!(rects.len(), line_metrics.len());
+        for (rect, line) in rects.iter().zip(line_metrics.iter()) {
+            assert_eq!(rect.origin.y, line.baseline - underline.position);
+            assert_eq!(rect.size.height, underline.thickness);
+        }
+
+        // The underline starts at the run's first glyph, not at the start of the line.
+        assert_eq!(rects[0].origin.x, typesetter.glyph_positions()[5].x);
+        assert_eq!(rects[1].origin.x, line_metrics[1].left);
+
+        let strikeout = font.strikeout_metrics(16.0);
+        assert!(strikeout.position > 0.0);
+        assert!(typesetter.decoration_rects(1, &strikeout)[0].origin.y < line_metrics[0].baseline);
+    })
+}
+
+#[test]
+fn underlines_cover_every_font_of_a_text() {
+    with_test_font(|font| {
+        with_font(MONOSPACE_TEST_FONT_PATH, |fallback_font| {
+            let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+            typesetter.add_text(font, 16.0, "plain ");
+            typesetter.add_text_with_fallback(&[font, fallback_font], 16.0, "a\u{2603}b c");
+            let text_index = typesetter.glyph_source(5).unwrap().text_index;
+            assert_eq!(text_index, 1);
+
+            let rects = typesetter.decoration_rects(text_index, &font.underline_metrics(16.0));
+            assert_eq!(rects.len(), 1);
+            let positions = typesetter.glyph_positions();
+            let last_glyph_right = positions[8].x + typesetter.glyph_advances()[8];
+            assert_eq!(rects[0].origin.x, positions[5].x);
+            assert!((rects[0].max_x() - last_glyph_right).abs() < 0.001);
+
+            // There is no text after it.
+            assert!(typesetter.decoration_rects(2, &font.underline_metrics(16.0)).is_empty());
+        })
+    })
+}
+
+#[test]
+fn streamed_glyphs_match_buffered_glyphs() {
+    with_test_font(|font| {
+        let text = ["The quick brown fox ", "jumps over the lazy dog ", "again and again."];
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        for piece in &text {
+            typesetter.add_text(font, 16.0, piece);
+        }
+
+        let mut streaming_typesetter = Typesetter::new(100.0, font, 16.0);
+        let mut streamed = vec![];
+        for piece in &text {
+            streaming_typesetter.add_text_streaming(font, 16.0, piece, |glyph_position| {
+                streamed.push(glyph_position)
+            });
+        }
+        // Only the glyphs on the current line are kept.
+        let current_line_glyph_count = streaming_typesetter.glyph_positions.len();
+        assert!(current_line_glyph_count > 0);
+        assert!(!streamed.is_empty());
+        assert_eq!(streamed.len() + current_line_glyph_count, typesetter.glyph_positions.len());
+
+        streaming_typesetter.finish_streaming(|glyph_position| streamed.push(glyph_position));
+        assert!(streaming_typesetter.glyph_positions.is_empty());
+        assert!(!streaming_typesetter.pop_last_run());
+        assert_eq!(streaming_typesetter.line_count(), typesetter.line_count());
+
+        let xs = |positions: &[GlyphPosition]| -> Vec<(u16, f32, f32)> {
+            positions.iter().map(|position| (position.glyph_id, position.x, position.y)).collect()
+        };
+        assert_eq!(xs(&streamed), xs(&typesetter.glyph_positions));
+    })
+}
+
+#[test]
+fn long_streamed_strings_keep_only_the_lines_in_progress() {
+    with_test_font(|font| {
+        let paragraph = "The quick brown fox jumps over the lazy dog. ".repeat(8);
+        let text = vec![&paragraph[..]; 6].join("\n");
+        let xs = |positions: &[GlyphPosition]| -> Vec<(u16, f32, f32)> {
+            positions.iter().map(|position| (position.glyph_id, position.x, position.y)).collect()
+        };
+
+        for &(strategy, drop_cap) in &[(LineBreakStrategy::Greedy, false),
+                                       (LineBreakStrategy::Greedy, true),
+                                       (LineBreakStrategy::OptimalFit, false)] {
+            let mut typesetter = Typesetter::new(150.0, font, 16.0);
+            typesetter.set_alignment(Alignment::Justify);
+            typesetter.set_line_break_strategy(strategy);
+            let mut streaming_typesetter = typesetter.clone();
+            if drop_cap {
+                typesetter.add_drop_cap(font, 40.0, "A", 3);
+                streaming_typesetter.add_drop_cap(font, 40.0, "A", 3);
+            }
+            typesetter.add_text(font, 16.0, &text);
+
+            let mut streamed = vec![];
+            streaming_typesetter.add_text_streaming(font, 16.0, &text, |glyph_position| {
+                streamed.push(glyph_position)
+            });
+            streaming_typesetter.finish_streaming(|glyph_position| streamed.push(glyph_position));
+            assert_eq!(xs(&streamed), xs(&typesetter.glyph_positions));
+
+            // The glyphs were streamed out while the string was laid out, so no more than a few
+            // lines' worth, or a paragraph's, were ever held at once.
+            let glyph_count = typesetter.glyph_positions.len();
+            let held_glyph_count = streaming_typesetter.glyph_positions.capacity();
+            if strategy == LineBreakStrategy::Greedy {
+                assert!(held_glyph_count * 8 < glyph_count);
+            } else {
+                assert!(held_glyph_count * 3 < glyph_count);
+            }
+        }
+    })
+}
+
+#[test]
+fn bidi_levels_reverse_right_to_left_runs() {
+    with_test_font(|font| {
+        let mut plain_typesetter = Typesetter::new(1000.0, font, 16.0);
+        plain_typesetter.add_text(font, 16.0, "ab cd");
+        plain_typesetter.add_text(font, 16.0, " ef");
+        let plain_positions = &plain_typesetter.glyph_positions;
+
+        // "cd" is embedded right-to-left text in a left-to-right paragraph.
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text_with_levels(font, 16.0, "ab cd", &[0, 0, 0, 1, 1]);
+        typesetter.add_text(font, 16.0, " ef");
+        let positions = &typesetter.glyph_positions;
+        for &glyph_index in &[0, 1, 4, 5] {
+            assert_eq!(positions[glyph_index].x, plain_positions[glyph_index].x);
+        }
+        assert!((positions[3].x - plain_positions[2].x).abs() < 0.001);
+        assert!(positions[2].x > positions[3].x);
+        assert!(positions[3].x > positions[1].x && positions[2].x < positions[4].x);
+
+        // "cd" is embedded left-to-right text in a right-to-left paragraph.
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_direction(Direction::RightToLeft);
+        typesetter.add_text_with_levels(font, 16.0, "ab cd", &[1, 1, 1, 2, 2]);
+        let positions = &typesetter.glyph_positions;
+        assert!(positions[0].x > positions[1].x);
+        assert!(positions[1].x > positions[3].x);
+        assert!(positions[2].x < positions[3].x);
+
+        // Levels must cover every byte.
+        let glyph_count = typesetter.glyph_positions.len();
+        assert_eq!(typesetter.try_add_text_with_levels(font, 16.0, " ef", &[1, 1]),
+                   Err(TypesetterError::LevelCountMismatch));
+        assert_eq!(typesetter.glyph_positions.len(), glyph_count);
+        assert_eq!(typesetter.try_add_text_with_levels(font, 16.0, " ef", &[1, 1, 1]), Ok(()));
+        assert_eq!(typesetter.glyph_positions.len(), glyph_count + 2);
+    })
+}
+
+#[test]
+fn line_boxes_fix_line_heights() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        typesetter.set_line_box(Some(LineBox {
+            ascent: 12.0,
+            descent: 4.0,
+        }));
+        typesetter.reflow();
+        typesetter.add_text(font, 16.0, "Some text that wraps");
+        typesetter.add_text(font, 32.0, " onto a few lines");
+
+        let line_metrics = typesetter.line_metrics();
+        assert!(line_metrics.len() > 2);
+        for (line_index, metrics) in line_metrics.iter().enumerate() {
+            assert_eq!(metrics.baseline, 12.0 + 16.0 * line_index as f32);
+            assert_eq!((metrics.ascent, metrics.descent, metrics.line_gap), (12.0, 4.0, 0.0));
+        }
+    })
+}
+
+#[test]
+fn right_to_left_brackets_are_mirrored() {
+    with_test_font(|font| {
+        let mut plain_typesetter = Typesetter::new(1000.0, font, 16.0);
+        plain_typesetter.add_text(font, 16.0, "(a) [b]");
+        let plain_glyph_ids: Vec<u16> = plain_typesetter.glyph_positions
+                                                        .iter()
+                                                        .map(|position| position.glyph_id)
+                                                        .collect();
+
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text_with_levels(font, 16.0, "(a) [b]", &[1, 1, 1, 0, 0, 0, 0]);
+        let glyph_ids: Vec<u16> = typesetter.glyph_positions
+                                            .iter()
+                                            .map(|position| position.glyph_id)
+                                            .collect();
+        assert_eq!(glyph_ids[0], plain_glyph_ids[2]);
+        assert_eq!(glyph_ids[1], plain_glyph_ids[1]);
+        assert_eq!(glyph_ids[2], plain_glyph_ids[0]);
+        assert_eq!(&glyph_ids[3..], &plain_glyph_ids[3..]);
+    })
+}
+
+#[test]
+fn translate_moves_glyphs_lines_and_cursor() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "Some text that wraps onto a few lines");
+        let positions = typesetter.glyph_positions.clone();
+        let line_metrics = typesetter.line_metrics();
+
+        typesetter.translate(Point2D::new(10.0, -5.0));
+        for (position, translated_position) in positions.iter()
+                                                        .zip(typesetter.glyph_positions.iter()) {
+            assert_eq!(translated_position.x, position.x + 10.0);
+            assert_eq!(translated_position.y, position.y - 5.0);
+        }
+        for (line, translated_line) in line_metrics.iter().zip(typesetter.line_metrics().iter()) {
+            assert_eq!(translated_line.baseline, line.baseline - 5.0);
+            assert_eq!(translated_line.left, line.left + 10.0);
+            assert_eq!(translated_line.right, line.right + 10.0);
+        }
+
+        // Text added afterward continues from the moved cursor.
+        typesetter.add_text(font, 16.0, ".");
+        let last_position = typesetter.glyph_positions.last().unwrap();
+        assert_eq!(last_position.y, positions.last().unwrap().y - 5.0);
+    })
+}
+
+#[test]
+fn origins_offset_the_layout_and_its_wrapping() {
+    with_test_font(|font| {
+        let text = "Some text that wraps onto a few lines";
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        typesetter.add_text(font, 16.0, text);
+        let origin = Point2D::new(200.0, 50.0);
+        let mut offset_typesetter = Typesetter::new_at(100.0, font, 16.0, origin);
+        offset_typesetter.add_text(font, 16.0, text);
+        assert_eq!(offset_typesetter.origin(), origin);
+
+        // Lines break at the same places, as the page moves with the origin.
+        assert!(typesetter.line_count() > 1);
+        assert_eq!(offset_typesetter.line_count(), typesetter.line_count());
+        let check_offset = |offset_typesetter: &Typesetter| {
+            let positions = typesetter.glyph_positions.iter();
+            for (position, offset_position) in positions.zip(&offset_typesetter.glyph_positions) {
+                assert_eq!(offset_position.x, position.x + 200.0);
+                assert_eq!(offset_position.y, position.y + 50.0);
+            }
+        };
+        check_offset(&offset_typesetter);
+
+        // Reflowing lays the text out from the origin again.
+        offset_typesetter.reflow();
+        check_offset(&offset_typesetter);
+    })
+}
+
+#[test]
+fn appended_blocks_stack_below_the_text() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "Heading\n");
+        let mut block = Typesetter::new(100.0, font, 16.0);
+        block.add_text(font, 16.0, "Some text that wraps onto a few lines");
+        let heading_glyph_count = typesetter.glyph_positions.len();
+        let heading_metrics = typesetter.line_metrics();
+
+        // The empty line after the heading is dropped, and the block keeps its own wrapping.
+        typesetter.append_block(&block, 10.0);
+        assert_eq!(typesetter.line_count(), 1 + block.line_count());
+        let heading_bottom = heading_metrics[0].baseline + heading_metrics[0].descent;
+        let block_top = block.line_metrics()[0].baseline - block.line_metrics()[0].ascent;
+        let dy = heading_bottom + 10.0 - block_top;
+        assert_eq!(typesetter.glyph_positions.len(),
+                   heading_glyph_count + block.glyph_positions.len());
+        let appended_positions = &typesetter.glyph_positions[heading_glyph_count..];
+        for (position, appended_position) in block.glyph_positions.iter().zip(appended_positions) {
+            assert_eq!(appended_position.x, position.x);
+            assert_eq!(appended_position.y, position.y + dy);
+        }
+
+        // Text added afterward continues from the end of the block, and the block stays put.
+        let positions = typesetter.glyph_positions.clone();
+        typesetter.add_text(font, 16.0, "!");
+        typesetter.reflow();
+        assert_eq!(typesetter.glyph_positions.len(), positions.len() + 1);
+        assert!(positions.iter().zip(&typesetter.glyph_positions).all(|(position, reflowed)| {
+            reflowed.position() == position.position()
+        }));
+        let last_position = typesetter.glyph_positions.last().unwrap();
+        assert_eq!(last_position.y, positions.last().unwrap().y);
+
+        // Only text added after the block can be removed.
+        assert!(typesetter.pop_last_run());
+        assert!(!typesetter.pop_last_run());
+        assert_eq!(typesetter.glyph_positions.len(), positions.len());
+    })
+}
+
+#[cfg(feature = "serde-serialization")]
+#[test]
+fn serialized_layouts_position_glyphs_like_the_typesetter() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(120.0, font, 16.0);
+        typesetter.set_page_height(Some(40.0));
+        typesetter.add_text_with_style(font, 16.0, "the quick brown fox ", 1);
+        typesetter.set_glyph_scale(1.5);
+        typesetter.add_text(font, 16.0, "jumps over the lazy dog");
+        let mut glyph_store = GlyphStore::new_on_cpu();
+        let glyph_ids: Vec<u16> = typesetter.glyph_positions
+                                            .iter()
+                                            .map(|glyph_position| glyph_position.glyph_id)
+                                            .collect();
+        glyph_store.add_glyph_ids(&glyph_ids, font).unwrap();
+
+        let json = ::serde_json::to_string(&typesetter.layout()).unwrap();
+        let layout: TypesetterLayout = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{:?}", layout), format!("{:?}", typesetter.layout()));
+
+        let rect = Rect::new(Point2D::zero(), Size2D::new(1000.0, 1000.0));
+        let positioned_glyphs =
+            typesetter.positioned_glyphs_in_rect(&rect, &glyph_store, 16.0, 2.0, 0.25);
+        assert!(positioned_glyphs.len() > 10);
+        assert_eq!(format!("{:?}",
+                           layout.positioned_glyphs_in_rect(&rect, &glyph_store, 16.0, 2.0, 0.25)),
+                   format!("{:?}", positioned_glyphs));
+    })
+}
+
+#[test]
+fn subpixel_buckets_wrap_at_whole_pixels() {
+    let glyph = |subpixel_x| {
+        PositionedGlyph {
+            bounds: Rect::zero(),
+            subpixel_x: subpixel_x,
+            glyph_index: 0,
+            style: 0,
+            scale: 1.0,
+        }
+    };
+    assert_eq!(glyph(0.0).subpixel_bucket(0.25), 0);
+    assert_eq!(glyph(0.25).subpixel_bucket(0.25), 1);
+    assert_eq!(glyph(0.7501).subpixel_bucket(0.25), 3);
+    assert_eq!(glyph(1.0).subpixel_bucket(0.25), 0);
+    assert_eq!(glyph(0.5).subpixel_bucket(1.0), 0);
+}
+
+#[test]
+fn glyph_vertices_are_transformed_and_bucketed() {
+    let positioned_glyphs = [
+        PositionedGlyph {
+            bounds: Rect::new(Point2D::new(10.5, 20.0), Size2D::new(8.0, 12.0)),
+            subpixel_x: 0.5,
+            glyph_index: 3,
+            style: 0,
+            scale: 1.0,
+        },
+        PositionedGlyph {
+            bounds: Rect::new(Point2D::new(30.0, 20.0), Size2D::new(8.0, 12.0)),
+            subpixel_x: 0.0,
+            glyph_index: 7,
+            style: 0,
+            scale: 1.0,
+        },
+    ];
+    let transform = Matrix2D::create_translation(100.0, -5.0);
+    let vertices = GlyphVertex::from_positioned_glyphs(&positioned_glyphs, 0.25, &transform);
+    assert_eq!(vertices, vec![
+        GlyphVertex { x: 110.5, y: 15.0, glyph_index: 3, subpixel_bucket: 2 },
+        GlyphVertex { x: 130.0, y: 15.0, glyph_index: 7, subpixel_bucket: 0 },
+    ]);
+
+    let mut buffer = [vertices[1]; 1];
+    assert_eq!(GlyphVertex::fill_from_positioned_glyphs(&positioned_glyphs,
+                                                        0.25,
+                                                        &transform,
+                                                        &mut buffer),
+               1);
+    assert_eq!(buffer[0], vertices[0]);
+}
+
+#[test]
+fn glyph_instances_group_glyphs_by_index_and_subpixel_offset() {
+    let glyph = |x: f32, subpixel_x: f32, glyph_index: u16| {
+        PositionedGlyph {
+            bounds: Rect::new(Point2D::new(x, 0.0), Size2D::new(8.0, 12.0)),
+            subpixel_x: subpixel_x,
+            glyph_index: glyph_index,
+            style: 0,
+            scale: 1.0,
+        }
+    };
+    let positioned_glyphs = [glyph(0.0, 0.5, 7), glyph(10.0, 0.0, 3), glyph(20.0, 0.0, 7),
+                             glyph(30.0, 0.5, 7)];
+
+    let groups = GlyphInstances::group(&positioned_glyphs, 0.25, false);
+    let summary: Vec<(u16, u32, Vec<f32>)> = groups.iter().map(|group| {
+        (group.glyph_index,
+         group.subpixel_bucket,
+         group.glyphs.iter().map(|glyph| glyph.bounds.origin.x).collect())
+    }).collect();
+    assert_eq!(summary, vec![(3, 0, vec![10.0]), (7, 0, vec![0.0, 20.0, 30.0])]);
+
+    let groups = GlyphInstances::group(&positioned_glyphs, 0.25, true);
+    let summary: Vec<(u16, u32, Vec<f32>)> = groups.iter().map(|group| {
+        (group.glyph_index,
+         group.subpixel_bucket,
+         group.glyphs.iter().map(|glyph| glyph.bounds.origin.x).collect())
+    }).collect();
+    assert_eq!(summary, vec![(3, 0, vec![10.0]), (7, 0, vec![20.0]), (7, 2, vec![0.0, 30.0])]);
+}
+
+#[test]
+fn estimated_atlas_area_sums_the_rounded_out_glyph_bounds() {
+    with_test_font(|font| {
+        let mut glyph_store = GlyphStore::new_on_cpu();
+        assert_eq!(glyph_store.estimated_atlas_area(16.0, 2.0), 0);
+
+        let glyph_ids: Vec<u16> = (1..40).collect();
+        glyph_store.add_glyph_ids(&glyph_ids, font).unwrap();
+
+        let mut expected_area = 0;
+        for &glyph_index in &glyph_store.all_glyph_indices {
+            let mut bounds = glyph_store.outlines.glyph_subpixel_bounds(glyph_index, 16.0);
+            bounds.scale(2.0);
+            let size = bounds.round_out().size();
+            expected_area += size.width as u64 * size.height as u64;
+        }
+        assert!(expected_area > 0);
+        assert_eq!(glyph_store.estimated_atlas_area(16.0, 2.0), expected_area);
+        assert!(glyph_store.estimated_atlas_area(16.0, 1.0) < expected_area);
+    })
+}
+
+#[test]
+fn layout_bounds_enclose_the_line_boxes() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        assert_eq!(typesetter.layout_bounds(), Rect::zero());
+
+        typesetter.add_text(font, 16.0, "Hello, jumpy world\n");
+        let line_metrics = typesetter.line_metrics();
+        assert_eq!(line_metrics.len(), 3);
+        let (first_line, last_line) = (&line_metrics[0], &line_metrics[1]);
+        let layout_bounds = typesetter.layout_bounds();
+        assert_eq!(layout_bounds.origin.y, first_line.baseline - first_line.ascent);
+        assert_eq!(layout_bounds.max_y(), last_line.baseline + last_line.descent);
+        assert_eq!(layout_bounds.origin.x, first_line.left.min(last_line.left));
+        assert_eq!(layout_bounds.max_x(), first_line.right.max(last_line.right));
+    })
+}
+
+#[test]
+fn ink_bounds_enclose_the_glyph_outlines() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        let mut glyph_store = GlyphStore::new_on_cpu();
+        assert_eq!(typesetter.ink_bounds(&glyph_store, 16.0), Rect::zero());
+
+        typesetter.add_text(font, 16.0, "Hello, jumpy world");
+        let glyph_ids: Vec<u16> = typesetter.glyph_positions
+                                            .iter()
+                                            .map(|glyph_position| glyph_position.glyph_id)
+                                            .collect();
+        glyph_store.add_glyph_ids(&glyph_ids, font).unwrap();
+
+        let (mut left, mut top) = (f32::INFINITY, f32::INFINITY);
+        let (mut right, mut bottom) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for glyph_position in &typesetter.glyph_positions {
+            let glyph_index = glyph_store.glyph_index(glyph_position.glyph_id).unwrap();
+            let bounds = glyph_store.outlines.glyph_subpixel_bounds(glyph_index, 16.0);
+            left = left.min(glyph_position.x + bounds.left);
+            right = right.max(glyph_position.x + bounds.right);
+            top = top.min(glyph_position.y - bounds.top);
+            bottom = bottom.max(glyph_position.y - bounds.bottom);
+        }
+        let ink_bounds = typesetter.ink_bounds(&glyph_store, 16.0);
+        assert!((ink_bounds.origin.x - left).abs() < 0.001);
+        assert!((ink_bounds.origin.y - top).abs() < 0.001);
+        assert!((ink_bounds.max_x() - right).abs() < 0.001);
+        assert!((ink_bounds.max_y() - bottom).abs() < 0.001);
+        assert!(typesetter.line_count() > 1);
+    })
+}
+
+#[test]
+fn glyphs_added_to_a_store_in_pieces_get_the_batch_indices() {
+    with_test_font(|font| {
+        let glyph_ids: Vec<u16> = (1..40).collect();
+        let mut batch_glyph_store = GlyphStore::new_on_cpu();
+        batch_glyph_store.add_glyph_ids(&glyph_ids, font).unwrap();
+
+        let mut glyph_store = GlyphStore::new_on_cpu();
+        glyph_store.add_glyph_ids(&glyph_ids[..10], font).unwrap();
+
+        // A failed addition adds none of its glyphs.
+        let mut bad_glyph_ids = glyph_ids[10..20].to_vec();
+        bad_glyph_ids.push(font.num_glyphs() + 1);
+        assert!(glyph_store.add_glyph_ids(&bad_glyph_ids, font).is_err());
+        assert_eq!(glyph_store.outlines.glyph_count(), 10);
+        assert_eq!(glyph_store.glyph_index(glyph_ids[10]), None);
+
+        glyph_store.add_glyph_ids(&glyph_ids[5..20], font).unwrap();
+        glyph_store.add_glyph_ids(&glyph_ids[20..], font).unwrap();
+        assert!(!glyph_store.outlines.is_uploaded());
+        assert_eq!(glyph_store.outlines.glyph_count(), glyph_ids.len());
+        assert_eq!(glyph_store.all_glyph_indices, batch_glyph_store.all_glyph_indices);
+        for &glyph_id in &glyph_ids {
+            let glyph_index = glyph_store.glyph_index(glyph_id);
+            assert_eq!(glyph_index, batch_glyph_store.glyph_index(glyph_id));
+            assert_eq!(glyph_store.glyph_id(glyph_index.unwrap()), Some(glyph_id));
+        }
+    })
+}
+
+#[test]
+fn glyph_stores_report_the_characters_they_miss() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "hello");
+        let glyph_ids: Vec<u16> = typesetter.glyph_positions()
+                                            .iter()
+                                            .map(|glyph_position| glyph_position.glyph_id)
+                                            .collect();
+        let mut glyph_store = GlyphStore::new_on_cpu();
+        glyph_store.add_glyph_ids(&glyph_ids, font).unwrap();
+
+        assert!(glyph_store.covers(font, "hole\thell"));
+        assert!(!glyph_store.covers(font, "world"));
+        assert_eq!(glyph_store.missing_chars(font, "hello world").unwrap(), vec!['d', 'r', 'w']);
+    })
+}
+
+#[test]
+fn shared_and_compacted_glyph_stores_keep_their_glyphs() {
+    with_test_font(|font| {
+        let glyph_ids: Vec<u16> = (1..20).collect();
+        let mut glyph_store = GlyphStore::new_on_cpu();
+        glyph_store.add_glyph_ids(&glyph_ids, font).unwrap();
+        let (index_3, index_5) = (glyph_store.glyph_index(3).unwrap(),
+                                  glyph_store.glyph_index(5).unwrap());
+
+        // Shared stores keep the source's glyph indices, and can't grow.
+        {
+            let mut shared_glyph_store = GlyphStore::from_shared_outlines(&glyph_store,
+                                                                          &[5, 3, 100]);
+            assert_eq!(shared_glyph_store.glyph_index(3), Some(index_3));
+            assert_eq!(shared_glyph_store.glyph_index(4), None);
+            assert_eq!(shared_glyph_store.glyph_index(100), None);
+            assert_eq!(shared_glyph_store.all_glyph_indices, vec![index_3, index_5]);
+            match shared_glyph_store.add_glyph_ids(&[7], font) {
+                Err(GlyphStoreCreationError::OutlinesShared) => {}
+                _ => panic!("Glyphs were added to shared outlines"),
+            }
+        }
+
+        let glyph_index_map = glyph_store.compact(&[5, 3, 100], font).unwrap();
+        assert_eq!(glyph_index_map.len(), glyph_ids.len());
+        assert_eq!(glyph_index_map[index_3 as usize], glyph_store.glyph_index(3));
+        assert_eq!(glyph_index_map[index_5 as usize], glyph_store.glyph_index(5));
+        assert_eq!(glyph_index_map.iter().filter(|glyph_index| glyph_index.is_some()).count(), 2);
+        assert_eq!(glyph_store.glyph_index(4), None);
+        assert_eq!(glyph_store.all_glyph_indices, vec![0, 1]);
+        assert_eq!(glyph_store.outlines.glyph_count(), 2);
+        assert!(!glyph_store.outlines.is_uploaded());
+    })
+}
+
+#[test]
+fn glyph_offsets_separate_glyph_origins_from_the_pen() {
+    with_test_font(|font| {
+        // The built-in shaper doesn't position marks, so horizontal glyphs sit on the pen.
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "ab");
+        assert!(typesetter.glyph_positions()
+                          .iter()
+                          .all(|position| position.x_offset == 0.0 && position.y_offset == 0.0));
+
+        // Vertical glyphs are offset to center them on the column, where the pen is.
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.set_writing_mode(WritingMode::VerticalRightToLeft);
+        typesetter.add_text(font, 16.0, "ab");
+        let positions = typesetter.glyph_positions();
+        assert!(positions.iter().all(|position| position.x_offset < 0.0));
+        assert_eq!(positions[0].x - positions[0].x_offset, positions[1].x - positions[1].x_offset);
+        assert_eq!(positions[0].x - positions[0].x_offset, typesetter.line_metrics()[0].baseline);
+    })
+}
+
+#[test]
+fn debug_output_summarizes_the_layout() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        typesetter.add_text(font, 16.0, "ab\ncd");
+
+        let summary = format!("{:?}", typesetter);
+        assert!(summary.starts_with("Typesetter { glyph_count: 4, line_count: 2, "), "{}", summary);
+        assert!(summary.contains("page_width: 1000"));
+        assert!(!summary.contains("glyph_positions"));
+        assert!(format!("{:#?}", typesetter).contains("glyph_positions"));
+    })
+}
+
+#[test]
+fn max_line_width_ignores_trailing_spaces() {
+    with_test_font(|font| {
+        let mut typesetter = Typesetter::new(1000.0, font, 16.0);
+        assert_eq!(typesetter.max_line_width(), 0.0);
+        typesetter.add_text(font, 16.0, "ab\nthe longest line   \ncd ");
+
+        let mut measurer = Typesetter::new(1000.0, font, 16.0);
+        let longest_width = measurer.measure_text(font, 16.0, "the longest line");
+        assert!((typesetter.max_line_width() - longest_width).abs() < 0.001);
+    })
+}
+
+#[test]
+fn trailing_spaces_dont_affect_alignment() {
+    with_test_font(|font| {
+        let mut right = Typesetter::new(1000.0, font, 16.0);
+        right.set_alignment(Alignment::Right);
+        right.add_text(font, 16.0, "hello ");
+        right.add_text(font, 16.0, "world \nnext line");
+        assert!(right.lines().all(|line| (line.right - 1000.0).abs() < 0.001));
+
+        let mut centered = Typesetter::new(1000.0, font, 16.0);
+        centered.set_alignment(Alignment::Center);
+        centered.add_text(font, 16.0, "hello world   ");
+        let line = centered.line(0).unwrap();
+        assert!((line.left - (1000.0 - line.right)).abs() < 0.001);
+
+        // Wrapped lines are flush with the margin too.
+        let mut wrapped = Typesetter::new(100.0, font, 16.0);
+        wrapped.set_alignment(Alignment::Right);
+        wrapped.add_text(font, 16.0, "the quick brown fox jumps over the lazy dog");
+        assert!(wrapped.lines().count() > 2);
+        assert!(wrapped.lines().all(|line| (line.right - 100.0).abs() < 0.001));
+    })
+}
+
+#[test]
+fn glyph_advances_match_glyph_metrics() {
+    with_test_font(|font| {
+        let glyph_ids: Vec<u16> = (0..font.num_glyphs()).collect();
+        let advances = font.advances_for_glyphs(&glyph_ids).unwrap();
+        assert_eq!(advances.len(), glyph_ids.len());
+        for (&glyph_id, &advance) in glyph_ids.iter().zip(advances.iter()) {
+            assert_eq!(advance, font.metrics_for_glyph(glyph_id).unwrap().advance_width);
+            assert_eq!(font.advance_for_glyph(glyph_id), Ok(advance));
+        }
+    })
+}
+
+#[test]
+fn shaped_runs_wrap_like_text() {
+    with_test_font(|font| {
+        let text = "the quick brown fox jumps over the lazy dog";
+        let mut typesetter = Typesetter::new(100.0, font, 16.0);
+        typesetter.set_kerning(false);
+        typesetter.add_text(font, 16.0, text);
+
+        let codepoint_ranges = [CodepointRange::new(' ' as u32, '~' as u32)];
+        let glyph_mapping = font.glyph_mapping_for_codepoint_ranges(&codepoint_ranges).unwrap();
+        let shaped_glyphs: Vec<ShapedGlyph> =
+            shaper::shape_text_with_kerning(font, &glyph_mapping, text, false)
+                .iter()
+                .map(|glyph| {
+                    ShapedGlyph {
+                        glyph_id: glyph.glyph_id,
+                        advance: glyph.advance as i32,
+                        x_offset: glyph.x_offset as i32,
+                        y_offset: glyph.y_offset as i32,
+                        cluster: glyph.cluster,
+                    }
+                })
+                .collect();
+        let mut shaped_typesetter = Typesetter::new(100.0, font, 16.0);
+        shaped_typesetter.add_shaped_run(font, 16.0, &shaped_glyphs);
+
+        let positions = |typesetter: &Typesetter| -> Vec<(u16, f32, f32, u32)> {
+            typesetter.glyph_positions()
+                      .iter()
+                      .map(|position| (position.glyph_id, position.x, position.y, position.cluster))
+                      .collect()
+        };
+        assert!(typesetter.line_count() > 2);
+        assert_eq!(shaped_typesetter.line_count(), typesetter.line_count());
+        assert_eq!(positions(&shaped_typesetter), positions(&typesetter));
+    })
+}
@@ -11,197 +11,6009 @@
 //! Simple text layout.
 //!
 //! Do not use this for international or high-quality text. This layout has all of the limitations
-//! of the shaper; additionally, it only does left-to-right text with a uniform page width and no
-//! control over line spacing. Use Cocoa's `NSLayoutManager`, Pango, etc. for real use.
-
-use charmap::CodepointRanges;
-use error::GlyphStoreCreationError;
-use euclid::{Point2D, Rect};
-use font::Font;
-use outline::{OutlineBuilder, Outlines};
+//! of the shaper; additionally, it only mixes directions given precomputed bidi levels, and only
+//! with a uniform page width. Use Cocoa's `NSLayoutManager`, Pango, etc. for real use.
+
+use charmap::{CodepointRanges, GlyphMapping};
+use error::{FontError, GlyphStoreCreationError, TypesetterError};
+use euclid::{Matrix2D, Point2D, Rect, Size2D};
+use font::{DecorationMetrics, Font};
+use mirror;
+use outline::{GlyphBounds, OutlineBuilder, Outlines};
 use shaper;
+use std::cmp;
+use std::collections::HashMap;
+use std::f32;
+use std::fmt;
+use std::iter::Enumerate;
+use std::mem;
+use std::ops::Range;
+use std::slice::Iter;
+use std::sync::Arc;
 use std::u16;
+use tables::vmtx::VerticalMetrics;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The character that replaces text dropped by `Typesetter::set_max_lines()`.
+const ELLIPSIS: char = '\u{2026}';
+
+/// An invisible character marking where a word may be hyphenated.
+const SOFT_HYPHEN: char = '\u{ad}';
+
+/// The character inserted where a line is broken at a soft hyphen.
+const HYPHEN: char = '-';
+
+/// The character that decimal tab stops line up. See `TabAlign::Decimal`.
+const DECIMAL_POINT: char = '.';
+
+/// How far each space may stretch when lines are broken with `LineBreakStrategy::OptimalFit`, as
+/// a fraction of its advance.
+const SPACE_STRETCH: f32 = 0.5;
+
+/// The badness of a line holding a word too long for it.
+const INFINITE_BADNESS: f32 = 10000.0;
+
+/// The extra badness of breaking a line other than at a space, for example at a hyphen.
+const HYPHEN_PENALTY: f32 = 50.0;
+
+/// The number of times `balanced_line_breaks()` halves the range of widths that it searches.
+const BALANCE_ITERATIONS: u32 = 16;
+
+/// The number of times `Typesetter::fit_point_size()` halves the range of point sizes that it
+/// searches.
+const FIT_ITERATIONS: u32 = 16;
+
+/// The width of the rectangles returned by `Typesetter::caret_rects()`, in pixels.
+const CARET_WIDTH: f32 = 1.0;
+
+/// The default width of a space in fonts that have no space glyph, in ems. See
+/// `Typesetter::set_fallback_space_width()`.
+pub const DEFAULT_FALLBACK_SPACE_WIDTH: f32 = 0.25;
+
+#[derive(Clone)]
+pub struct Typesetter {
+    pub glyph_positions: Vec<GlyphPosition>,
+    page_width: f32,
+    origin: Point2D<f32>,
+    left_margin: f32,
+    right_margin: f32,
+    cursor: Point2D<f32>,
+    writing_mode: WritingMode,
+    direction: Direction,
+    alignment: Option<Alignment>,
+    line_spacing: LineSpacing,
+    line_box: Option<LineBox>,
+    letter_spacing: f32,
+    word_spacing: f32,
+    justification_limits: (f32, f32),
+    tab_width: f32,
+    tab_stops: Vec<TabStop>,
+    fallback_space_width: f32,
+    baseline_shift: f32,
+    glyph_scale: f32,
+    kerning: bool,
+    first_line_indent: f32,
+    hanging_indent: f32,
+    max_lines: Option<usize>,
+    page_height: Option<f32>,
+    break_policy: BreakPolicy,
+    line_break_strategy: LineBreakStrategy,
+    whitespace_mode: WhitespaceMode,
+    pen_snapping: PenSnapping,
+    overflow_policy: OverflowPolicy,
+    notdef_policy: NotdefPolicy,
+    runs: Vec<Run>,
+    run_starts: Vec<RunStart>,
+    /// The number of runs that started on lines streamed out by `add_text_streaming()`, which
+    /// can no longer be removed or laid out again.
+    streamed_run_count: usize,
+    controls: Vec<Control>,
+    glyph_advances: Vec<f32>,
+    glyph_offsets: Vec<Point2D<f32>>,
+    glyph_byte_ranges: Vec<Range<usize>>,
+    glyph_levels: Vec<Option<u8>>,
+    lines: Vec<Line>,
+    words: Vec<Word>,
+    line_offset: f32,
+    /// The extra space, in pixels, that justification added between the words on the current
+    /// line, which `unalign_line()` takes out again.
+    line_stretch: f32,
+    /// True if the glyphs on the current line have been put in visual order by
+    /// `reorder_current_line()`.
+    line_reordered: bool,
+    trailing_space: f32,
+    /// The text after a right or decimal tab stop on the current line, if there is one.
+    tab_column: Option<TabColumn>,
+    line_limit_reached: bool,
+    truncated: bool,
+    ellipsis_word: Option<usize>,
+    drop_cap: Option<DropCap>,
+    /// The layout state at the start of the current paragraph, if it can be broken into lines
+    /// again by `break_paragraph_optimally()`.
+    paragraph_start: Option<Box<RunStart>>,
+    /// Whether the next word placed by `place_word()` must start a new line, as planned by
+    /// `break_paragraph_optimally()`.
+    planned_break: Option<bool>,
+    initial_ascent: f32,
+    initial_descent: f32,
+    initial_line_gap: f32,
+    initial_vertical_ascent: f32,
+    initial_vertical_descent: f32,
+    initial_vertical_line_gap: f32,
+    glyph_mapping_cache: Vec<CachedGlyphMapping>,
+    /// The characters recorded by `NotdefPolicy::Skip` and `NotdefPolicy::Report`, sorted.
+    missing_codepoints: Vec<char>,
+}
+
+impl Typesetter {
+    /// Creates a typesetter with default settings. Use `TypesetterBuilder` to configure one as it
+    /// is created.
+    ///
+    /// The initial font and point size determine the position of the first baseline.
+    #[inline]
+    pub fn new(page_width: f32, initial_font: &Font, initial_point_size: f32) -> Typesetter {
+        Typesetter::new_at(page_width, initial_font, initial_point_size, Point2D::zero())
+    }
+
+    /// Like `new()`, but lays text out as though the page started at `origin` rather than at the
+    /// top left corner, which is useful for laying out a second column or continuing from a known
+    /// pen position.
+    ///
+    /// The first baseline is one ascent below `origin.y`, and lines start and wrap between the
+    /// margins measured from `origin.x`, so the page spans `origin.x` to `origin.x + page_width`.
+    /// Pages, if a page height is set, are measured down from `origin.y`. Vertical text ignores
+    /// the origin, as it does the margins.
+    pub fn new_at(page_width: f32,
+                  initial_font: &Font,
+                  initial_point_size: f32,
+                  origin: Point2D<f32>)
+                  -> Typesetter {
+        let mut typesetter = Typesetter {
+            glyph_positions: vec![],
+            page_width: page_width,
+            origin: origin,
+            left_margin: 0.0,
+            right_margin: 0.0,
+            cursor: Point2D::zero(),
+            writing_mode: WritingMode::Horizontal,
+            direction: Direction::LeftToRight,
+            alignment: None,
+            line_spacing: LineSpacing::Multiple(1.0),
+            line_box: None,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+            justification_limits: (1.0, f32::INFINITY),
+            tab_width: 0.0,
+            tab_stops: vec![],
+            fallback_space_width: DEFAULT_FALLBACK_SPACE_WIDTH,
+            baseline_shift: 0.0,
+            glyph_scale: 1.0,
+            kerning: true,
+            first_line_indent: 0.0,
+            hanging_indent: 0.0,
+            max_lines: None,
+            page_height: None,
+            break_policy: BreakPolicy::Whitespace,
+            line_break_strategy: LineBreakStrategy::Greedy,
+            whitespace_mode: WhitespaceMode::Collapse,
+            pen_snapping: PenSnapping::None,
+            overflow_policy: OverflowPolicy::Allow,
+            notdef_policy: NotdefPolicy::Show,
+            runs: vec![],
+            run_starts: vec![],
+            streamed_run_count: 0,
+            controls: vec![],
+            glyph_advances: vec![],
+            glyph_offsets: vec![],
+            glyph_byte_ranges: vec![],
+            glyph_levels: vec![],
+            lines: vec![],
+            words: vec![],
+            line_offset: 0.0,
+            line_stretch: 0.0,
+            line_reordered: false,
+            trailing_space: 0.0,
+            tab_column: None,
+            line_limit_reached: false,
+            truncated: false,
+            ellipsis_word: None,
+            drop_cap: None,
+            paragraph_start: None,
+            planned_break: None,
+            initial_ascent: 0.0,
+            initial_descent: 0.0,
+            initial_line_gap: 0.0,
+            initial_vertical_ascent: 0.0,
+            initial_vertical_descent: 0.0,
+            initial_vertical_line_gap: 0.0,
+            glyph_mapping_cache: vec![],
+            missing_codepoints: vec![],
+        };
+        typesetter.reset_with(initial_font, initial_point_size);
+        typesetter
+    }
+
+    /// Removes all laid-out text and moves the cursor back to the start of the first line.
+    ///
+    /// Settings and cached glyph mappings are retained, as is the memory allocated for glyph
+    /// positions, so this is cheaper than creating a new typesetter.
+    pub fn clear(&mut self) {
+        self.runs.clear();
+        self.controls.clear();
+        self.glyph_advances.clear();
+        self.glyph_offsets.clear();
+        self.glyph_byte_ranges.clear();
+        self.glyph_levels.clear();
+        self.glyph_positions.clear();
+        self.missing_codepoints.clear();
+        self.restart_layout()
+    }
+
+    /// Moves everything laid out so far, along with the cursor, by the given offset in pixels.
+    ///
+    /// Line baselines and extents move too, so line metrics and hit testing stay consistent with
+    /// the glyphs. Margins and page boundaries don't move, so text added afterward that wraps
+    /// starts its lines at the margins as before, and `reflow()` lays the text out again from
+    /// its original position.
+    pub fn translate(&mut self, delta: Point2D<f32>) {
+        // Lines are stacked along the y axis in horizontal text and along the x axis in vertical
+        // text, and words run along the other axis.
+        let (line_delta, word_delta) = if self.writing_mode.is_vertical() {
+            (delta.x, delta.y)
+        } else {
+            (delta.y, delta.x)
+        };
+
+        for glyph_position in &mut self.glyph_positions {
+            glyph_position.x += delta.x;
+            glyph_position.y += delta.y;
+        }
+        for line in &mut self.lines {
+            line.baseline += line_delta;
+            line.start += word_delta
+        }
+        for word in &mut self.words {
+            word.left += word_delta;
+            word.right += word_delta;
+        }
+        self.cursor = self.cursor + delta;
+
+        // Keep the saved starts of runs in step, so that `pop_last_run()` puts things back where
+        // they now are.
+        for run_start in &mut self.run_starts {
+            run_start.translate(delta, line_delta, word_delta)
+        }
+        if let Some(ref mut paragraph_start) = self.paragraph_start {
+            paragraph_start.translate(delta, line_delta, word_delta)
+        }
+    }
+
+    /// Appends a copy of everything laid out by another typesetter below the text laid out so
+    /// far, separated from it by the given gap in pixels, so that paragraphs typeset separately,
+    /// for example with different page widths, can be composed into one document.
+    ///
+    /// The other typesetter's glyphs and lines keep their x positions and are moved down so that
+    /// the top of its first line is `gap` below the bottom of the last line here with anything on
+    /// it; an empty line that the cursor is on is discarded. Text added afterward continues from
+    /// the end of the appended block, with the settings of this typesetter. Like text on lines
+    /// streamed out by `add_text_streaming()`, neither the block nor the text before it can be
+    /// removed with `pop_last_run()` or laid out again. Both typesetters must lay out horizontal
+    /// text.
+    pub fn append_block(&mut self, other: &Typesetter, gap: f32) {
+        let current_line = *self.current_line();
+        let bottom = if current_line.glyph_start == self.glyph_positions.len() &&
+                current_line.word_start == self.words.len() {
+            self.lines.pop();
+            match self.lines.last() {
+                Some(line) => line.baseline + line.descent,
+                None => current_line.baseline - current_line.ascent,
+            }
+        } else {
+            current_line.baseline + current_line.descent
+        };
+        let other_top = other.lines[0].baseline - other.lines[0].ascent;
+        let dy = bottom + gap - other_top;
+        let page = self.lines.last().map_or(current_line.page, |line| line.page);
+
+        let (glyph_count, word_count) = (self.glyph_positions.len(), self.words.len());
+        let (line_count, run_count) = (self.lines.len(), self.runs.len());
+        self.glyph_positions.extend(other.glyph_positions.iter().map(|glyph_position| {
+            GlyphPosition {
+                y: glyph_position.y + dy,
+                page: glyph_position.page + page as u32,
+                ..*glyph_position
+            }
+        }));
+        self.glyph_advances.extend_from_slice(&other.glyph_advances);
+        self.glyph_offsets.extend_from_slice(&other.glyph_offsets);
+        self.glyph_byte_ranges.extend(other.glyph_byte_ranges.iter().cloned());
+        self.glyph_levels.extend_from_slice(&other.glyph_levels);
+        let text_count = self.runs.last().map_or(0, |run| run.text_index + 1);
+        self.runs.extend(other.runs.iter().map(|run| {
+            Run { text_index: run.text_index + text_count, ..*run }
+        }));
+        self.words.extend(other.words.iter().map(|word| {
+            Word {
+                glyph_start: word.glyph_start + glyph_count,
+                glyph_end: word.glyph_end + glyph_count,
+                run: word.run + run_count,
+                ..*word
+            }
+        }));
+        self.lines.extend(other.lines.iter().map(|line| {
+            Line {
+                glyph_start: line.glyph_start + glyph_count,
+                word_start: line.word_start + word_count,
+                baseline: line.baseline + dy,
+                page: line.page + page,
+                ..*line
+            }
+        }));
+        self.controls.extend(other.controls.iter().map(|control| {
+            Control {
+                word_index: control.word_index + word_count,
+                run: control.run + run_count,
+                ..*control
+            }
+        }));
+
+        // Carry on from where the other typesetter left off.
+        self.cursor = Point2D::new(other.cursor.x, other.cursor.y + dy);
+        self.line_offset = other.line_offset;
+        self.line_stretch = other.line_stretch;
+        self.line_reordered = other.line_reordered;
+        self.trailing_space = other.trailing_space;
+        self.tab_column = other.tab_column;
+        self.line_limit_reached = other.line_limit_reached;
+        self.truncated = self.truncated || other.truncated;
+        self.ellipsis_word = other.ellipsis_word.map(|word_index| word_index + word_count);
+        self.drop_cap = other.drop_cap.map(|drop_cap| {
+            DropCap {
+                word: drop_cap.word.map(|word_index| word_index + word_count),
+                line: drop_cap.line + line_count,
+                line_end: drop_cap.line_end + line_count,
+                ..drop_cap
+            }
+        });
+        self.paragraph_start = None;
+        self.planned_break = None;
+
+        // None of the runs so far can be rewound to, so they all get the state at the end of the
+        // block as their start, without the saved line that only rewinding needs.
+        let run_start = RunStart {
+            line_glyph_positions: vec![],
+            line_glyphs: vec![],
+            line_words: vec![],
+            paragraph_start: None,
+            ..self.run_start()
+        };
+        self.run_starts.clear();
+        self.run_starts.resize(self.runs.len(), run_start);
+        self.streamed_run_count = self.runs.len();
+    }
+
+    /// Discards all lines and moves the cursor back to the start of the first line.
+    fn restart_layout(&mut self) {
+        self.run_starts.clear();
+        self.streamed_run_count = 0;
+        self.words.clear();
+        self.lines.clear();
+        self.line_offset = 0.0;
+        self.line_stretch = 0.0;
+        self.line_reordered = false;
+        self.trailing_space = 0.0;
+        self.tab_column = None;
+        self.line_limit_reached = false;
+        self.truncated = false;
+        self.ellipsis_word = None;
+        self.drop_cap = None;
+        self.paragraph_start = None;
+
+        if self.writing_mode.is_vertical() {
+            // The first column is flush with x = 0, on the side that columns advance away from.
+            let center = self.writing_mode.column_sign() * self.initial_vertical_ascent;
+            self.lines.push(Line {
+                glyph_start: 0,
+                word_start: 0,
+                baseline: center,
+                ascent: self.initial_vertical_ascent,
+                descent: self.initial_vertical_descent,
+                line_gap: self.initial_vertical_line_gap,
+                starts_paragraph: true,
+                indent: 0.0,
+                start: 0.0,
+                direction: self.direction,
+                page: 0,
+            });
+            self.cursor = Point2D::new(center, 0.0);
+            return
+        }
+
+        let (ascent, descent, line_gap) = match self.line_box {
+            Some(line_box) => (line_box.ascent, line_box.descent, 0.0),
+            None => (self.initial_ascent, self.initial_descent, self.initial_line_gap),
+        };
+        self.lines.push(Line {
+            glyph_start: 0,
+            word_start: 0,
+            baseline: self.origin.y + ascent,
+            ascent: ascent,
+            descent: descent,
+            line_gap: line_gap,
+            starts_paragraph: true,
+            indent: self.first_line_indent,
+            start: 0.0,
+            direction: self.direction,
+            page: 0,
+        });
+        self.cursor.y = self.origin.y + ascent;
+        self.move_to_line_start();
+        self.start_paragraph()
+    }
+
+    /// Like `clear()`, but also changes the font and point size that determine the position of the
+    /// first baseline, as though they had been passed to `new()`.
+    pub fn reset_with(&mut self, initial_font: &Font, initial_point_size: f32) {
+        let pixels_per_unit = initial_point_size / initial_font.units_per_em() as f32;
+        self.initial_ascent = initial_font.ascender() as f32 * pixels_per_unit;
+        self.initial_descent = -initial_font.descender() as f32 * pixels_per_unit;
+        self.initial_line_gap = initial_font.line_gap() as f32 * pixels_per_unit;
+        self.initial_vertical_ascent = initial_font.vertical_ascender() as f32 * pixels_per_unit;
+        self.initial_vertical_descent = -initial_font.vertical_descender() as f32 *
+            pixels_per_unit;
+        self.initial_vertical_line_gap = initial_font.vertical_line_gap() as f32 *
+            pixels_per_unit;
+        self.clear()
+    }
+
+    /// Returns the ascent, in pixels, of the font and point size passed to `new()` or
+    /// `reset_with()`, which places the first baseline.
+    #[inline]
+    pub fn initial_ascent(&self) -> f32 {
+        self.initial_ascent
+    }
+
+    /// Returns the descent, in pixels, of the initial font, as a positive distance below the
+    /// baseline.
+    #[inline]
+    pub fn initial_descent(&self) -> f32 {
+        self.initial_descent
+    }
+
+    /// Returns the line gap, in pixels, of the initial font.
+    #[inline]
+    pub fn initial_line_gap(&self) -> f32 {
+        self.initial_line_gap
+    }
+
+    /// Returns the height of a line of text in the initial font: its ascent plus its descent plus
+    /// its line gap, in pixels.
+    ///
+    /// This is the spacing between baselines under the default line spacing. Neither this nor
+    /// the other initial metrics are affected by `set_line_spacing()` or `set_line_box()`.
+    #[inline]
+    pub fn line_height(&self) -> f32 {
+        self.initial_ascent + self.initial_descent + self.initial_line_gap
+    }
+
+    /// Sets the orientation in which subsequent calls to `add_text()` lay out lines.
+    ///
+    /// In the vertical modes, glyphs stack downward from y = 0 in columns, and text wraps to a new
+    /// column once a column would grow longer than the page width. Columns are spaced according
+    /// to the font's vertical metrics or the line spacing. The first column is flush with x = 0:
+    /// to its left for `WritingMode::VerticalRightToLeft` and to its right for
+    /// `WritingMode::VerticalLeftToRight`.
+    ///
+    /// Vertical text isn't kerned, and alignment, tabs, indents, ellipses, and hit testing apply
+    /// only to horizontal text.
+    ///
+    /// Text is shaped differently in each mode, so changing the writing mode discards all text
+    /// laid out so far, as `clear()` does.
+    pub fn set_writing_mode(&mut self, writing_mode: WritingMode) {
+        if writing_mode != self.writing_mode {
+            self.writing_mode = writing_mode;
+            self.clear()
+        }
+    }
+
+    #[inline]
+    pub fn writing_mode(&self) -> WritingMode {
+        self.writing_mode
+    }
+
+    /// Sets the direction in which subsequent calls to `add_text()` lay out glyphs.
+    ///
+    /// If the cursor is at the start of a line, it moves to the start of the line in the new
+    /// direction: the left margin for left-to-right text and the right margin for right-to-left
+    /// text, less any indent.
+    pub fn set_direction(&mut self, direction: Direction) {
+        let at_line_start = !self.writing_mode.is_vertical() &&
+            self.cursor.x == self.line_start();
+        self.direction = direction;
+        if at_line_start {
+            self.move_to_line_start()
+        }
+    }
+
+    #[inline]
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Sets how lines are aligned within the page.
+    ///
+    /// Alignment is applied to each line after it has been laid out, so it takes effect for the
+    /// current line and all lines after it.
+    #[inline]
+    pub fn set_alignment(&mut self, alignment: Alignment) {
+        self.alignment = Some(alignment)
+    }
+
+    /// Returns how lines are aligned within the page.
+    ///
+    /// Unless `set_alignment()` has been called, lines are flush with the edge at which they
+    /// start: `Alignment::Left` for left-to-right text and `Alignment::Right` for right-to-left
+    /// text.
+    #[inline]
+    pub fn alignment(&self) -> Alignment {
+        match (self.alignment, self.direction) {
+            (Some(alignment), _) => alignment,
+            (None, Direction::LeftToRight) => Alignment::Left,
+            (None, Direction::RightToLeft) => Alignment::Right,
+        }
+    }
+
+    /// Sets the distance between the baselines of successive lines.
+    ///
+    /// The default is `LineSpacing::Multiple(1.0)`, which spaces lines according to the metrics of
+    /// the font passed to `add_text()`.
+    ///
+    /// When fonts are mixed, each line is spaced according to the text that ends the line above
+    /// it, and moved further down if a font on either line ascends or descends further than that
+    /// text's font does.
+    #[inline]
+    pub fn set_line_spacing(&mut self, line_spacing: LineSpacing) {
+        self.line_spacing = line_spacing
+    }
+
+    #[inline]
+    pub fn line_spacing(&self) -> LineSpacing {
+        self.line_spacing
+    }
+
+    /// Overrides the ascent and descent of the fonts passed to `add_text()` with fixed values, as
+    /// CSS line boxes do, so that line heights don't depend on the fonts used.
+    ///
+    /// While a line box is set, text has the given ascent and descent and no line gap: these are
+    /// used in place of the font's metrics for `LineSpacing::Multiple`, for line metrics, and for
+    /// the position of the first baseline. The glyphs themselves are not clipped and may extend
+    /// past the line box. The default is `None`, which uses the font's metrics. Vertical text
+    /// ignores the line box. Text that has already been laid out is not affected until `reflow()`
+    /// is called.
+    #[inline]
+    pub fn set_line_box(&mut self, line_box: Option<LineBox>) {
+        self.line_box = line_box
+    }
+
+    #[inline]
+    pub fn line_box(&self) -> Option<LineBox> {
+        self.line_box
+    }
+
+    /// Sets the extra space, in pixels, inserted between adjacent glyphs within a word.
+    ///
+    /// Negative values move glyphs closer together. The default is zero.
+    #[inline]
+    pub fn set_letter_spacing(&mut self, letter_spacing: f32) {
+        self.letter_spacing = letter_spacing
+    }
+
+    #[inline]
+    pub fn letter_spacing(&self) -> f32 {
+        self.letter_spacing
+    }
+
+    /// Sets the extra space, in pixels, added to the advance of the space glyph between words.
+    ///
+    /// Negative values move words closer together. The default is zero.
+    #[inline]
+    pub fn set_word_spacing(&mut self, word_spacing: f32) {
+        self.word_spacing = word_spacing
+    }
+
+    #[inline]
+    pub fn word_spacing(&self) -> f32 {
+        self.word_spacing
+    }
+
+    /// Sets how far `Alignment::Justify` may shrink and stretch the spaces between words, as
+    /// multiples of their natural advances.
+    ///
+    /// A `min_space` below one lets a word stay on a line that it would otherwise overflow, as
+    /// long as squeezing the spaces on the line to no less than `min_space` times their advances
+    /// makes room for it; such a line is justified even if it ends its paragraph. A line whose
+    /// spaces would have to stretch past `max_space` times their advances to fill it is instead
+    /// left flush with the edge at which it starts, like the last line of a paragraph. The
+    /// default, `(1.0, f32::INFINITY)`, never shrinks spaces and stretches them as far as needed.
+    #[inline]
+    pub fn set_justification_limits(&mut self, min_space: f32, max_space: f32) {
+        self.justification_limits = (min_space, max_space)
+    }
+
+    /// Returns the smallest and largest multiples of their natural advances that justification
+    /// may shrink and stretch spaces to.
+    #[inline]
+    pub fn justification_limits(&self) -> (f32, f32) {
+        self.justification_limits
+    }
+
+    /// Sets the distance, in pixels, between tab stops.
+    ///
+    /// A tab moves the cursor to the next multiple of this distance from the start of the line
+    /// (the left margin in left-to-right text) instead of inserting a space. A tab width of zero,
+    /// the default, makes tabs separate words just as spaces do.
+    #[inline]
+    pub fn set_tab_width(&mut self, tab_width: f32) {
+        self.tab_width = tab_width
+    }
+
+    #[inline]
+    pub fn tab_width(&self) -> f32 {
+        self.tab_width
+    }
+
+    /// Sets the tab stops that tabs move to before those spaced by `set_tab_width()`.
+    ///
+    /// A tab moves to the first of these stops past the cursor, and to the next multiple of the
+    /// tab width once the cursor is past all of them. The text after a right or decimal stop
+    /// grows back toward the start of the line as it is laid out, until the next tab or line
+    /// break, so that it ends or has its decimal point at the stop; if it doesn't fit between
+    /// the tab and the stop, it starts at the tab. Unlike the tab width, tab stops apply to all
+    /// of the text, including text already added when it is laid out again by `reflow()`.
+    pub fn set_tab_stops(&mut self, tab_stops: &[TabStop]) {
+        self.tab_stops = tab_stops.to_vec();
+        self.tab_stops.sort_by(|a, b| {
+            a.position.partial_cmp(&b.position).unwrap_or(cmp::Ordering::Equal)
+        })
+    }
+
+    /// Returns the tab stops set by `set_tab_stops()`, in order of position.
+    #[inline]
+    pub fn tab_stops(&self) -> &[TabStop] {
+        &self.tab_stops
+    }
+
+    /// Sets the width of a space, in ems, for subsequent calls to `add_text()` with fonts that
+    /// don't map the space character to a glyph, such as icon fonts.
+    ///
+    /// The word spacing is added to it as usual. The default is `DEFAULT_FALLBACK_SPACE_WIDTH`, a
+    /// quarter of an em.
+    #[inline]
+    pub fn set_fallback_space_width(&mut self, fallback_space_width: f32) {
+        self.fallback_space_width = fallback_space_width
+    }
+
+    #[inline]
+    pub fn fallback_space_width(&self) -> f32 {
+        self.fallback_space_width
+    }
+
+    /// Sets the distance, in pixels, by which subsequent calls to `add_text()` raise their glyphs
+    /// above the baseline, for superscripts and subscripts.
+    ///
+    /// Positive values raise glyphs and negative values lower them. The cursor stays on the
+    /// baseline, so once the shift is set back to zero, the default, text lines up with the text
+    /// before the shifted glyphs. Lines grow to make room for shifted glyphs. To draw superscripts
+    /// smaller, pass a smaller point size to `add_text()` as well. Vertical text ignores the
+    /// shift.
+    #[inline]
+    pub fn set_baseline_shift(&mut self, baseline_shift: f32) {
+        self.baseline_shift = baseline_shift
+    }
+
+    #[inline]
+    pub fn baseline_shift(&self) -> f32 {
+        self.baseline_shift
+    }
+
+    /// Sets the factor by which subsequent calls to `add_text()` scale their glyphs, to enlarge
+    /// words for emphasis while keeping them on the same baseline.
+    ///
+    /// Unlike a larger point size, this keeps the glyphs shaped and rasterized at the point size
+    /// passed to `add_text()`: their advances, and the ascent, descent, and line gap of the font,
+    /// are multiplied by the scale, and the `scale` field of each glyph position asks the renderer
+    /// to draw the glyph scaled by the same factor. Lines grow to fit the most scaled text on
+    /// them. The default is 1.0.
+    #[inline]
+    pub fn set_glyph_scale(&mut self, glyph_scale: f32) {
+        self.glyph_scale = glyph_scale
+    }
+
+    #[inline]
+    pub fn glyph_scale(&self) -> f32 {
+        self.glyph_scale
+    }
+
+    /// Sets whether subsequent calls to `add_text()` apply the pair kerning in the font's `kern`
+    /// table.
+    ///
+    /// Kerning is on by default. Line breaking always uses the same advances as glyph placement, so
+    /// kerned words wrap correctly.
+    #[inline]
+    pub fn set_kerning(&mut self, kerning: bool) {
+        self.kerning = kerning
+    }
+
+    #[inline]
+    pub fn kerning(&self) -> bool {
+        self.kerning
+    }
+
+    /// Sets the distance, in pixels, from the start margin at which the first line of each
+    /// paragraph starts.
+    ///
+    /// Paragraphs begin at the start of the text and after each forced line break. The default is
+    /// zero.
+    pub fn set_first_line_indent(&mut self, first_line_indent: f32) {
+        self.first_line_indent = first_line_indent;
+        self.reindent_empty_line()
+    }
+
+    #[inline]
+    pub fn first_line_indent(&self) -> f32 {
+        self.first_line_indent
+    }
+
+    /// Sets the distance, in pixels, from the start margin at which lines that continue a wrapped
+    /// paragraph start.
+    ///
+    /// For a hanging indent, set this to a larger value than the first line indent. The default is
+    /// zero.
+    pub fn set_hanging_indent(&mut self, hanging_indent: f32) {
+        self.hanging_indent = hanging_indent;
+        self.reindent_empty_line()
+    }
+
+    #[inline]
+    pub fn hanging_indent(&self) -> f32 {
+        self.hanging_indent
+    }
+
+    /// Sets where subsequent calls to `add_text()` may break lines.
+    ///
+    /// The default is `BreakPolicy::Whitespace`.
+    #[inline]
+    pub fn set_break_policy(&mut self, break_policy: BreakPolicy) {
+        self.break_policy = break_policy
+    }
+
+    #[inline]
+    pub fn break_policy(&self) -> BreakPolicy {
+        self.break_policy
+    }
+
+    /// Sets how paragraphs are broken into lines.
+    ///
+    /// With `LineBreakStrategy::OptimalFit` or `LineBreakStrategy::Balanced`, each paragraph is
+    /// broken into lines again whenever it is finished, by a newline or by the end of a call to
+    /// `add_text()`, so the strategy in effect then applies to the whole paragraph. The default is
+    /// `LineBreakStrategy::Greedy`.
+    #[inline]
+    pub fn set_line_break_strategy(&mut self, line_break_strategy: LineBreakStrategy) {
+        self.line_break_strategy = line_break_strategy
+    }
+
+    #[inline]
+    pub fn line_break_strategy(&self) -> LineBreakStrategy {
+        self.line_break_strategy
+    }
+
+    /// Sets how subsequent calls to `add_text()` treat runs of spaces.
+    ///
+    /// The default is `WhitespaceMode::Collapse`. Together with newlines and tabs,
+    /// `WhitespaceMode::Preserve` lays out preformatted text as the CSS `white-space: pre` property
+    /// does, apart from wrapping.
+    #[inline]
+    pub fn set_whitespace_mode(&mut self, whitespace_mode: WhitespaceMode) {
+        self.whitespace_mode = whitespace_mode
+    }
+
+    #[inline]
+    pub fn whitespace_mode(&self) -> WhitespaceMode {
+        self.whitespace_mode
+    }
+
+    /// Sets whether the x position of each glyph is rounded as it is laid out.
+    ///
+    /// The pen itself still advances by the exact fractional advances, so rounding errors don't
+    /// accumulate along a line. Like alignment, this takes effect for the current line and all
+    /// lines after it, and for all text when `reflow()` is called. The default is
+    /// `PenSnapping::None`.
+    #[inline]
+    pub fn set_pen_snapping(&mut self, pen_snapping: PenSnapping) {
+        self.pen_snapping = pen_snapping
+    }
+
+    #[inline]
+    pub fn pen_snapping(&self) -> PenSnapping {
+        self.pen_snapping
+    }
+
+    /// Sets what happens to glyphs of a word too wide for a line of its own, for example in a
+    /// narrow column.
+    ///
+    /// With `OverflowPolicy::Clip`, the glyphs that would cross the end margin are dropped, so no
+    /// glyph extends past it. Like text dropped by `set_max_lines()`, they are not retained, so
+    /// they are not restored by `reflow()`. The default is `OverflowPolicy::Allow`. Vertical text
+    /// ignores the policy.
+    #[inline]
+    pub fn set_overflow_policy(&mut self, overflow_policy: OverflowPolicy) {
+        self.overflow_policy = overflow_policy
+    }
+
+    #[inline]
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    /// Sets what happens to characters that the font maps to its missing glyph, glyph 0, in
+    /// subsequent calls to `add_text()`.
+    ///
+    /// The default, `NotdefPolicy::Show`, lays out the missing glyph like any other, which usually
+    /// draws as an empty box. `NotdefPolicy::Skip` leaves it out, and both it and
+    /// `NotdefPolicy::Report` record the characters for `missing_codepoints()`, so that they can
+    /// be laid out in a fallback font.
+    #[inline]
+    pub fn set_notdef_policy(&mut self, notdef_policy: NotdefPolicy) {
+        self.notdef_policy = notdef_policy
+    }
+
+    #[inline]
+    pub fn notdef_policy(&self) -> NotdefPolicy {
+        self.notdef_policy
+    }
+
+    /// Returns the characters that fonts passed to `add_text()` had no glyphs for, sorted and with
+    /// duplicates removed, since the typesetter was created or last cleared.
+    ///
+    /// Characters are only recorded while the policy set by `set_notdef_policy()` is
+    /// `NotdefPolicy::Skip` or `NotdefPolicy::Report`.
+    #[inline]
+    pub fn missing_codepoints(&self) -> &[char] {
+        &self.missing_codepoints
+    }
+
+    /// Limits the number of lines that text is laid out on.
+    ///
+    /// Once the limit is reached, text that would start a new line is dropped, and the end of the
+    /// last line is replaced with an ellipsis ("…") that fits within the page width. Dropped text
+    /// is not retained, so it is not restored by `reflow()`. The default is `None`, for no limit.
+    #[inline]
+    pub fn set_max_lines(&mut self, max_lines: Option<usize>) {
+        self.max_lines = max_lines
+    }
+
+    #[inline]
+    pub fn max_lines(&self) -> Option<usize> {
+        self.max_lines
+    }
+
+    /// Splits the text into pages of the given height, in pixels.
+    ///
+    /// A line that would extend past the bottom of its page starts the next page instead, with its
+    /// baseline as far below the top of that page as its ascent, just as the first line is below
+    /// the top of the first one. Pages are stacked: page `n` covers the y positions from
+    /// `n * page_height` to `(n + 1) * page_height`, and the `page` field of each glyph position
+    /// says which page it is on, so a renderer can subtract `page * page_height` from its y
+    /// position to draw pages side by side or one at a time. A line taller than a page gets a page
+    /// of its own and overflows it.
+    ///
+    /// The default is `None`, for a single page of unlimited height. Vertical text ignores the
+    /// page height. Text that has already been laid out is not affected until `reflow()` is
+    /// called.
+    #[inline]
+    pub fn set_page_height(&mut self, page_height: Option<f32>) {
+        self.page_height = page_height
+    }
+
+    #[inline]
+    pub fn page_height(&self) -> Option<f32> {
+        self.page_height
+    }
+
+    /// Returns the number of pages that the text laid out so far occupies. This is never zero.
+    ///
+    /// See `set_page_height()`.
+    #[inline]
+    pub fn page_count(&self) -> usize {
+        self.current_line().page + 1
+    }
+
+    /// Returns true if text was dropped because it would have started a line past the limit set
+    /// with `set_max_lines()`.
+    #[inline]
+    pub fn was_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Applies the current indents to the current line if nothing has been placed on it yet.
+    fn reindent_empty_line(&mut self) {
+        if self.writing_mode.is_vertical() || self.cursor.x != self.line_start() {
+            return
+        }
+
+        let line_index = self.lines.len() - 1;
+        let indent = self.indent(self.current_line().starts_paragraph) +
+            self.drop_cap_indent(line_index);
+        self.current_line_mut().indent = indent;
+        self.move_to_line_start()
+    }
+
+    #[inline]
+    fn indent(&self, starts_paragraph: bool) -> f32 {
+        if starts_paragraph {
+            self.first_line_indent
+        } else {
+            self.hanging_indent
+        }
+    }
+
+    /// Returns the extra indent of the line with the given index that makes room for the drop
+    /// cap beside it, if any.
+    #[inline]
+    fn drop_cap_indent(&self, line_index: usize) -> f32 {
+        match self.drop_cap {
+            Some(drop_cap) if line_index >= drop_cap.line && line_index < drop_cap.line_end => {
+                drop_cap.advance
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Lays out the given string at the cursor, wrapping at word boundaries.
+    ///
+    /// Newlines (`\n` or `\r\n`) force a line break, and tabs advance to the next tab stop. See
+    /// `set_tab_width()`. Other runs of whitespace separate words as a single space unless the
+    /// whitespace mode preserves them. See `set_whitespace_mode()`.
+    ///
+    /// The glyphs get a style of zero. See `add_text_with_style()`.
+    ///
+    /// Panics if the font's character map or the metrics of its space glyph can't be read. Use
+    /// `try_add_text()` for fonts that may be malformed.
+    #[inline]
+    pub fn add_text(&mut self, font: &Font, point_size: f32, string: &str) {
+        self.add_text_with_style(font, point_size, string, 0)
+    }
+
+    /// Like `add_text()`, but tags each glyph with the given style.
+    ///
+    /// The typesetter doesn't interpret the style. It is carried through to the `style` fields of
+    /// `GlyphPosition` and `PositionedGlyph`, so that renderers can, for example, look up a color
+    /// for each glyph or batch glyphs by style.
+    #[inline]
+    pub fn add_text_with_style(&mut self, font: &Font, point_size: f32, string: &str, style: u32) {
+        self.try_add_text_with_style(font, point_size, string, style).unwrap()
+    }
+
+    /// Like `add_text()`, but returns an error instead of panicking if the font can't be used.
+    ///
+    /// If an error is returned, nothing is laid out and the typesetter is left as it was.
+    #[inline]
+    pub fn try_add_text(&mut self, font: &Font, point_size: f32, string: &str)
+                        -> Result<(), TypesetterError> {
+        self.try_add_text_with_style(font, point_size, string, 0)
+    }
+
+    /// Like `add_text_with_style()`, but returns an error instead of panicking if the font can't be
+    /// used. See `try_add_text()`.
+    #[inline]
+    pub fn try_add_text_with_style(&mut self,
+                                   font: &Font,
+                                   point_size: f32,
+                                   string: &str,
+                                   style: u32)
+                                   -> Result<(), TypesetterError> {
+        let options = RunOptions { style: style, ..RunOptions::default() };
+        self.try_add_run(font, point_size, string, options)
+    }
+
+    /// Like `add_text()`, but lets lines break within words at the points chosen by the given
+    /// hyphenator, for example one backed by the dictionaries of the `hyphenation` crate.
+    ///
+    /// The break points are treated as soft hyphens: a word is only broken at one when it doesn't
+    /// fit on the line, and a hyphen is then inserted at the end of the line. The breaks are
+    /// remembered, so `reflow()` can use them without the hyphenator.
+    #[inline]
+    pub fn add_text_with_hyphenator(&mut self,
+                                    font: &Font,
+                                    point_size: f32,
+                                    string: &str,
+                                    hyphenator: &dyn Hyphenator) {
+        self.try_add_text_with_hyphenator(font, point_size, string, hyphenator).unwrap()
+    }
+
+    /// Like `add_text_with_hyphenator()`, but returns an error instead of panicking if the font
+    /// can't be used. See `try_add_text()`.
+    #[inline]
+    pub fn try_add_text_with_hyphenator(&mut self,
+                                        font: &Font,
+                                        point_size: f32,
+                                        string: &str,
+                                        hyphenator: &dyn Hyphenator)
+                                        -> Result<(), TypesetterError> {
+        let options = RunOptions { hyphenator: Some(hyphenator), ..RunOptions::default() };
+        self.try_add_run(font, point_size, string, options)
+    }
+
+    /// Like `add_text()`, but shapes the text with the given OpenType features, such as small
+    /// capitals (`smcp`) or oldstyle figures (`onum`), each a tag from `shaper::feature_tag()`
+    /// and a value. See `shaper::shape_text_with_features()` for which features are supported.
+    ///
+    /// Turning the `kern` feature off disables kerning for this text, as `set_kerning()` does for
+    /// all text.
+    #[inline]
+    pub fn add_text_with_features(&mut self,
+                                  font: &Font,
+                                  point_size: f32,
+                                  string: &str,
+                                  features: &[(u32, u32)]) {
+        self.try_add_text_with_features(font, point_size, string, features).unwrap()
+    }
+
+    /// Like `add_text_with_features()`, but returns an error instead of panicking if the font
+    /// can't be used. See `try_add_text()`.
+    #[inline]
+    pub fn try_add_text_with_features(&mut self,
+                                      font: &Font,
+                                      point_size: f32,
+                                      string: &str,
+                                      features: &[(u32, u32)])
+                                      -> Result<(), TypesetterError> {
+        let options = RunOptions { features: features, ..RunOptions::default() };
+        self.try_add_run(font, point_size, string, options)
+    }
+
+    /// Like `add_text()`, but shapes the text for the given script and language, each a tag from
+    /// `shaper::feature_tag()`, such as `feature_tag(b"cyrl")` and `feature_tag(b"SRB ")` for
+    /// Serbian, so that glyphs take the forms that the font localizes for the language. See
+    /// `shaper::shape_text_with_language()`.
+    #[inline]
+    pub fn add_text_with_language(&mut self,
+                                  font: &Font,
+                                  point_size: f32,
+                                  string: &str,
+                                  script: u32,
+                                  language: u32) {
+        self.try_add_text_with_language(font, point_size, string, script, language).unwrap()
+    }
+
+    /// Like `add_text_with_language()`, but returns an error instead of panicking if the font
+    /// can't be used. See `try_add_text()`.
+    #[inline]
+    pub fn try_add_text_with_language(&mut self,
+                                      font: &Font,
+                                      point_size: f32,
+                                      string: &str,
+                                      script: u32,
+                                      language: u32)
+                                      -> Result<(), TypesetterError> {
+        let options = RunOptions { language: Some((script, language)), ..RunOptions::default() };
+        self.try_add_run(font, point_size, string, options)
+    }
+
+    /// Like `add_text()`, but replaces each shaped glyph that is a key of the given map with the
+    /// glyph it maps to, for example to suppress a ligature or force a stylistic alternate when
+    /// the shaper offers no way to toggle the OpenType feature.
+    ///
+    /// Substituted glyphs get their own advances from the font; kerning between them is that of
+    /// the glyphs they replace. A glyph whose metrics are missing keeps the advance of the glyph
+    /// it replaces.
+    #[inline]
+    pub fn add_text_with_substitutions(&mut self,
+                                       font: &Font,
+                                       point_size: f32,
+                                       string: &str,
+                                       substitutions: &HashMap<u16, u16>) {
+        self.try_add_text_with_substitutions(font, point_size, string, substitutions).unwrap()
+    }
+
+    /// Like `add_text_with_substitutions()`, but returns an error instead of panicking if the
+    /// font can't be used. See `try_add_text()`.
+    #[inline]
+    pub fn try_add_text_with_substitutions(&mut self,
+                                           font: &Font,
+                                           point_size: f32,
+                                           string: &str,
+                                           substitutions: &HashMap<u16, u16>)
+                                           -> Result<(), TypesetterError> {
+        let options = RunOptions { substitutions: Some(substitutions), ..RunOptions::default() };
+        self.try_add_run(font, point_size, string, options)
+    }
+
+    /// Like `add_text()`, but lays out each character in the first of the given fonts that has a
+    /// glyph for it, so that text in scripts or symbols missing from the first font can be mixed
+    /// with it.
+    ///
+    /// The string is split into runs by font, each shaped with its own font, and the runs share
+    /// baselines; each line is as tall as the tallest font on it. Grapheme clusters stay in the
+    /// font of their first character, and whitespace stays in the font of the text before it.
+    /// Characters that none of the fonts have a glyph for are laid out in the first font. A word
+    /// that changes fonts partway through may be broken where it does, as at a hyphen.
+    ///
+    /// Panics as `add_text()` does. Use `try_add_text_with_fallback()` for fonts that may be
+    /// malformed or a list of fonts that may be empty.
+    #[inline]
+    pub fn add_text_with_fallback(&mut self, fonts: &[&Font], point_size: f32, string: &str) {
+        self.try_add_text_with_fallback(fonts, point_size, string).unwrap()
+    }
+
+    /// Like `add_text_with_fallback()`, but returns an error instead of panicking if one of the
+    /// fonts can't be used, or `TypesetterError::NoFonts` if `fonts` is empty.
+    ///
+    /// If an error is returned, nothing is laid out and the typesetter is left as it was.
+    pub fn try_add_text_with_fallback(&mut self,
+                                      fonts: &[&Font],
+                                      point_size: f32,
+                                      string: &str)
+                                      -> Result<(), TypesetterError> {
+        if fonts.is_empty() {
+            return Err(TypesetterError::NoFonts)
+        }
+
+        let mut cache_indices = Vec::with_capacity(fonts.len());
+        for font in fonts {
+            cache_indices.push(try!(self.cache_glyph_mapping(font, string)))
+        }
+
+        // Split the string where the font changes, as the byte offset and font index of each run.
+        let mut font_runs: Vec<(usize, usize)> = vec![];
+        for (byte_start, cluster) in string.grapheme_indices(true) {
+            let ch = cluster.chars().next().unwrap();
+            let font_index = if ch.is_whitespace() {
+                font_runs.last().map_or(0, |&(_, font_index)| font_index)
+            } else {
+                cache_indices.iter().position(|&cache_index| {
+                    let glyph_mapping = &self.glyph_mapping_cache[cache_index].glyph_mapping;
+                    glyph_mapping.glyph_for(ch as u32).map_or(false, |glyph_id| glyph_id != 0)
+                }).unwrap_or(0)
+            };
+            match font_runs.last() {
+                Some(&(_, last_font_index)) if last_font_index == font_index => {}
+                _ => font_runs.push((byte_start, font_index)),
+            }
+        }
+
+        // Lay out the runs as one piece of text, so that an error leaves none of them behind.
+        let state = self.snapshot();
+        for (run_index, &(byte_start, font_index)) in font_runs.iter().enumerate() {
+            let byte_end = font_runs.get(run_index + 1)
+                                    .map_or(string.len(), |&(next_byte_start, _)| next_byte_start);
+            let joined = string[..byte_start].chars()
+                                             .next_back()
+                                             .map_or(false, |ch| !ch.is_whitespace());
+            let options = RunOptions {
+                joined: joined,
+                continues_text: run_index > 0,
+                byte_offset: byte_start,
+                ..RunOptions::default()
+            };
+            if let Err(error) = self.try_add_run(fonts[font_index],
+                                                 point_size,
+                                                 &string[byte_start..byte_end],
+                                                 options) {
+                self.restore(state);
+                return Err(error)
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `add_text()`, but places the characters of the string in visual order according to
+    /// the given bidi embedding levels, so that left-to-right and right-to-left text can be mixed
+    /// on a line.
+    ///
+    /// The typesetter doesn't implement the Unicode Bidirectional Algorithm itself; the levels
+    /// must be computed beforehand, for example with the `unicode-bidi` crate. `levels` holds one
+    /// level for each byte of `string`, as the `levels` field of `unicode_bidi::BidiInfo` does
+    /// (convert each with `Level::number()`). Only the level of the first byte of each character
+    /// is used. Even levels are left-to-right and odd levels right-to-left. Characters at odd
+    /// levels with a bidi mirroring counterpart, such as `(` and `)`, are drawn with the glyph of
+    /// that counterpart, so that brackets face the right way.
+    ///
+    /// Lines are broken in logical order, and then the glyphs on each line are reordered as in
+    /// rule L2 of the algorithm: from the highest level on the line down to the lowest odd level,
+    /// every sequence of glyphs at that level or higher is reversed. The paragraph direction is
+    /// the typesetter's direction, which should be set to match the paragraph embedding level
+    /// and not changed within the paragraph; text added without levels on the same line gets the
+    /// paragraph level. Hit testing and carets treat reordered glyphs as though they had not been
+    /// reversed.
+    ///
+    /// Panics if `levels` doesn't have one entry per byte of `string`, or as `add_text()` does.
+    /// Use `try_add_text_with_levels()` for levels or fonts that may be malformed.
+    #[inline]
+    pub fn add_text_with_levels(&mut self,
+                                font: &Font,
+                                point_size: f32,
+                                string: &str,
+                                levels: &[u8]) {
+        self.try_add_text_with_levels(font, point_size, string, levels).unwrap()
+    }
+
+    /// Like `add_text_with_levels()`, but returns an error instead of panicking if the font can't
+    /// be used, or `TypesetterError::LevelCountMismatch` if `levels` doesn't have one entry per
+    /// byte of `string`.
+    ///
+    /// If an error is returned, nothing is laid out and the typesetter is left as it was.
+    pub fn try_add_text_with_levels(&mut self,
+                                    font: &Font,
+                                    point_size: f32,
+                                    string: &str,
+                                    levels: &[u8])
+                                    -> Result<(), TypesetterError> {
+        if levels.len() != string.len() {
+            return Err(TypesetterError::LevelCountMismatch)
+        }
+        let options = RunOptions { levels: levels, ..RunOptions::default() };
+        self.try_add_run(font, point_size, string, options)
+    }
+
+    /// Lays out the given string as a new run, giving its glyphs the levels of their characters
+    /// in `options.levels` if it isn't empty and breaking words where the hyphenator allows.
+    #[inline]
+    fn try_add_run(&mut self, font: &Font, point_size: f32, string: &str, options: RunOptions)
+                   -> Result<(), TypesetterError> {
+        self.lay_out_run(font, point_size, string, options, None)
+    }
+
+    /// Like `try_add_run()`, but if `stream` is given, passes the glyphs of each line to it as
+    /// soon as the line is finished, as described in `add_text_streaming()`.
+    ///
+    /// Errors are only returned before anything is laid out.
+    fn lay_out_run(&mut self,
+                   font: &Font,
+                   point_size: f32,
+                   string: &str,
+                   options: RunOptions,
+                   mut stream: Option<&mut FnMut(GlyphPosition)>)
+                   -> Result<(), TypesetterError> {
+        let levels = options.levels;
+        // Right-to-left characters are shaped as their mirrored counterparts, so that brackets
+        // face the right way once the line is reordered. Line breaking still uses the original
+        // characters.
+        let mirrored_string;
+        let shaped_string = if levels.is_empty() {
+            string
+        } else {
+            mirrored_string = mirror::mirror_right_to_left_chars(string, levels);
+            &mirrored_string
+        };
+
+        let cache_index = try!(self.cache_glyph_mapping(font, shaped_string));
+        let run_index = try!(self.start_run(font, point_size, cache_index, &options));
+        let pixels_per_unit = self.pixels_per_unit(font, point_size);
+
+        // Hard line breaks (`\n` or `\r\n`) always start a new line, even if the current one is
+        // empty.
+        let mut word_glyphs = vec![];
+        for (line_index, line) in string.split('\n').enumerate() {
+            if line_index > 0 {
+                self.add_hard_break(run_index);
+                if let Some(ref mut callback) = stream {
+                    self.stream_finished_lines(&mut **callback)
+                }
+            }
+
+            let line = line.trim_right_matches('\r');
+            for (segment_index, segment) in line.split('\t').enumerate() {
+                if segment_index > 0 {
+                    self.add_tab(run_index);
+                }
+
+                let (mut gap_start, mut after_word) = (0, false);
+                for word in segment.split_whitespace() {
+                    let word_start = word.as_ptr() as usize - segment.as_ptr() as usize;
+                    self.add_preserved_spaces(&segment[gap_start..word_start],
+                                              after_word,
+                                              run_index);
+                    gap_start = word_start + word.len();
+                    after_word = true;
+
+                    let word_byte_start = word.as_ptr() as usize - string.as_ptr() as usize;
+                    let shaped_word = &shaped_string[word_byte_start..(word_byte_start +
+                                                                       word.len())];
+                    self.shape_word(font,
+                                    cache_index,
+                                    pixels_per_unit,
+                                    shaped_word,
+                                    &options,
+                                    &mut word_glyphs);
+                    for glyph in &mut word_glyphs {
+                        glyph.byte_start += word_byte_start;
+                        glyph.byte_end += word_byte_start;
+                        glyph.level = levels.get(glyph.byte_start).cloned();
+                    }
+                    if self.notdef_policy != NotdefPolicy::Show {
+                        self.record_missing_codepoints(string, &word_glyphs)
+                    }
+                    for glyph in &mut word_glyphs {
+                        glyph.byte_start += options.byte_offset;
+                        glyph.byte_end += options.byte_offset;
+                    }
+
+                    // The shaper emits one glyph per character, so break opportunities can be
+                    // found in the string.
+                    let hyphenation_points = match options.hyphenator {
+                        Some(hyphenator) => hyphenator.break_points(word),
+                        None => vec![],
+                    };
+                    let mut pieces = word_pieces(word, self.break_policy, &hyphenation_points);
+                    if options.joined && word_byte_start == 0 {
+                        pieces[0].joint = Joint::Direct
+                    }
+                    for (piece_index, piece) in pieces.iter().enumerate() {
+                        let hyphen_follows = pieces.get(piece_index + 1).map_or(false, |piece| {
+                            piece.joint == Joint::SoftHyphen
+                        });
+                        let piece_glyphs = &word_glyphs[piece.glyph_range.clone()];
+                        let found_glyphs: Vec<WordGlyph>;
+                        let piece_glyphs = if self.notdef_policy == NotdefPolicy::Skip &&
+                                piece_glyphs.iter().any(|glyph| glyph.glyph_id == 0) {
+                            found_glyphs = piece_glyphs.iter()
+                                                       .filter(|glyph| glyph.glyph_id != 0)
+                                                       .cloned()
+                                                       .collect();
+                            if found_glyphs.is_empty() {
+                                continue
+                            }
+                            &found_glyphs[..]
+                        } else {
+                            piece_glyphs
+                        };
+                        self.place_word(piece_glyphs, run_index, piece.joint, hyphen_follows);
+                    }
+                    if let Some(ref mut callback) = stream {
+                        self.stream_finished_lines(&mut **callback)
+                    }
+                }
+                self.add_preserved_spaces(&segment[gap_start..], after_word, run_index);
+            }
+        }
+
+        // Align the current line as though it were the last one in the paragraph. If more text is
+        // added to it later, it will be unaligned and realigned then.
+        self.break_paragraph_optimally();
+        self.align_line(true);
+        Ok(())
+    }
+
+    /// Lays out the given string, usually the first letter of a paragraph, as a drop cap: glyphs
+    /// at a larger point size that sink into the text below, so that they span the given number
+    /// of lines.
+    ///
+    /// The drop cap starts a new paragraph unless the cursor is at the start of a line. Its
+    /// baseline is that of the last line it spans, and it doesn't make the first line any taller.
+    /// Those lines are indented past its advance, so text added afterward starts right after it,
+    /// and later lines return to the usual indent. Leading and trailing whitespace in the string
+    /// is ignored. In the vertical writing modes, the drop cap is laid out as an ordinary word.
+    ///
+    /// The point size is up to the caller; a drop cap spanning `n` lines usually looks best when
+    /// the height of its capital letters is a little under `n - 1` line spacings plus the cap
+    /// height of the body text. `add_text_streaming()` holds back the lines that the drop cap
+    /// spans until the last of them is finished.
+    ///
+    /// Panics as `add_text()` does. Use `try_add_drop_cap()` for fonts that may be malformed.
+    #[inline]
+    pub fn add_drop_cap(&mut self, font: &Font, point_size: f32, string: &str, lines: usize) {
+        self.try_add_drop_cap(font, point_size, string, lines).unwrap()
+    }
+
+    /// Like `add_drop_cap()`, but returns an error instead of panicking if the font can't be
+    /// used.
+    ///
+    /// If an error is returned, nothing is laid out and the typesetter is left as it was.
+    pub fn try_add_drop_cap(&mut self, font: &Font, point_size: f32, string: &str, lines: usize)
+                            -> Result<(), TypesetterError> {
+        let cache_index = try!(self.cache_glyph_mapping(font, string));
+        let run_index = try!(self.start_run(font, point_size, cache_index, &RunOptions::default()));
+        self.runs[run_index].drop_cap_lines = cmp::max(lines, 1);
+        let pixels_per_unit = self.pixels_per_unit(font, point_size);
+
+        let word = string.trim();
+        let mut word_glyphs = vec![];
+        self.shape_word(font,
+                        cache_index,
+                        pixels_per_unit,
+                        word,
+                        &RunOptions::default(),
+                        &mut word_glyphs);
+        let word_byte_start = word.as_ptr() as usize - string.as_ptr() as usize;
+        for glyph in &mut word_glyphs {
+            glyph.byte_start += word_byte_start;
+            glyph.byte_end += word_byte_start;
+        }
+        if !word_glyphs.is_empty() {
+            self.place_drop_cap(&word_glyphs, run_index)
+        }
+
+        self.align_line(true);
+        Ok(())
+    }
+
+    /// Lays out glyphs that have already been shaped, for example by HarfBuzz, at the cursor,
+    /// wrapping at word boundaries as `add_text()` does.
+    ///
+    /// The glyphs must be in logical order. Lines are broken only at the font's space glyph:
+    /// each run of space glyphs separates two words and is replaced by a single space, as
+    /// whitespace in `add_text()` is. Other glyphs keep the advances and offsets that they were
+    /// shaped with, so kerning is left to the shaper. In the vertical writing modes, the glyphs
+    /// get their vertical metrics from the font instead. Each glyph's byte range, as reported by
+    /// `glyph_source()`, runs from its cluster to the next, and the range of the last cluster is
+    /// empty because the length of the text isn't known.
+    ///
+    /// Panics as `add_text()` does. Use `try_add_shaped_run()` for fonts that may be malformed.
+    #[inline]
+    pub fn add_shaped_run(&mut self, font: &Font, point_size: f32, glyphs: &[ShapedGlyph]) {
+        self.try_add_shaped_run(font, point_size, glyphs).unwrap()
+    }
+
+    /// Like `add_shaped_run()`, but returns an error instead of panicking if the font can't be
+    /// used.
+    ///
+    /// If an error is returned, nothing is laid out and the typesetter is left as it was.
+    pub fn try_add_shaped_run(&mut self, font: &Font, point_size: f32, glyphs: &[ShapedGlyph])
+                              -> Result<(), TypesetterError> {
+        let cache_index = try!(self.cache_glyph_mapping(font, ""));
+        let run_index = try!(self.start_run(font, point_size, cache_index, &RunOptions::default()));
+        let pixels_per_unit = self.pixels_per_unit(font, point_size);
+        let space_glyph_id = self.glyph_mapping_cache[cache_index].glyph_mapping
+                                 .glyph_for(' ' as u32)
+                                 .unwrap_or(0);
+
+        let mut word_glyphs = vec![];
+        for (glyph_index, glyph) in glyphs.iter().enumerate() {
+            if glyph.glyph_id == space_glyph_id && space_glyph_id != 0 {
+                self.place_shaped_word(font, pixels_per_unit, &mut word_glyphs, run_index);
+                continue
+            }
+
+            let byte_start = glyph.cluster as usize;
+            let byte_end = glyphs[(glyph_index + 1)..]
+                .iter()
+                .map(|next_glyph| next_glyph.cluster as usize)
+                .find(|&next_cluster| next_cluster != byte_start)
+                .unwrap_or(byte_start);
+            word_glyphs.push(WordGlyph {
+                glyph_id: glyph.glyph_id,
+                advance: glyph.advance as f32 * pixels_per_unit,
+                offset: Point2D::new(glyph.x_offset as f32 * pixels_per_unit,
+                                     -glyph.y_offset as f32 * pixels_per_unit),
+                byte_start: byte_start,
+                byte_end: byte_end,
+                level: None,
+            })
+        }
+        self.place_shaped_word(font, pixels_per_unit, &mut word_glyphs, run_index);
+
+        self.break_paragraph_optimally();
+        self.align_line(true);
+        Ok(())
+    }
+
+    /// Places the pre-shaped glyphs collected so far as a word, if there are any, and clears them.
+    fn place_shaped_word(&mut self,
+                         font: &Font,
+                         pixels_per_unit: f32,
+                         word_glyphs: &mut Vec<WordGlyph>,
+                         run_index: usize) {
+        if word_glyphs.is_empty() {
+            return
+        }
+        if self.writing_mode.is_vertical() {
+            apply_vertical_metrics(font, pixels_per_unit, word_glyphs)
+        }
+        self.place_word(word_glyphs, run_index, Joint::Space, false);
+        word_glyphs.clear()
+    }
+
+    /// Starts a new run at the cursor for text in the given font, returning its index.
+    ///
+    /// The current line is left unaligned.
+    fn start_run(&mut self,
+                 font: &Font,
+                 point_size: f32,
+                 cache_index: usize,
+                 options: &RunOptions)
+                 -> Result<usize, TypesetterError> {
+        // All of these values are in pixels.
+        let pixels_per_unit = self.pixels_per_unit(font, point_size);
+        let space_advance = try!(self.cached_space_advance(font, cache_index, pixels_per_unit));
+
+        // The current line may grow, so move it back to where it was laid out. It will be
+        // realigned once we're done.
+        self.unalign_line();
+        let run_index = self.runs.len();
+        self.record_run_starts(run_index + 1);
+
+        let ellipsis_glyph_id = self.glyph_mapping_cache[cache_index].glyph_mapping
+                                    .glyph_for(ELLIPSIS as u32);
+        let ellipsis = match ellipsis_glyph_id {
+            Some(glyph_id) if glyph_id != 0 && !self.writing_mode.is_vertical() => {
+                font.metrics_for_glyph(glyph_id).ok().map(|metrics| {
+                    WordGlyph {
+                        glyph_id: glyph_id,
+                        advance: metrics.advance_width as f32 * pixels_per_unit,
+                        offset: Point2D::zero(),
+                        byte_start: 0,
+                        byte_end: 0,
+                        level: None,
+                    }
+                })
+            }
+            _ => None,
+        };
+        // In vertical text, the ascent and descent are the extents of a column to either side of
+        // its center line.
+        let (ascender, descender, line_gap) = if self.writing_mode.is_vertical() {
+            (font.vertical_ascender(), font.vertical_descender(), font.vertical_line_gap())
+        } else {
+            (font.ascender(), font.descender(), font.line_gap())
+        };
+        let (ascent, descent, line_gap) = match self.line_box {
+            Some(line_box) if !self.writing_mode.is_vertical() => {
+                (line_box.ascent * self.glyph_scale, line_box.descent * self.glyph_scale, 0.0)
+            }
+            _ => {
+                (ascender as f32 * pixels_per_unit,
+                 -descender as f32 * pixels_per_unit,
+                 line_gap as f32 * pixels_per_unit)
+            }
+        };
+        let hyphen_glyph_id = self.glyph_mapping_cache[cache_index].glyph_mapping
+                                  .glyph_for(HYPHEN as u32);
+        let hyphen = match hyphen_glyph_id {
+            Some(glyph_id) if glyph_id != 0 && !self.writing_mode.is_vertical() => {
+                font.metrics_for_glyph(glyph_id).ok().map(|metrics| {
+                    WordGlyph {
+                        glyph_id: glyph_id,
+                        advance: metrics.advance_width as f32 * pixels_per_unit,
+                        offset: Point2D::zero(),
+                        byte_start: 0,
+                        byte_end: 0,
+                        level: None,
+                    }
+                })
+            }
+            _ => None,
+        };
+        let decimal_point = self.glyph_mapping_cache[cache_index].glyph_mapping
+                                .glyph_for(DECIMAL_POINT as u32)
+                                .and_then(|glyph_id| {
+                                    if glyph_id != 0 { Some(glyph_id) } else { None }
+                                });
+        let line_spacing = match self.line_spacing {
+            LineSpacing::Multiple(multiple) => (ascent + descent + line_gap) * multiple,
+            LineSpacing::Fixed(line_spacing) => line_spacing,
+        };
+        let text_index = match self.runs.last() {
+            None => 0,
+            Some(run) if options.continues_text => run.text_index,
+            Some(run) => run.text_index + 1,
+        };
+
+        self.runs.push(Run {
+            direction: self.direction,
+            space_advance: space_advance,
+            letter_spacing: self.letter_spacing,
+            tab_width: self.tab_width,
+            baseline_shift: if self.writing_mode.is_vertical() {
+                0.0
+            } else {
+                self.baseline_shift
+            },
+            break_policy: self.break_policy,
+            ellipsis: ellipsis,
+            hyphen: hyphen,
+            decimal_point: decimal_point,
+            line_spacing: line_spacing,
+            style: options.style,
+            ascent: ascent,
+            descent: descent,
+            line_gap: line_gap,
+            scale: self.glyph_scale,
+            drop_cap_lines: 0,
+            text_index: text_index,
+        });
+        Ok(run_index)
+    }
+
+    /// Like `add_text()`, but passes glyphs to the given callback once their positions are final
+    /// instead of keeping them, so that they can be written straight into, for example, a vertex
+    /// buffer.
+    ///
+    /// The text is laid out exactly as `add_text()` would lay it out. As each line is finished,
+    /// while the string is being laid out, its glyphs are passed to the callback in order and
+    /// removed from `glyph_positions`, so memory use is bounded by the length of a line rather
+    /// than that of the whole document, however long the string is. The glyphs on the current
+    /// line are kept, because aligning the line or adding more text to it may still move them;
+    /// call `finish_streaming()` once all of the text has been added to receive them. Lines
+    /// spanned by a drop cap are kept until the last of them is finished, and with a line
+    /// breaking strategy other than `LineBreakStrategy::Greedy`, every line of the paragraph in
+    /// progress is kept until the paragraph ends, so that it can still be broken anew.
+    ///
+    /// Streamed lines still count toward `line_count()` and `set_max_lines()`, but no longer have
+    /// any glyphs. Text that started on a streamed line can't be removed with `pop_last_run()` or
+    /// laid out again.
+    ///
+    /// Panics as `add_text()` does.
+    pub fn add_text_streaming<F>(&mut self,
+                                 font: &Font,
+                                 point_size: f32,
+                                 string: &str,
+                                 mut callback: F)
+                                 where F: FnMut(GlyphPosition) {
+        self.lay_out_run(font, point_size, string, RunOptions::default(), Some(&mut callback))
+            .unwrap();
+        self.stream_finished_lines(callback)
+    }
+
+    /// Passes the glyphs remaining after calls to `add_text_streaming()`, which are those on the
+    /// line that the cursor is on, to the given callback in order and removes them from
+    /// `glyph_positions`.
+    ///
+    /// Text added afterward continues at the cursor, but aligning the current line no longer
+    /// moves the glyphs that were passed to the callback, so this should only be called once all
+    /// of the text has been added.
+    pub fn finish_streaming<F>(&mut self, callback: F) where F: FnMut(GlyphPosition) {
+        let line_count = self.lines.len();
+        self.stream_lines_before(line_count, callback)
+    }
+
+    /// Passes the glyphs on the lines before the current one to the callback and discards them,
+    /// as `add_text_streaming()` does, holding back those on lines that may still be moved by a
+    /// drop cap or by breaking the current paragraph into lines again.
+    fn stream_finished_lines<F>(&mut self, callback: F) where F: FnMut(GlyphPosition) {
+        let mut line_index = self.lines.len() - 1;
+        if self.line_break_strategy != LineBreakStrategy::Greedy {
+            if let Some(ref paragraph_start) = self.paragraph_start {
+                line_index = cmp::min(line_index, paragraph_start.line_count - 1)
+            }
+        }
+        if let Some(drop_cap) = self.drop_cap {
+            if drop_cap.word.is_some() && line_index < drop_cap.line_end {
+                line_index = cmp::min(line_index, drop_cap.line)
+            }
+        }
+        let line = self.lines[line_index];
+        if line.glyph_start > 0 || line.word_start > 0 {
+            self.stream_lines_before(line_index, callback)
+        }
+    }
+
+    /// Passes the glyphs on the lines before the one with the given index to the callback and
+    /// discards them, along with their words and controls.
+    ///
+    /// The lines themselves are kept, without any glyphs, so that line indices don't change.
+    fn stream_lines_before<F>(&mut self, line_index: usize, mut callback: F)
+                              where F: FnMut(GlyphPosition) {
+        let (glyph_count, word_count) = match self.lines.get(line_index) {
+            Some(line) => (line.glyph_start, line.word_start),
+            None => (self.glyph_positions.len(), self.words.len()),
+        };
+
+        for glyph_position in self.glyph_positions.drain(..glyph_count) {
+            callback(glyph_position)
+        }
+        self.glyph_advances.drain(..glyph_count);
+        self.glyph_offsets.drain(..glyph_count);
+        self.glyph_byte_ranges.drain(..glyph_count);
+        self.glyph_levels.drain(..glyph_count);
+
+        self.words.drain(..word_count);
+        for word in &mut self.words {
+            word.glyph_start -= glyph_count;
+            word.glyph_end -= glyph_count;
+        }
+        // Lines streamed earlier already start at zero, and don't need to be visited again.
+        for line in self.lines.iter_mut().rev() {
+            if line.glyph_start == 0 && line.word_start == 0 {
+                break
+            }
+            line.glyph_start = line.glyph_start.saturating_sub(glyph_count);
+            line.word_start = line.word_start.saturating_sub(word_count);
+        }
+
+        let control_count = self.controls
+                                .iter()
+                                .take_while(|control| control.word_index < word_count)
+                                .count();
+        self.controls.drain(..control_count);
+        for control in &mut self.controls {
+            control.word_index -= word_count
+        }
+        self.ellipsis_word = self.ellipsis_word
+                                 .and_then(|word_index| word_index.checked_sub(word_count));
+        if let Some(ref mut drop_cap) = self.drop_cap {
+            drop_cap.word = drop_cap.word.and_then(|word_index| word_index.checked_sub(word_count))
+        }
+
+        // Paragraphs that started on a streamed line can't be broken into lines again.
+        self.paragraph_start = match self.paragraph_start.take() {
+            Some(mut paragraph_start) if paragraph_start.line_count > line_index => {
+                paragraph_start.discard_streamed(line_index,
+                                                 glyph_count,
+                                                 word_count,
+                                                 control_count);
+                Some(paragraph_start)
+            }
+            _ => None,
+        };
+
+        // Runs that started on a streamed line can't be rewound to, so free their saved lines.
+        for (run_index, run_start) in self.run_starts.iter_mut().enumerate() {
+            if run_start.line_count <= line_index {
+                self.streamed_run_count = cmp::max(self.streamed_run_count, run_index + 1);
+                run_start.line_glyph_positions = vec![];
+                run_start.line_glyphs = vec![];
+                run_start.line_words = vec![];
+                run_start.paragraph_start = None;
+                continue
+            }
+            run_start.discard_streamed(line_index, glyph_count, word_count, control_count)
+        }
+    }
+
+    /// Returns the width, in pixels, that the given string would occupy if it were laid out on a
+    /// single line with the current settings, without laying it out. In the vertical writing
+    /// modes, this is the height of a single column instead.
+    ///
+    /// All whitespace, including newlines and tabs, is treated as a single space between words.
+    /// Whitespace at the start and end of the string doesn't count, and neither do soft hyphens.
+    ///
+    /// Panics as `add_text()` does. Use `try_measure_text()` for fonts that may be malformed.
+    #[inline]
+    pub fn measure_text(&mut self, font: &Font, point_size: f32, string: &str) -> f32 {
+        self.try_measure_text(font, point_size, string).unwrap()
+    }
+
+    /// Like `measure_text()`, but returns an error instead of panicking if the font's character
+    /// map or the metrics of its space glyph can't be read.
+    pub fn try_measure_text(&mut self, font: &Font, point_size: f32, string: &str)
+                            -> Result<f32, TypesetterError> {
+        let cache_index = try!(self.cache_glyph_mapping(font, string));
+        let pixels_per_unit = self.pixels_per_unit(font, point_size);
+        let space_advance = try!(self.cached_space_advance(font, cache_index, pixels_per_unit));
+
+        let (mut width, mut word_count) = (0.0, 0);
+        let mut word_glyphs = vec![];
+        for word in string.split_whitespace() {
+            self.shape_word(font,
+                            cache_index,
+                            pixels_per_unit,
+                            word,
+                            &RunOptions::default(),
+                            &mut word_glyphs);
+            for piece in word_pieces(word, self.break_policy, &[]) {
+                width += word_advance(&word_glyphs[piece.glyph_range], self.letter_spacing)
+            }
+            word_count += 1;
+        }
+
+        if word_count > 1 {
+            width += space_advance * (word_count - 1) as f32
+        }
+        Ok(width)
+    }
+
+    /// Returns the largest point size between `min_point_size` and `max_point_size` at which the
+    /// given string, laid out from the start with the current settings, fits in a box of the
+    /// given size in pixels, for example to size a headline to fill a banner.
+    ///
+    /// At each size tried, the string is wrapped at `max_width` less the margins, as `add_text()`
+    /// would wrap it, and the font and size also determine the first baseline, as in `new()`.
+    /// The string fits if no line extends past the margins and the lines, from the top of the
+    /// first to the bottom of the last, are no taller than `max_height`; text dropped by
+    /// `set_max_lines()` means that it doesn't fit. If it doesn't fit even at `min_point_size`,
+    /// that is returned. The size is found to within 1/65536 of the range by binary search.
+    ///
+    /// Text laid out so far is unaffected. The typesetter must lay out horizontal text. Panics as
+    /// `add_text()` does.
+    pub fn fit_point_size(&self,
+                          font: &Font,
+                          string: &str,
+                          max_width: f32,
+                          max_height: f32,
+                          min_point_size: f32,
+                          max_point_size: f32)
+                          -> f32 {
+        let mut typesetter = self.clone();
+        typesetter.set_page_width(max_width);
+        let mut fits = |point_size: f32| {
+            typesetter.reset_with(font, point_size);
+            typesetter.add_text(font, point_size, string);
+            typesetter.fits_in(max_height)
+        };
+
+        if fits(max_point_size) {
+            return max_point_size
+        }
+        let (mut low, mut high) = (min_point_size, max_point_size);
+        for _ in 0..FIT_ITERATIONS {
+            let mid = (low + high) * 0.5;
+            if fits(mid) {
+                low = mid
+            } else {
+                high = mid
+            }
+        }
+        low
+    }
+
+    /// Returns true if everything laid out so far fits between the margins, with nothing dropped
+    /// by `set_max_lines()`, and the lines are no taller than the given height.
+    fn fits_in(&self, max_height: f32) -> bool {
+        let (start, end) = (self.origin.x + self.left_margin,
+                            self.origin.x + self.page_width - self.right_margin);
+        let line_metrics = self.line_metrics();
+        let (first_line, last_line) = (&line_metrics[0], &line_metrics[line_metrics.len() - 1]);
+        let top = first_line.baseline - first_line.ascent;
+        let bottom = last_line.baseline + last_line.descent;
+        !self.truncated && bottom - top <= max_height && line_metrics.iter().all(|line| {
+            line.left == line.right || (line.left >= start - 0.001 && line.right <= end + 0.001)
+        })
+    }
+
+    /// Shapes each word of the given string with the current settings, without laying it out, so
+    /// that callers can break lines and position words themselves.
+    ///
+    /// Words are separated by whitespace, as in `add_text()`. The glyphs of each word are
+    /// positioned relative to a pen at the start of the word on a baseline at y = 0, with letter
+    /// spacing applied and soft hyphens left out; in the vertical writing modes, they advance
+    /// down the y axis instead. Letter spacing isn't added at the break points of a word, such as
+    /// after a hyphen, just as the typesetter doesn't add it there. Glyph clusters are byte
+    /// offsets within the string.
+    ///
+    /// Panics as `add_text()` does. Use `try_shape_words()` for fonts that may be malformed.
+    #[inline]
+    pub fn shape_words(&mut self, font: &Font, point_size: f32, string: &str) -> Vec<WordMetrics> {
+        self.try_shape_words(font, point_size, string).unwrap()
+    }
+
+    /// Like `shape_words()`, but returns an error instead of panicking if the font's character
+    /// map can't be read.
+    pub fn try_shape_words(&mut self, font: &Font, point_size: f32, string: &str)
+                           -> Result<Vec<WordMetrics>, TypesetterError> {
+        let cache_index = try!(self.cache_glyph_mapping(font, string));
+        let pixels_per_unit = self.pixels_per_unit(font, point_size);
+        let vertical = self.writing_mode.is_vertical();
+
+        let mut words = vec![];
+        let mut word_glyphs = vec![];
+        for word in string.split_whitespace() {
+            let word_byte_start = word.as_ptr() as usize - string.as_ptr() as usize;
+            self.shape_word(font,
+                            cache_index,
+                            pixels_per_unit,
+                            word,
+                            &RunOptions::default(),
+                            &mut word_glyphs);
+            // Letter spacing starts again with each piece, as it does when the pieces are laid
+            // out.
+            let (mut pen, mut glyph_positions) = (0.0, vec![]);
+            for piece in word_pieces(word, self.break_policy, &[]) {
+                let piece_glyphs = &word_glyphs[piece.glyph_range];
+                for (glyph_index, glyph) in piece_glyphs.iter().enumerate() {
+                    if glyph_index > 0 && glyph.advance != 0.0 {
+                        pen += self.letter_spacing
+                    }
+                    let (x, y) = if vertical { (0.0, pen) } else { (pen, 0.0) };
+                    pen += glyph.advance;
+                    glyph_positions.push(GlyphPosition {
+                        x: x + glyph.offset.x,
+                        y: y + glyph.offset.y,
+                        x_offset: glyph.offset.x,
+                        y_offset: glyph.offset.y,
+                        glyph_id: glyph.glyph_id,
+                        style: 0,
+                        cluster: (word_byte_start + glyph.byte_start) as u32,
+                        page: 0,
+                        scale: self.glyph_scale,
+                    })
+                }
+            }
+
+            words.push(WordMetrics {
+                byte_range: word_byte_start..(word_byte_start + word.len()),
+                glyph_positions: glyph_positions,
+                advance: pen,
+            })
+        }
+        Ok(words)
+    }
+
+    /// Returns the number of pixels per font unit that text in the given font and point size is
+    /// laid out at, taking the glyph scale into account.
+    #[inline]
+    fn pixels_per_unit(&self, font: &Font, point_size: f32) -> f32 {
+        point_size * self.glyph_scale / font.units_per_em() as f32
+    }
+
+    /// Returns the distance, in pixels, by which a space between words in the given font and
+    /// point size advances the pen with the current settings: the advance of the font's space
+    /// glyph, plus the word spacing.
+    ///
+    /// If the font has no space glyph, the fallback space width is used instead, as in
+    /// `add_text()`. In the vertical writing modes, this is the vertical advance of the space.
+    ///
+    /// Panics as `add_text()` does. Use `try_space_advance()` for fonts that may be malformed.
+    #[inline]
+    pub fn space_advance(&mut self, font: &Font, point_size: f32) -> f32 {
+        self.try_space_advance(font, point_size).unwrap()
+    }
+
+    /// Like `space_advance()`, but returns an error instead of panicking if the font's character
+    /// map or the metrics of its space glyph can't be read.
+    pub fn try_space_advance(&mut self, font: &Font, point_size: f32)
+                             -> Result<f32, TypesetterError> {
+        let cache_index = try!(self.cache_glyph_mapping(font, ""));
+        let pixels_per_unit = self.pixels_per_unit(font, point_size);
+        self.cached_space_advance(font, cache_index, pixels_per_unit)
+    }
+
+    /// Returns the advance of the space glyph in the given font, plus the word spacing, in pixels.
+    ///
+    /// If the font has no space glyph, the fallback space width is used instead.
+    ///
+    /// The space glyph and its advance are looked up once per font and writing direction, and
+    /// remembered in the cache entry, so that laying out many short strings doesn't look them up
+    /// again for every call.
+    fn cached_space_advance(&mut self, font: &Font, cache_index: usize, pixels_per_unit: f32)
+                            -> Result<f32, TypesetterError> {
+        let vertical = self.writing_mode.is_vertical();
+        let space_glyph = match self.glyph_mapping_cache[cache_index].space_glyph {
+            Some(space_glyph) if space_glyph.vertical == vertical => space_glyph,
+            _ => {
+                let glyph_id = self.glyph_mapping_cache[cache_index].glyph_mapping
+                                   .glyph_for(' ' as u32)
+                                   .unwrap_or(0);
+                let advance = if glyph_id == 0 {
+                    0
+                } else if vertical {
+                    try!(font.vertical_metrics_for_glyph(glyph_id)
+                             .map_err(TypesetterError::MetricsMissing)).advance_height
+                } else {
+                    try!(font.metrics_for_glyph(glyph_id)
+                             .map_err(TypesetterError::MetricsMissing)).advance_width
+                };
+                let space_glyph = CachedSpaceGlyph {
+                    glyph_id: glyph_id,
+                    advance: advance,
+                    vertical: vertical,
+                };
+                self.glyph_mapping_cache[cache_index].space_glyph = Some(space_glyph);
+                space_glyph
+            }
+        };
+
+        if space_glyph.glyph_id == 0 {
+            let advance = self.fallback_space_width * font.units_per_em() as f32;
+            return Ok(advance * pixels_per_unit + self.word_spacing)
+        }
+        Ok(space_glyph.advance as f32 * pixels_per_unit + self.word_spacing)
+    }
+
+    /// Shapes a word with the glyph mapping in the given cache entry, replacing the contents of
+    /// `word_glyphs` with the result.
+    ///
+    /// In the vertical writing modes, the glyphs get their vertical advances, and are offset so
+    /// that each is centered on the pen horizontally with its top side bearing below the pen.
+    fn shape_word(&self,
+                  font: &Font,
+                  cache_index: usize,
+                  pixels_per_unit: f32,
+                  word: &str,
+                  options: &RunOptions,
+                  word_glyphs: &mut Vec<WordGlyph>) {
+        let entry = &self.glyph_mapping_cache[cache_index];
+        word_glyphs.clear();
+
+        // Fixed-pitch fonts without kerning or features need no shaping: every glyph has the same
+        // advance.
+        if let Some(fixed_advance) = entry.fixed_advance {
+            if !self.writing_mode.is_vertical() && !(self.kerning && font.has_kerning()) &&
+                    options.features.is_empty() && options.language.is_none() {
+                let advance = fixed_advance as f32 * pixels_per_unit;
+                word_glyphs.extend(word.char_indices().map(|(byte_start, ch)| {
+                    WordGlyph {
+                        glyph_id: entry.glyph_mapping.glyph_for(ch as u32).unwrap_or(0),
+                        advance: advance,
+                        offset: Point2D::zero(),
+                        byte_start: byte_start,
+                        byte_end: byte_start + ch.len_utf8(),
+                        level: None,
+                    }
+                }));
+                if let Some(substitutions) = options.substitutions {
+                    substitute_glyphs(font, pixels_per_unit, substitutions, word_glyphs)
+                }
+                merge_grapheme_clusters(word, word_glyphs);
+                return
+            }
+        }
+
+        let kerning = self.kerning && !self.writing_mode.is_vertical();
+        let shaped_glyph_positions = if options.features.is_empty() && options.language.is_none() {
+            shaper::shape_text_with_kerning(font, &entry.glyph_mapping, word, kerning)
+        } else {
+            let features_without_kerning;
+            let features = if kerning {
+                options.features
+            } else {
+                let mut features = options.features.to_vec();
+                features.push((shaper::KERN_FEATURE, 0));
+                features_without_kerning = features;
+                &features_without_kerning[..]
+            };
+            match options.language {
+                None => {
+                    shaper::shape_text_with_features(font, &entry.glyph_mapping, word, features)
+                }
+                Some((script, language)) => {
+                    shaper::shape_text_with_language(font,
+                                                     &entry.glyph_mapping,
+                                                     word,
+                                                     script,
+                                                     language,
+                                                     features)
+                }
+            }
+        };
+        word_glyphs.extend(shaped_glyph_positions.iter()
+                                                 .enumerate()
+                                                 .map(|(glyph_index, glyph_position)| {
+            // Every glyph of a cluster covers the whole cluster.
+            let byte_start = glyph_position.cluster as usize;
+            let byte_end = shaped_glyph_positions[(glyph_index + 1)..]
+                .iter()
+                .map(|next_glyph_position| next_glyph_position.cluster as usize)
+                .find(|&next_cluster| next_cluster != byte_start)
+                .unwrap_or(word.len());
+            WordGlyph {
+                glyph_id: glyph_position.glyph_id,
+                advance: glyph_position.advance as f32 * pixels_per_unit,
+                offset: Point2D::new(glyph_position.x_offset as f32 * pixels_per_unit,
+                                     -glyph_position.y_offset as f32 * pixels_per_unit),
+                byte_start: byte_start,
+                byte_end: byte_end,
+                level: None,
+            }
+        }));
+
+        if let Some(substitutions) = options.substitutions {
+            substitute_glyphs(font, pixels_per_unit, substitutions, word_glyphs)
+        }
+        merge_grapheme_clusters(word, word_glyphs);
+        if self.writing_mode.is_vertical() {
+            apply_vertical_metrics(font, pixels_per_unit, word_glyphs)
+        }
+    }
+
+    /// Sets the width of the page, in pixels.
+    ///
+    /// Text that has already been laid out is not affected until `reflow()` is called.
+    #[inline]
+    pub fn set_page_width(&mut self, page_width: f32) {
+        self.page_width = page_width
+    }
+
+    #[inline]
+    pub fn page_width(&self) -> f32 {
+        self.page_width
+    }
+
+    /// Returns the top left corner of the page, in pixels. See `new_at()`.
+    #[inline]
+    pub fn origin(&self) -> Point2D<f32> {
+        self.origin
+    }
+
+    /// Sets the distances, in pixels, from the left and right edges of the page to the text.
+    ///
+    /// Lines of horizontal text run from `left` to `page_width - right`; indents are measured
+    /// from the margin at which lines start, and lines are aligned between the margins. The
+    /// margins are zero by default. Vertical text ignores them.
+    ///
+    /// If the cursor is at the start of a line, it moves to the start of the line within the new
+    /// margins. Other text that has already been laid out is not affected until `reflow()` is
+    /// called.
+    pub fn set_margins(&mut self, left: f32, right: f32) {
+        let at_line_start = !self.writing_mode.is_vertical() &&
+            self.cursor.x == self.line_start();
+        self.left_margin = left;
+        self.right_margin = right;
+        if at_line_start {
+            self.move_to_line_start()
+        }
+    }
+
+    /// Returns the left and right margins, in pixels.
+    #[inline]
+    pub fn margins(&self) -> (f32, f32) {
+        (self.left_margin, self.right_margin)
+    }
+
+    /// Returns the width, in pixels, of the space between the margins.
+    #[inline]
+    fn text_width(&self) -> f32 {
+        self.page_width - self.left_margin - self.right_margin
+    }
+
+    /// Lays out all text added so far again, breaking lines according to the current page width
+    /// and aligning them according to the current alignment.
+    ///
+    /// Other settings, such as the direction and spacing, are those that were in effect when each
+    /// piece of text was added. The text is not shaped again, so no fonts are needed.
+    ///
+    /// If lines have been streamed out by `add_text_streaming()`, only the text added after the
+    /// last one that started on a streamed line is laid out again.
+    pub fn reflow(&mut self) {
+        if self.streamed_run_count > 0 {
+            let run_index = self.streamed_run_count;
+            return self.relayout_from_run(run_index)
+        }
+
+        let retained_layout = self.retained_layout(0, 0);
+        let direction = self.direction;
+        self.controls.clear();
+        self.truncate_glyphs(0);
+        self.restart_layout();
+
+        self.replay_layout(retained_layout);
+        self.set_direction(direction);
+        self.break_paragraph_optimally();
+        self.align_line(true);
+    }
+
+    /// Like `reflow()`, but lays out again only the text from the start of the `add_text()` call
+    /// that produced the glyph with the given index onward.
+    ///
+    /// Glyphs on the lines before the one that this text started on do not move, and neither do
+    /// those before it on that line, except to be aligned again. This is much cheaper than
+    /// `reflow()` when only the end of a large document needs to be laid out again, for example
+    /// after removing the last few pieces of text with `pop_last_run()` and adding new ones.
+    ///
+    /// Text that started on a line streamed out by `add_text_streaming()` is not laid out again.
+    pub fn relayout_from(&mut self, glyph_index: usize) {
+        if glyph_index >= self.glyph_positions.len() {
+            return
+        }
+        let run_index = self.words[self.word_index_for_glyph(glyph_index)].run;
+        let run_index = self.first_run_of_text(run_index);
+        if run_index == 0 {
+            return self.reflow()
+        }
+        let run_index = cmp::max(run_index, self.streamed_run_count);
+        self.relayout_from_run(run_index)
+    }
+
+    /// Lays out the text from the start of the run with the given index onward again. The run
+    /// must not be the first one.
+    fn relayout_from_run(&mut self, run_index: usize) {
+        if run_index >= self.runs.len() {
+            return
+        }
+
+        self.run_starts.truncate(run_index + 1);
+        let run_start = self.run_starts.pop().unwrap();
+        let word_start = self.words
+                             .iter()
+                             .position(|word| word.run >= run_index)
+                             .unwrap_or(self.words.len());
+        let retained_layout = self.retained_layout(word_start, run_start.control_count);
+        let direction = self.direction;
+        self.rewind_to_run_start(run_start);
+
+        // Start from the direction that the previous run left the typesetter in, as `reflow()`
+        // does, so that switching to this run's direction moves the cursor in the same way.
+        self.direction = self.runs[run_index - 1].direction;
+        self.replay_layout(retained_layout);
+        self.set_direction(direction);
+        self.break_paragraph_optimally();
+        self.align_line(true);
+    }
+
+    /// Returns the index of the first run of the text that the run with the given index is part
+    /// of, which is laid out by more than one run if `add_text_with_fallback()` switched fonts.
+    fn first_run_of_text(&self, run_index: usize) -> usize {
+        let text_index = self.runs[run_index].text_index;
+        self.runs[..run_index].iter()
+                              .rposition(|run| run.text_index != text_index)
+                              .map_or(0, |previous_run_index| previous_run_index + 1)
+    }
+
+    /// Removes the text added by the last call to `add_text()`, putting the cursor and the line it
+    /// started on back as they were before that call.
+    ///
+    /// This is much cheaper than clearing and laying out all the other text again. Lines that the
+    /// removed text wrapped onto are discarded, and text that the removed text dropped from the
+    /// end of the line it started on because of `set_max_lines()` is restored. Returns false if
+    /// there was no text to remove, or if the text started on a line that was streamed out by
+    /// `add_text_streaming()`.
+    pub fn pop_last_run(&mut self) -> bool {
+        let run_index = match self.runs.len() {
+            0 => return false,
+            run_count => self.first_run_of_text(run_count - 1),
+        };
+        if run_index < self.streamed_run_count || run_index >= self.run_starts.len() {
+            return false
+        }
+        self.run_starts.truncate(run_index + 1);
+        let run_start = self.run_starts.pop().unwrap();
+        self.runs.truncate(run_index);
+        self.rewind_to_run_start(run_start);
+        self.break_paragraph_optimally();
+        self.align_line(true);
+        true
+    }
+
+    /// Returns a snapshot of the layout so far, which `restore()` can return to.
+    ///
+    /// Only the state of the current line is copied, so this is much cheaper than cloning the
+    /// typesetter. This makes it possible to try laying out some text and back out if it
+    /// doesn't fit, for example when building a line breaking algorithm on top of the
+    /// typesetter.
+    pub fn snapshot(&self) -> LayoutState {
+        LayoutState {
+            run_count: self.runs.len(),
+            run_start: self.run_start(),
+            line_offset: self.line_offset,
+            line_stretch: self.line_stretch,
+            line_reordered: self.line_reordered,
+        }
+    }
+
+    /// Puts the layout back as it was when the given snapshot was taken, removing the text added
+    /// since then.
+    ///
+    /// The snapshot must have been taken from this typesetter, and no text added before it was
+    /// taken may have been removed, laid out again, or streamed out since then. Passing any other
+    /// snapshot leaves the layout in an unspecified state.
+    ///
+    /// Panics if text added before the snapshot was taken has been removed with
+    /// `pop_last_run()` or `clear()`.
+    pub fn restore(&mut self, state: LayoutState) {
+        assert!(state.run_count <= self.runs.len(),
+                "Text added before the snapshot was taken has been removed");
+        self.runs.truncate(state.run_count);
+        self.run_starts.truncate(state.run_count);
+        self.rewind_to_run_start(state.run_start);
+        self.line_offset = state.line_offset;
+        self.line_stretch = state.line_stretch;
+        self.line_reordered = state.line_reordered
+    }
+
+    /// Puts the layout back as it was when the given run started, leaving the line that it
+    /// started on unaligned.
+    fn rewind_to_run_start(&mut self, run_start: RunStart) {
+        self.controls.truncate(run_start.control_count);
+
+        // Glyphs on the line that the text started on may have been moved to make room for it or
+        // to justify the line, so put back the copies saved when it started.
+        let line = run_start.line;
+        self.lines.truncate(run_start.line_count - 1);
+        self.lines.push(line);
+        self.truncate_glyphs(line.glyph_start);
+        for (glyph_position, glyph) in run_start.line_glyph_positions
+                                                .iter()
+                                                .zip(run_start.line_glyphs.iter()) {
+            self.glyph_positions.push(*glyph_position);
+            self.glyph_advances.push(glyph.advance);
+            self.glyph_offsets.push(glyph.offset);
+            self.glyph_byte_ranges.push(glyph.byte_start..glyph.byte_end);
+            self.glyph_levels.push(glyph.level);
+        }
+        self.words.truncate(line.word_start);
+        self.words.extend_from_slice(&run_start.line_words);
+
+        self.cursor = run_start.cursor;
+        self.line_offset = 0.0;
+        self.line_stretch = 0.0;
+        self.line_reordered = false;
+        self.trailing_space = run_start.trailing_space;
+        self.tab_column = run_start.tab_column;
+        self.line_limit_reached = run_start.line_limit_reached;
+        self.truncated = run_start.truncated;
+        self.ellipsis_word = run_start.ellipsis_word;
+        self.drop_cap = run_start.drop_cap;
+        self.paragraph_start = run_start.paragraph_start;
+        self.position_drop_cap()
+    }
+
+    /// Discards all glyphs from the given index onward.
+    fn truncate_glyphs(&mut self, glyph_count: usize) {
+        self.glyph_positions.truncate(glyph_count);
+        self.glyph_advances.truncate(glyph_count);
+        self.glyph_offsets.truncate(glyph_count);
+        self.glyph_byte_ranges.truncate(glyph_count);
+        self.glyph_levels.truncate(glyph_count);
+    }
+
+    /// Returns a copy of the shaped glyph that was placed at the given index.
+    fn word_glyph(&self, glyph_index: usize) -> WordGlyph {
+        WordGlyph {
+            glyph_id: self.glyph_positions[glyph_index].glyph_id,
+            advance: self.glyph_advances[glyph_index],
+            offset: self.glyph_offsets[glyph_index],
+            byte_start: self.glyph_byte_ranges[glyph_index].start,
+            byte_end: self.glyph_byte_ranges[glyph_index].end,
+            level: self.glyph_levels[glyph_index],
+        }
+    }
+
+    /// Copies the words from the given index onward, along with their glyphs and the controls from
+    /// the given index onward, so that they can be laid out again by `replay_layout()`.
+    fn retained_layout(&self, word_start: usize, control_start: usize) -> RetainedLayout {
+        let glyph_start = self.words.get(word_start).map_or(self.glyph_positions.len(),
+                                                            |word| word.glyph_start);
+        RetainedLayout {
+            word_start: word_start,
+            glyph_start: glyph_start,
+            words: self.words[word_start..].to_vec(),
+            controls: self.controls[control_start..].to_vec(),
+            glyphs: (glyph_start..self.glyph_positions.len()).map(|glyph_index| {
+                self.word_glyph(glyph_index)
+            }).collect(),
+            ellipsis_word: self.ellipsis_word,
+            line_breaks: vec![],
+        }
+    }
+
+    /// Lays out the retained words and controls again, starting at the cursor.
+    ///
+    /// The current line must be unaligned. It is left unaligned.
+    fn replay_layout(&mut self, retained_layout: RetainedLayout) {
+        let RetainedLayout {
+            word_start,
+            glyph_start,
+            words,
+            controls,
+            glyphs,
+            ellipsis_word,
+            line_breaks,
+        } = retained_layout;
+
+        let mut controls = controls.into_iter().peekable();
+        let word_end = word_start + words.len();
+        let (mut word_index, mut item_index) = (word_start, 0);
+        while word_index < word_end {
+            while controls.peek().map_or(false, |control| control.word_index == word_index) {
+                let control = controls.next().unwrap();
+                self.record_run_starts(control.run + 1);
+                self.add_control(control.kind, control.run)
+            }
+
+            let word = words[word_index - word_start];
+            self.record_run_starts(word.run + 1);
+            word_index += 1;
+            if ellipsis_word == Some(word_index - 1) || word.joint == Joint::InsertedHyphen {
+                continue
+            }
+
+            // Put back together words that were split because they were too long for a line, so
+            // that they can be split again at the new page width.
+            let mut glyph_end = word.glyph_end;
+            while word_index < word_end && words[word_index - word_start].joint == Joint::Split &&
+                    ellipsis_word != Some(word_index) {
+                glyph_end = words[word_index - word_start].glyph_end;
+                word_index += 1
+            }
+
+            let word_glyphs = &glyphs[(word.glyph_start - glyph_start)..(glyph_end - glyph_start)];
+            self.planned_break = line_breaks.get(item_index).cloned();
+            item_index += 1;
+            if self.runs[word.run].drop_cap_lines > 0 {
+                self.place_drop_cap(word_glyphs, word.run);
+                continue
+            }
+
+            let hyphen_follows = words[(word_index - word_start)..]
+                .iter()
+                .find(|word| word.joint != Joint::InsertedHyphen)
+                .map_or(false, |word| word.joint == Joint::SoftHyphen);
+            self.place_word(word_glyphs, word.run, word.joint, hyphen_follows);
+        }
+        for control in controls {
+            self.record_run_starts(control.run + 1);
+            self.add_control(control.kind, control.run)
+        }
+        let run_count = self.runs.len();
+        self.record_run_starts(run_count);
+    }
+
+    /// Saves the layout state as the start of each run from the first one without a saved start
+    /// up to, but not including, `run_count`.
+    ///
+    /// The current line must be unaligned.
+    fn record_run_starts(&mut self, run_count: usize) {
+        while self.run_starts.len() < run_count {
+            let run_start = self.run_start();
+            self.run_starts.push(run_start)
+        }
+    }
+
+    /// Saves the layout state at the cursor, with copies of the glyphs and words on the current
+    /// line as they are now.
+    fn run_start(&self) -> RunStart {
+        let line = *self.current_line();
+        RunStart {
+            cursor: self.cursor,
+            line_count: self.lines.len(),
+            line: line,
+            line_glyph_positions: self.glyph_positions[line.glyph_start..].to_vec(),
+            line_glyphs: (line.glyph_start..self.glyph_positions.len()).map(|glyph_index| {
+                self.word_glyph(glyph_index)
+            }).collect(),
+            line_words: self.words[line.word_start..].to_vec(),
+            control_count: self.controls.len(),
+            trailing_space: self.trailing_space,
+            tab_column: self.tab_column,
+            line_limit_reached: self.line_limit_reached,
+            truncated: self.truncated,
+            ellipsis_word: self.ellipsis_word,
+            drop_cap: self.drop_cap,
+            paragraph_start: self.paragraph_start.clone(),
+        }
+    }
+
+    /// Saves the layout state at the cursor as the start of a new paragraph.
+    fn start_paragraph(&mut self) {
+        let mut paragraph_start = self.run_start();
+        paragraph_start.paragraph_start = None;
+        self.paragraph_start = Some(Box::new(paragraph_start))
+    }
+
+    /// With `LineBreakStrategy::OptimalFit`, breaks the current paragraph into lines again so as
+    /// to minimize the total badness of its lines, from its start up to the cursor. With
+    /// `LineBreakStrategy::Balanced`, breaks it again to even out the lengths of its lines.
+    ///
+    /// The badness of a line grows with the cube of how far its spaces would have to stretch to
+    /// fill it, as in TeX, and the demerits of a paragraph are the sum of the squares of the
+    /// badness of each line but the last, plus a penalty for each line broken other than at a
+    /// space. Paragraphs containing tabs, preserved spaces, or drop caps, and paragraphs that
+    /// have been truncated, keep their greedy line breaks.
+    ///
+    /// The current line must be unaligned. It is left unaligned.
+    fn break_paragraph_optimally(&mut self) {
+        if self.line_break_strategy == LineBreakStrategy::Greedy ||
+                self.writing_mode.is_vertical() || self.truncated || self.line_limit_reached ||
+                !self.text_width().is_finite() {
+            return
+        }
+        let paragraph_start = match self.paragraph_start {
+            Some(ref paragraph_start) => (**paragraph_start).clone(),
+            None => return,
+        };
+        if self.lines.len() == paragraph_start.line_count ||
+                self.controls.len() > paragraph_start.control_count {
+            return
+        }
+
+        // Gather the words as `replay_layout()` will place them, with the pieces of words that
+        // were split because they were too long for a line put back together.
+        let word_start = paragraph_start.line.word_start + paragraph_start.line_words.len();
+        let mut items: Vec<BreakItem> = vec![];
+        let mut item_runs: Vec<usize> = vec![];
+        for word in &self.words[word_start..] {
+            let run = self.runs[word.run];
+            if run.drop_cap_lines > 0 {
+                return
+            }
+            let width = word.right - word.left;
+            match (word.joint, items.last_mut()) {
+                (Joint::InsertedHyphen, _) => continue,
+                (Joint::Split, Some(item)) => {
+                    item.width += width;
+                    continue
+                }
+                _ => {}
+            }
+
+            let previous_run = item_runs.last().map(|&run_index| self.runs[run_index]);
+            let space_before = match (word.joint, previous_run) {
+                (Joint::Space, Some(previous_run)) => previous_run.space_advance,
+                _ => 0.0,
+            };
+            let hyphen_advance = match (word.joint, previous_run) {
+                (Joint::SoftHyphen, Some(previous_run)) => {
+                    previous_run.hyphen.map_or(0.0, |hyphen| hyphen.advance)
+                }
+                _ => 0.0,
+            };
+            items.push(BreakItem {
+                width: width,
+                space_before: space_before,
+                hyphen_advance: hyphen_advance,
+                at_space: word.joint == Joint::Space,
+            });
+            item_runs.push(word.run)
+        }
+        if items.is_empty() {
+            return
+        }
+
+        // The first line keeps the indent it started with. Later lines may be indented past a
+        // drop cap before the usual indent applies.
+        let line_index = paragraph_start.line_count - 1;
+        let drop_cap_line_count = match self.drop_cap {
+            Some(drop_cap) if drop_cap.line_end > line_index + 1 => {
+                cmp::min(drop_cap.line_end - line_index - 1, items.len())
+            }
+            _ => 0,
+        };
+        let mut line_widths = vec![self.text_width() - paragraph_start.line.indent];
+        line_widths.extend((1..(drop_cap_line_count + 2)).map(|line_offset| {
+            self.text_width() - self.indent(false) - self.drop_cap_indent(line_index + line_offset)
+        }));
+        let line_breaks = match self.line_break_strategy {
+            LineBreakStrategy::Balanced => balanced_line_breaks(&items, &line_widths),
+            _ => optimal_line_breaks(&items, &line_widths),
+        };
+
+        self.run_starts.truncate(self.words[word_start].run + 1);
+        let mut retained_layout = self.retained_layout(word_start, paragraph_start.control_count);
+        retained_layout.line_breaks = line_breaks;
+        let direction = self.direction;
+        self.rewind_to_run_start(paragraph_start.clone());
+        self.paragraph_start = Some(Box::new(paragraph_start));
+        self.replay_layout(retained_layout);
+        self.set_direction(direction);
+    }
+
+    /// Switches to the direction of the given run if necessary.
+    #[inline]
+    fn enter_run(&mut self, run_index: usize) {
+        let direction = self.runs[run_index].direction;
+        if direction != self.direction {
+            self.set_direction(direction)
+        }
+    }
+
+    /// Forces a line break in the given run.
+    #[inline]
+    fn add_hard_break(&mut self, run_index: usize) {
+        self.add_control(ControlKind::LineBreak, run_index)
+    }
+
+    /// Advances to the next tab stop in the given run.
+    #[inline]
+    fn add_tab(&mut self, run_index: usize) {
+        self.add_control(ControlKind::Tab, run_index)
+    }
+
+    /// In `WhitespaceMode::Preserve`, advances by one space for each character of the given run
+    /// of whitespace, less the space that a word is always followed by if `after_word` is true.
+    fn add_preserved_spaces(&mut self, whitespace: &str, after_word: bool, run_index: usize) {
+        if self.whitespace_mode != WhitespaceMode::Preserve {
+            return
+        }
+
+        let space_count = whitespace.chars().count().saturating_sub(after_word as usize);
+        for _ in 0..space_count {
+            self.add_control(ControlKind::Space, run_index)
+        }
+    }
+
+    fn add_control(&mut self, kind: ControlKind, run_index: usize) {
+        if self.truncated {
+            return
+        }
+        if kind == ControlKind::LineBreak {
+            self.break_paragraph_optimally()
+        }
+
+        self.enter_run(run_index);
+        self.controls.push(Control {
+            word_index: self.words.len(),
+            run: run_index,
+            kind: kind,
+        });
+
+        // Nothing can follow a line break past the line limit until we know whether there is any
+        // more text to drop.
+        if self.line_limit_reached {
+            return
+        }
+        if kind == ControlKind::LineBreak && self.at_line_limit() {
+            self.line_limit_reached = true;
+            return
+        }
+
+        // The control character itself sits on the current line, so the line must be tall enough
+        // for its font.
+        self.grow_current_line(run_index);
+
+        match kind {
+            ControlKind::LineBreak => {
+                self.break_line(run_index, true);
+                self.start_paragraph()
+            }
+            ControlKind::Tab => self.advance_to_tab_stop(run_index),
+            ControlKind::Space => self.advance_by_space(run_index),
+        }
+    }
+
+    /// Moves the cursor past a preserved space. Like the space that follows a word, it doesn't
+    /// count toward the width of the line if nothing follows it.
+    fn advance_by_space(&mut self, run_index: usize) {
+        let space_advance = self.runs[run_index].space_advance;
+        if self.writing_mode.is_vertical() {
+            self.cursor.y += space_advance
+        } else {
+            self.cursor.x += self.direction.sign() * space_advance
+        }
+        self.trailing_space += space_advance
+    }
+
+    /// Moves the cursor to the next tab stop, wrapping first if that stop is past the end of the
+    /// line.
+    fn advance_to_tab_stop(&mut self, run_index: usize) {
+        // The text after a right or decimal tab stop ends at the next tab.
+        self.tab_column = None;
+        if self.writing_mode.is_vertical() {
+            return
+        }
+
+        // The tab replaces the space that followed the previous word.
+        let (tab_width, trailing_space) = (self.runs[run_index].tab_width, self.trailing_space);
+        let sign = self.direction.sign();
+        self.cursor.x -= sign * trailing_space;
+        self.trailing_space = 0.0;
+
+        // Tab stops are measured from the margin, not the indent, so that columns line up from
+        // one paragraph to the next.
+        let mut tab_stop = match self.next_tab_stop(tab_width) {
+            Some(tab_stop) => tab_stop,
+            None => {
+                // There are no more tab stops, so the tab is just a space.
+                self.cursor.x += sign * trailing_space;
+                self.trailing_space = trailing_space;
+                return
+            }
+        };
+        if tab_stop.position > self.text_width() && self.cursor.x != self.line_start() {
+            if self.at_line_limit() {
+                self.line_limit_reached = true;
+                return
+            }
+            self.break_line(run_index, false);
+            tab_stop = match self.next_tab_stop(tab_width) {
+                Some(tab_stop) => tab_stop,
+                None => return,
+            }
+        }
+
+        if tab_stop.alignment == TabAlign::Left {
+            self.cursor.x = self.page_start() + sign * tab_stop.position;
+            return
+        }
+        let line = *self.current_line();
+        self.tab_column = Some(TabColumn {
+            stop: tab_stop.position,
+            alignment: tab_stop.alignment,
+            line_glyph_start: self.glyph_positions.len() - line.glyph_start,
+            line_word_start: self.words.len() - line.word_start,
+            shift: 0.0,
+            anchored: false,
+        });
+        self.align_tab_column()
+    }
+
+    /// Returns the first tab stop after the cursor: the first one set by `set_tab_stops()` past
+    /// it, or else the next multiple of the given tab width, if it isn't zero.
+    fn next_tab_stop(&self, tab_width: f32) -> Option<TabStop> {
+        let offset = (self.cursor.x - self.page_start()).abs();
+        if let Some(tab_stop) = self.tab_stops.iter().find(|tab_stop| tab_stop.position > offset) {
+            return Some(*tab_stop)
+        }
+        if !(tab_width > 0.0) {
+            return None
+        }
+        Some(TabStop {
+            position: ((offset / tab_width).floor() + 1.0) * tab_width,
+            alignment: TabAlign::Left,
+        })
+    }
+
+    /// Moves the text after a right or decimal tab stop on the current line, along with the
+    /// cursor, so that it ends or has its decimal point at the stop, without moving it before the
+    /// tab.
+    ///
+    /// Once a decimal point has been placed, the text stays where it is.
+    fn align_tab_column(&mut self) {
+        let mut tab_column = match self.tab_column {
+            Some(tab_column) if !tab_column.anchored => tab_column,
+            _ => return,
+        };
+
+        // Glyphs may have been dropped from the line to make room for an ellipsis.
+        let line = *self.current_line();
+        let glyph_start = cmp::min(line.glyph_start + tab_column.line_glyph_start,
+                                   self.glyph_positions.len());
+        let word_start = cmp::min(line.word_start + tab_column.line_word_start, self.words.len());
+
+        let sign = self.direction.sign();
+        let mut anchor = self.cursor.x;
+        if tab_column.alignment == TabAlign::Decimal {
+            let decimal_point = self.words[word_start..].iter().filter_map(|word| {
+                let decimal_point = match self.runs[word.run].decimal_point {
+                    None => return None,
+                    Some(decimal_point) => decimal_point,
+                };
+                (word.glyph_start..word.glyph_end).find(|&glyph_index| {
+                    self.glyph_positions[glyph_index].glyph_id == decimal_point
+                })
+            }).next();
+            if let Some(glyph_index) = decimal_point {
+                // The stop goes where the pen was before the decimal point.
+                anchor = match self.direction {
+                    Direction::LeftToRight => self.pen_x(glyph_index),
+                    Direction::RightToLeft => {
+                        self.pen_x(glyph_index) + self.glyph_advances[glyph_index]
+                    }
+                };
+                tab_column.anchored = true
+            }
+        }
+
+        let unshifted_end = sign * (anchor - self.page_start()) - tab_column.shift;
+        let shift = (tab_column.stop - unshifted_end).max(0.0);
+        let offset = self.pen_snapping.snap(sign * (shift - tab_column.shift));
+        tab_column.shift += sign * offset;
+        self.tab_column = Some(tab_column);
+        if offset == 0.0 {
+            return
+        }
+
+        for glyph_position in &mut self.glyph_positions[glyph_start..] {
+            glyph_position.x += offset
+        }
+        for word in &mut self.words[word_start..] {
+            word.left += offset;
+            word.right += offset;
+        }
+        self.cursor.x += offset
+    }
+
+    /// Lays out a shaped word at the cursor, first moving to the next line if it doesn't fit.
+    ///
+    /// If the word doesn't fit on a line by itself and the break policy of the run allows it, the
+    /// word is split after the last glyph that fits. If the word is followed by a soft hyphen, it
+    /// must also leave room for the hyphen that would be inserted if the line were broken there.
+    fn place_word(&mut self,
+                  glyphs: &[WordGlyph],
+                  run_index: usize,
+                  joint: Joint,
+                  hyphen_follows: bool) {
+        let planned_break = self.planned_break.take();
+        if self.truncated {
+            return
+        }
+
+        self.enter_run(run_index);
+        if self.writing_mode.is_vertical() {
+            self.place_word_vertically(glyphs, run_index, joint);
+            return
+        }
+        let run = self.runs[run_index];
+
+        // Words joined directly to the previous one don't get a space in between.
+        let sign = self.direction.sign();
+        if joint != Joint::Space {
+            self.cursor.x -= sign * self.trailing_space;
+            self.trailing_space = 0.0;
+        }
+
+        // Don't leave a line empty just because the word is too long for it.
+        let total_advance = word_advance(glyphs, run.letter_spacing);
+        let hyphen_advance = match run.hyphen {
+            Some(hyphen) if hyphen_follows => hyphen.advance,
+            _ => 0.0,
+        };
+        let must_break = total_advance > 0.0 &&
+            planned_break.unwrap_or_else(|| {
+                !self.fits_on_line(total_advance + hyphen_advance - self.shrinkable_space())
+            }) &&
+            self.cursor.x != self.line_start();
+        if self.line_limit_reached || (must_break && self.at_line_limit()) {
+            self.truncate(run_index);
+            return
+        }
+        if must_break {
+            if joint == Joint::SoftHyphen {
+                let byte_end = glyphs.first().map_or(0, |glyph| glyph.byte_start);
+                self.insert_hyphen(run_index, byte_end)
+            }
+            self.break_line(run_index, false);
+        }
+
+        if run.break_policy == BreakPolicy::Anywhere && glyphs.len() > 1 &&
+                !self.fits_on_line(total_advance) {
+            // Always keep at least one cluster on the line so that we make progress.
+            let split_index = match self.fitting_glyph_count(glyphs, run.letter_spacing) {
+                0 => (1..glyphs.len()).find(|&glyph_index| starts_cluster(glyphs, glyph_index))
+                                      .unwrap_or(glyphs.len()),
+                glyph_count => glyph_count,
+            };
+            if split_index < glyphs.len() {
+                self.lay_out_clipped_glyphs(&glyphs[..split_index], run_index, joint);
+                self.place_word(&glyphs[split_index..], run_index, Joint::Split, hyphen_follows);
+                return
+            }
+        }
+
+        self.lay_out_clipped_glyphs(glyphs, run_index, joint)
+    }
+
+    /// Like `lay_out_glyphs()`, but with `OverflowPolicy::Clip`, leaves out the glyphs that would
+    /// extend past the end margin.
+    fn lay_out_clipped_glyphs(&mut self, glyphs: &[WordGlyph], run_index: usize, joint: Joint) {
+        let letter_spacing = self.runs[run_index].letter_spacing;
+        if self.overflow_policy == OverflowPolicy::Allow ||
+                self.fits_on_line(word_advance(glyphs, letter_spacing)) {
+            self.lay_out_glyphs(glyphs, run_index, joint);
+            return
+        }
+
+        let glyph_count = self.fitting_glyph_count(glyphs, letter_spacing);
+        if glyph_count > 0 {
+            self.lay_out_glyphs(&glyphs[..glyph_count], run_index, joint)
+        }
+    }
+
+    /// Returns the number of glyphs at the start of the given word that fit on the current line,
+    /// rounded down to a whole number of clusters.
+    ///
+    /// Grapheme clusters are kept together, as are zero-advance glyphs, such as combining marks,
+    /// and the glyph before them.
+    fn fitting_glyph_count(&self, glyphs: &[WordGlyph], letter_spacing: f32) -> usize {
+        let (mut advance, mut spacing, mut cluster_start) = (0.0, 0.0, 0);
+        for (glyph_index, glyph) in glyphs.iter().enumerate() {
+            if starts_cluster(glyphs, glyph_index) {
+                cluster_start = glyph_index
+            }
+            if glyph.advance == 0.0 {
+                continue
+            }
+            advance += spacing + glyph.advance;
+            spacing = letter_spacing;
+            if !self.fits_on_line(advance) {
+                return cluster_start
+            }
+        }
+        glyphs.len()
+    }
+
+    /// Ends the current line with a hyphen in place of the soft hyphen that ends at the given byte
+    /// offset, if the font of the given run has a hyphen.
+    fn insert_hyphen(&mut self, run_index: usize, byte_end: usize) {
+        let hyphen = match self.runs[run_index].hyphen {
+            None => return,
+            Some(hyphen) => hyphen,
+        };
+        // The soft hyphen was part of the word just placed, so it has the level of that word's
+        // last glyph.
+        let hyphen = WordGlyph {
+            byte_start: byte_end - SOFT_HYPHEN.len_utf8(),
+            byte_end: byte_end,
+            level: self.glyph_levels.last().cloned().unwrap_or(None),
+            ..hyphen
+        };
+        self.lay_out_glyphs(&[hyphen], run_index, Joint::InsertedHyphen)
+    }
+
+    /// Lays out a shaped word at the cursor, followed by a space.
+    fn lay_out_glyphs(&mut self, glyphs: &[WordGlyph], run_index: usize, joint: Joint) {
+        let run = self.runs[run_index];
+        self.grow_current_line(run_index);
+        self.push_word(glyphs, run_index, joint);
+        self.align_tab_column();
+
+        let sign = self.direction.sign();
+        self.cursor.x += sign * run.space_advance;
+        self.trailing_space = run.space_advance
+    }
+
+    /// Places the glyphs of a shaped word on the current baseline, starting at the cursor, and
+    /// moves the cursor past them.
+    fn push_word(&mut self, glyphs: &[WordGlyph], run_index: usize, joint: Joint) {
+        let run = self.runs[run_index];
+        let (word_glyph_start, word_start_x) = (self.glyph_positions.len(), self.cursor.x);
+
+        // Glyphs are shaped in logical order, so in right-to-left text each glyph is placed to the
+        // left of the previous one.
+        let sign = self.direction.sign();
+        let page = self.current_line().page as u32;
+        for (glyph_index, glyph) in glyphs.iter().enumerate() {
+            if glyph_index > 0 && glyph.advance != 0.0 {
+                self.cursor.x += sign * run.letter_spacing
+            }
+
+            let x = match self.direction {
+                Direction::LeftToRight => self.cursor.x,
+                Direction::RightToLeft => self.cursor.x - glyph.advance,
+            };
+
+            self.glyph_positions.push(GlyphPosition {
+                x: self.pen_snapping.snap(x + glyph.offset.x),
+                y: self.cursor.y - run.baseline_shift + glyph.offset.y,
+                x_offset: glyph.offset.x,
+                y_offset: glyph.offset.y,
+                glyph_id: glyph.glyph_id,
+                style: run.style,
+                cluster: glyph.byte_start as u32,
+                page: page,
+                scale: run.scale,
+            });
+            self.glyph_advances.push(glyph.advance);
+            self.glyph_offsets.push(glyph.offset);
+            self.glyph_byte_ranges.push(glyph.byte_start..glyph.byte_end);
+            self.glyph_levels.push(glyph.level);
+            self.cursor.x += sign * glyph.advance;
+        }
+
+        self.words.push(Word {
+            glyph_start: word_glyph_start,
+            glyph_end: self.glyph_positions.len(),
+            left: word_start_x.min(self.cursor.x),
+            right: word_start_x.max(self.cursor.x),
+            run: run_index,
+            joint: joint,
+        })
+    }
+
+    /// Lays out a shaped drop cap at the start of a line, first starting a new paragraph if the
+    /// current line isn't empty, and indents the lines that it spans past it.
+    fn place_drop_cap(&mut self, glyphs: &[WordGlyph], run_index: usize) {
+        if self.truncated {
+            return
+        }
+
+        self.enter_run(run_index);
+        if self.writing_mode.is_vertical() {
+            self.place_word(glyphs, run_index, Joint::Space, false);
+            return
+        }
+
+        let at_line_start = self.cursor.x == self.line_start();
+        if self.line_limit_reached || (!at_line_start && self.at_line_limit()) {
+            self.truncate(run_index);
+            return
+        }
+        if !at_line_start {
+            self.break_line(run_index, true);
+        }
+
+        // The drop cap hangs below the first line, so it doesn't grow it.
+        let (word_index, line_index) = (self.words.len(), self.lines.len() - 1);
+        self.push_word(glyphs, run_index, Joint::Space);
+        let advance = (self.cursor.x - self.line_start()).abs();
+        self.drop_cap = Some(DropCap {
+            word: Some(word_index),
+            line: line_index,
+            line_end: line_index + self.runs[run_index].drop_cap_lines,
+            advance: advance,
+        });
+        self.current_line_mut().indent += advance;
+        self.move_to_line_start();
+        self.trailing_space = 0.0;
+        self.position_drop_cap();
+
+        // The text after the drop cap can be broken into lines around it.
+        self.start_paragraph()
+    }
+
+    /// Moves the drop cap, if there is one, onto the baseline of the last line that it spans so
+    /// far.
+    ///
+    /// Lines that have been moved to a later page don't count.
+    fn position_drop_cap(&mut self) {
+        let (word_index, drop_cap) = match self.drop_cap {
+            Some(drop_cap) => match drop_cap.word {
+                Some(word_index) => (word_index, drop_cap),
+                None => return,
+            },
+            None => return,
+        };
+
+        let page = self.lines[drop_cap.line].page;
+        let line_end = cmp::min(drop_cap.line_end, self.lines.len());
+        let baseline = self.lines[drop_cap.line..line_end]
+                           .iter()
+                           .rev()
+                           .find(|line| line.page == page)
+                           .map_or(self.lines[drop_cap.line].baseline, |line| line.baseline);
+        let word = self.words[word_index];
+        let baseline_shift = self.runs[word.run].baseline_shift;
+        for glyph_index in word.glyph_start..word.glyph_end {
+            self.glyph_positions[glyph_index].y = baseline - baseline_shift +
+                self.glyph_offsets[glyph_index].y
+        }
+    }
+
+    /// Lays out a shaped word down the current column, first moving to the next column if it
+    /// doesn't fit.
+    ///
+    /// Words are never split mid-word in vertical text, and text past the line limit is dropped
+    /// without an ellipsis.
+    fn place_word_vertically(&mut self, glyphs: &[WordGlyph], run_index: usize, joint: Joint) {
+        let run = self.runs[run_index];
+        if joint != Joint::Space {
+            self.cursor.y -= self.trailing_space;
+            self.trailing_space = 0.0;
+        }
+
+        let total_advance = word_advance(glyphs, run.letter_spacing);
+        let must_break = self.cursor.y + total_advance > self.page_width && self.cursor.y != 0.0;
+        if self.line_limit_reached || (must_break && self.at_line_limit()) {
+            self.truncated = true;
+            return
+        }
+        if must_break {
+            self.break_line(run_index, false);
+        }
+
+        self.grow_current_line(run_index);
+        let (word_glyph_start, word_start_y) = (self.glyph_positions.len(), self.cursor.y);
+        for (glyph_index, glyph) in glyphs.iter().enumerate() {
+            if glyph_index > 0 && glyph.advance != 0.0 {
+                self.cursor.y += run.letter_spacing
+            }
+
+            self.glyph_positions.push(GlyphPosition {
+                x: self.pen_snapping.snap(self.cursor.x + glyph.offset.x),
+                y: self.cursor.y + glyph.offset.y,
+                x_offset: glyph.offset.x,
+                y_offset: glyph.offset.y,
+                glyph_id: glyph.glyph_id,
+                style: run.style,
+                cluster: glyph.byte_start as u32,
+                page: 0,
+                scale: run.scale,
+            });
+            self.glyph_advances.push(glyph.advance);
+            self.glyph_offsets.push(glyph.offset);
+            self.glyph_byte_ranges.push(glyph.byte_start..glyph.byte_end);
+            self.glyph_levels.push(glyph.level);
+            self.cursor.y += glyph.advance;
+        }
+
+        self.words.push(Word {
+            glyph_start: word_glyph_start,
+            glyph_end: self.glyph_positions.len(),
+            left: word_start_y,
+            right: self.cursor.y,
+            run: run_index,
+            joint: joint,
+        });
+
+        self.cursor.y += run.space_advance;
+        self.trailing_space = run.space_advance
+    }
+
+    /// Adds the characters of the given string that were shaped as the missing glyph to
+    /// `missing_codepoints`.
+    fn record_missing_codepoints(&mut self, string: &str, glyphs: &[WordGlyph]) {
+        for glyph in glyphs.iter().filter(|glyph| glyph.glyph_id == 0) {
+            let ch = match string[glyph.byte_start..].chars().next() {
+                None => continue,
+                Some(ch) => ch,
+            };
+            if let Err(index) = self.missing_codepoints.binary_search(&ch) {
+                self.missing_codepoints.insert(index, ch)
+            }
+        }
+    }
+
+    /// Discards all cached codepoint-to-glyph mappings.
+    ///
+    /// The typesetter remembers the glyph mapping of every font passed to `add_text()`, by
+    /// `Font::id()`. Call this to free that memory when switching to different fonts.
+    pub fn clear_glyph_mapping_cache(&mut self) {
+        self.glyph_mapping_cache.clear()
+    }
+
+    /// Ensures that the glyph mapping cache for the given font covers every character in the
+    /// string, as well as the space, ellipsis, and hyphen characters, and returns the index of its
+    /// cache entry.
+    ///
+    /// If the font's character map can't be read, the cache entry is left as it was.
+    fn cache_glyph_mapping(&mut self, font: &Font, string: &str)
+                           -> Result<usize, TypesetterError> {
+        let font_id = font.id();
+        let cache_index = match self.glyph_mapping_cache
+                                    .iter()
+                                    .position(|entry| entry.font_id == font_id) {
+            Some(cache_index) => cache_index,
+            None => {
+                self.glyph_mapping_cache.push(CachedGlyphMapping {
+                    font_id: font_id,
+                    chars: vec![],
+                    glyph_mapping: GlyphMapping::new(),
+                    fixed_advance: None,
+                    space_glyph: None,
+                });
+                self.glyph_mapping_cache.len() - 1
+            }
+        };
+
+        let entry = &mut self.glyph_mapping_cache[cache_index];
+        let missing_chars: Vec<char> = string.chars()
+                                             .chain(Some(' '))
+                                             .chain(Some(ELLIPSIS))
+                                             .chain(Some(HYPHEN))
+                                             .chain(Some(DECIMAL_POINT))
+                                             .filter(|ch| entry.chars.binary_search(ch).is_err())
+                                             .collect();
+        if !missing_chars.is_empty() {
+            let mut chars = entry.chars.clone();
+            chars.extend_from_slice(&missing_chars);
+            chars.sort();
+            chars.dedup();
+            let codepoint_ranges = CodepointRanges::from_sorted_chars(&chars);
+            entry.glyph_mapping =
+                try!(font.glyph_mapping_for_codepoint_ranges(&codepoint_ranges.ranges)
+                         .map_err(TypesetterError::GlyphMappingFailed));
+            entry.chars = chars;
+        }
+
+        if entry.fixed_advance.is_none() && font.is_fixed_pitch() {
+            let space_glyph = entry.glyph_mapping.glyph_for(' ' as u32).unwrap_or(0);
+            entry.fixed_advance = font.metrics_for_glyph(space_glyph)
+                                      .ok()
+                                      .map(|metrics| metrics.advance_width);
+        }
+
+        Ok(cache_index)
+    }
+
+    /// Returns true if the current line is the last one allowed by `set_max_lines()`.
+    #[inline]
+    fn at_line_limit(&self) -> bool {
+        self.max_lines.map_or(false, |max_lines| self.lines.len() >= max_lines)
+    }
+
+    /// Stops laying out text, replacing glyphs at the end of the current line with an ellipsis as
+    /// necessary to make it fit.
+    ///
+    /// The ellipsis comes from the font of the last word on the line, or from that of the given run
+    /// if the line is empty.
+    fn truncate(&mut self, run_index: usize) {
+        self.truncated = true;
+
+        let line_word_start = self.current_line().word_start;
+        let ellipsis_run = match self.words.last() {
+            Some(word) if self.words.len() > line_word_start => word.run,
+            _ => run_index,
+        };
+        let ellipsis = match self.runs[ellipsis_run].ellipsis {
+            Some(ellipsis) => ellipsis,
+            None => return,
+        };
+
+        // The ellipsis replaces the space that followed the last word.
+        let sign = self.direction.sign();
+        self.cursor.x -= sign * self.trailing_space;
+        self.trailing_space = 0.0;
+
+        let line_glyph_start = self.current_line().glyph_start;
+        while !self.fits_on_line(ellipsis.advance) &&
+                self.glyph_positions.len() > line_glyph_start {
+            let glyph_position = self.glyph_positions.pop().unwrap();
+            let advance = self.glyph_advances.pop().unwrap();
+            self.glyph_offsets.pop();
+            self.glyph_byte_ranges.pop();
+            self.glyph_levels.pop();
+            let pen_x = glyph_position.x - glyph_position.x_offset;
+            self.cursor.x = match self.direction {
+                Direction::LeftToRight => pen_x,
+                Direction::RightToLeft => pen_x + advance,
+            };
+
+            let glyph_count = self.glyph_positions.len();
+            let word_is_empty = {
+                let word = self.words.last_mut().unwrap();
+                word.glyph_end = glyph_count;
+                match self.direction {
+                    Direction::LeftToRight => word.right = self.cursor.x,
+                    Direction::RightToLeft => word.left = self.cursor.x,
+                }
+                word.glyph_start == word.glyph_end
+            };
+            if word_is_empty {
+                self.words.pop();
+            }
+        }
+
+        let (glyph_start, start_x) = (self.glyph_positions.len(), self.cursor.x);
+        let x = match self.direction {
+            Direction::LeftToRight => self.cursor.x,
+            Direction::RightToLeft => self.cursor.x - ellipsis.advance,
+        };
+        self.glyph_positions.push(GlyphPosition {
+            x: self.pen_snapping.snap(x),
+            y: self.cursor.y - self.runs[ellipsis_run].baseline_shift,
+            x_offset: 0.0,
+            y_offset: 0.0,
+            glyph_id: ellipsis.glyph_id,
+            style: self.runs[ellipsis_run].style,
+            cluster: 0,
+            page: self.current_line().page as u32,
+            scale: self.runs[ellipsis_run].scale,
+        });
+        self.glyph_advances.push(ellipsis.advance);
+        self.glyph_offsets.push(ellipsis.offset);
+        self.glyph_byte_ranges.push(0..0);
+        self.glyph_levels.push(None);
+        self.cursor.x += sign * ellipsis.advance;
+
+        self.ellipsis_word = Some(self.words.len());
+        self.words.push(Word {
+            glyph_start: glyph_start,
+            glyph_end: self.glyph_positions.len(),
+            left: start_x.min(self.cursor.x),
+            right: start_x.max(self.cursor.x),
+            run: ellipsis_run,
+            joint: Joint::Direct,
+        })
+    }
+
+    /// Finishes the current line and moves the cursor to the start of the next one, using the line
+    /// spacing and font metrics of the given run.
+    fn break_line(&mut self, run_index: usize, ends_paragraph: bool) {
+        let run = self.runs[run_index];
+        self.align_line(ends_paragraph);
+
+        // If the fonts on this line descend further than the font of this run, make room for
+        // them.
+        let extra_descent = (self.current_line().descent - run.descent).max(0.0);
+        let baseline = self.current_line().baseline +
+            self.writing_mode.column_sign() * (run.line_spacing + extra_descent);
+        let page = self.current_line().page;
+        self.line_offset = 0.0;
+        self.line_stretch = 0.0;
+        self.line_reordered = false;
+        self.trailing_space = 0.0;
+        self.tab_column = None;
+        if self.writing_mode.is_vertical() {
+            self.lines.push(Line {
+                glyph_start: self.glyph_positions.len(),
+                word_start: self.words.len(),
+                baseline: baseline,
+                ascent: run.ascent,
+                descent: run.descent,
+                line_gap: run.line_gap,
+                starts_paragraph: ends_paragraph,
+                indent: 0.0,
+                start: 0.0,
+                direction: self.direction,
+                page: page,
+            });
+            self.cursor = Point2D::new(baseline, 0.0);
+            return
+        }
+
+        self.cursor.y = baseline;
+        let indent = self.indent(ends_paragraph) + self.drop_cap_indent(self.lines.len());
+        self.lines.push(Line {
+            glyph_start: self.glyph_positions.len(),
+            word_start: self.words.len(),
+            baseline: self.cursor.y,
+            ascent: run.ascent,
+            descent: run.descent,
+            line_gap: run.line_gap,
+            starts_paragraph: ends_paragraph,
+            indent: indent,
+            start: 0.0,
+            direction: self.direction,
+            page: page,
+        });
+        self.move_to_line_start();
+        self.fit_current_line_on_page();
+        self.position_drop_cap()
+    }
+
+    /// Makes room on the current line for text in the given run.
+    ///
+    /// If the font of the run ascends further than the fonts on the line so far, the baseline of
+    /// the line, along with everything already on it, moves down to make room. In vertical text,
+    /// the column moves away from the one before it instead.
+    ///
+    /// A baseline shift raises the run's ascent and lowers its descent by the same amount.
+    fn grow_current_line(&mut self, run_index: usize) {
+        let run = self.runs[run_index];
+        let (ascent, descent) = (run.ascent + run.baseline_shift, run.descent - run.baseline_shift);
+        let shift = ascent - self.current_line().ascent;
+        if shift > 0.0 {
+            let glyph_start = self.current_line().glyph_start;
+            if self.writing_mode.is_vertical() {
+                let shift = self.writing_mode.column_sign() * shift;
+                for glyph_position in &mut self.glyph_positions[glyph_start..] {
+                    glyph_position.x += shift
+                }
+                self.current_line_mut().baseline += shift;
+                self.cursor.x += shift;
+            } else {
+                for glyph_position in &mut self.glyph_positions[glyph_start..] {
+                    glyph_position.y += shift
+                }
+                self.current_line_mut().baseline += shift;
+                self.cursor.y += shift;
+            }
+        }
+
+        self.current_line_mut().grow(ascent, descent, run.line_gap);
+        self.fit_current_line_on_page();
+        self.position_drop_cap()
+    }
+
+    /// Moves the current line, along with everything on it, to the top of the next page if it
+    /// extends past the bottom of its page and isn't the first line on that page.
+    fn fit_current_line_on_page(&mut self) {
+        let page_height = match self.page_height {
+            Some(page_height) if !self.writing_mode.is_vertical() => page_height,
+            _ => return,
+        };
+        let line = *self.current_line();
+        let line_count = self.lines.len();
+        let first_on_page = line_count == 1 || self.lines[line_count - 2].page != line.page;
+        let page_bottom = self.origin.y + (line.page + 1) as f32 * page_height;
+        if first_on_page || line.baseline + line.descent <= page_bottom {
+            return
+        }
+
+        let shift = page_bottom + line.ascent - line.baseline;
+        for glyph_position in &mut self.glyph_positions[line.glyph_start..] {
+            glyph_position.y += shift;
+            glyph_position.page += 1
+        }
+        let line = self.current_line_mut();
+        line.baseline += shift;
+        line.page += 1;
+        self.cursor.y += shift;
+    }
+
+    /// Moves the glyphs on the current line into place according to the alignment, and then into
+    /// visual order.
+    ///
+    /// Justified lines that end a paragraph are left flush with the edge at which they start.
+    fn align_line(&mut self, ends_paragraph: bool) {
+        self.offset_line_for_alignment(ends_paragraph);
+        if !self.line_reordered {
+            self.reorder_current_line();
+            self.line_reordered = true
+        }
+    }
+
+    /// Moves the glyphs on the current line, still in logical order, according to the alignment.
+    fn offset_line_for_alignment(&mut self, ends_paragraph: bool) {
+        // The cursor is past the space that follows the last word, but that space shouldn't push
+        // the line away from the edge that it's aligned to.
+        let line_width = (self.cursor.x - self.page_start()).abs() - self.trailing_space;
+        let slack = self.text_width() - line_width;
+        if !slack.is_finite() || self.writing_mode.is_vertical() {
+            return
+        }
+
+        let sign = self.direction.sign();
+        let offset = match (self.alignment(), self.direction) {
+            (Alignment::Left, Direction::LeftToRight) |
+            (Alignment::Right, Direction::RightToLeft) => 0.0,
+            (Alignment::Left, Direction::RightToLeft) => -slack,
+            (Alignment::Right, Direction::LeftToRight) => slack,
+            (Alignment::Center, _) => sign * slack * 0.5,
+            (Alignment::Justify, _) => {
+                // Lines squeezed to make room for their last word must be justified to fit, but
+                // a line that ends its paragraph is otherwise left flush with its start, as is
+                // one whose spaces would stretch too far.
+                let (gap_count, space_width) = self.justifiable_spaces();
+                let (min_space, max_space) = self.justification_limits;
+                let stretches = if ends_paragraph {
+                    slack < 0.0 && min_space < 1.0
+                } else {
+                    max_space == f32::INFINITY || slack <= (max_space - 1.0) * space_width
+                };
+                if stretches && gap_count > 0 {
+                    // Spread the slack evenly between the words.
+                    let extra_space = sign * slack / gap_count as f32;
+                    self.stretch_current_line(extra_space, false);
+                    self.line_stretch = extra_space
+                }
+                return
+            }
+        };
+
+        // Keep snapped glyphs on the grid.
+        let offset = self.pen_snapping.snap(offset);
+        self.offset_current_line(offset);
+        self.line_offset = offset
+    }
+
+    /// Undoes the alignment of the current line.
+    fn unalign_line(&mut self) {
+        let offset = self.line_offset;
+        self.offset_current_line(-offset);
+        self.line_offset = 0.0;
+
+        // Reordering only mirrors glyphs, so doing it again puts them back in logical order.
+        if self.line_reordered {
+            self.reorder_current_line();
+            self.line_reordered = false
+        }
+
+        let extra_space = self.line_stretch;
+        if extra_space != 0.0 {
+            self.stretch_current_line(extra_space, true);
+            self.line_stretch = 0.0
+        }
+    }
+
+    /// Returns the index of the first word on the current line that justification moves.
+    ///
+    /// Only spaces are stretched, not the joints between pieces of hyphenated words and the like,
+    /// nor the gap after a drop cap.
+    fn justified_word_start(&self) -> usize {
+        let word_start = self.current_line().word_start;
+        if self.drop_cap.and_then(|drop_cap| drop_cap.word) == Some(word_start) {
+            word_start + 1
+        } else {
+            word_start
+        }
+    }
+
+    /// Returns the number of spaces between words on the current line that justification
+    /// stretches, and the sum of their natural advances, in pixels.
+    fn justifiable_spaces(&self) -> (usize, f32) {
+        let words = &self.words[self.justified_word_start()..];
+        let (mut gap_count, mut space_width) = (0, 0.0);
+        for (previous_word, word) in words.iter().zip(words.iter().skip(1)) {
+            if word.joint == Joint::Space {
+                gap_count += 1;
+                space_width += self.runs[previous_word.run].space_advance
+            }
+        }
+        (gap_count, space_width)
+    }
+
+    /// Moves the words on the current line, still in logical order, apart by the given extra
+    /// space at each space between them, or back together if `undo` is true.
+    fn stretch_current_line(&mut self, extra_space: f32, undo: bool) {
+        let word_start = self.justified_word_start();
+        let mut gap_index = 0;
+        for (word_index, word) in self.words[word_start..].iter_mut().enumerate() {
+            if word_index > 0 && word.joint == Joint::Space {
+                gap_index += 1
+            }
+            let mut word_offset = self.pen_snapping.snap(extra_space * gap_index as f32);
+            if undo {
+                word_offset = -word_offset
+            }
+            word.left += word_offset;
+            word.right += word_offset;
+            for glyph_position in &mut self.glyph_positions[word.glyph_start..word.glyph_end] {
+                glyph_position.x += word_offset
+            }
+        }
+    }
+
+    /// Returns how far, in pixels, the spaces on the current line, including the one before the
+    /// cursor, may shrink under `Alignment::Justify` to make room for another word. See
+    /// `set_justification_limits()`.
+    fn shrinkable_space(&self) -> f32 {
+        let min_space = self.justification_limits.0;
+        if self.alignment() != Alignment::Justify || !(min_space < 1.0) ||
+                self.writing_mode.is_vertical() {
+            return 0.0
+        }
+        let (_, space_width) = self.justifiable_spaces();
+        (space_width + self.trailing_space) * (1.0 - min_space)
+    }
+
+    /// Moves the glyphs on the current line from logical order into visual order according to
+    /// their bidi levels, as described in `add_text_with_levels()`. Lines without levels are left
+    /// alone.
+    ///
+    /// Each reversal mirrors a sequence of glyphs within the extent that they cover. The line was
+    /// laid out in the paragraph direction, so for right-to-left paragraphs, which were already
+    /// reversed as a whole, the whole line is mirrored once more at the end.
+    fn reorder_current_line(&mut self) {
+        let glyph_start = self.current_line().glyph_start;
+        if self.writing_mode.is_vertical() ||
+                self.glyph_levels[glyph_start..].iter().all(|level| level.is_none()) {
+            return
+        }
+
+        let paragraph_level = self.direction.level();
+        let levels: Vec<u8> = self.glyph_levels[glyph_start..]
+                                  .iter()
+                                  .map(|level| level.unwrap_or(paragraph_level))
+                                  .collect();
+        let highest_level = levels.iter().cloned().max().unwrap_or(0);
+        let lowest_odd_level = levels.iter().cloned().min().unwrap_or(0) | 1;
+
+        let mut level = highest_level;
+        while level >= lowest_odd_level {
+            let mut start = 0;
+            while start < levels.len() {
+                if levels[start] < level {
+                    start += 1;
+                    continue
+                }
+                let end = levels[start..].iter()
+                                         .position(|&other_level| other_level < level)
+                                         .map_or(levels.len(), |length| start + length);
+                self.mirror_glyphs(glyph_start + start..glyph_start + end);
+                start = end
+            }
+            level -= 1
+        }
+
+        if self.direction == Direction::RightToLeft {
+            let glyph_end = self.glyph_positions.len();
+            self.mirror_glyphs(glyph_start..glyph_end)
+        }
+    }
+
+    /// Reverses the horizontal order of the given glyphs within the extent of their advance
+    /// boxes, along with the words made up entirely of them.
+    ///
+    /// Words only partly made up of the glyphs keep their extents.
+    fn mirror_glyphs(&mut self, glyph_range: Range<usize>) {
+        let (mut left, mut right) = (f32::INFINITY, f32::NEG_INFINITY);
+        for glyph_index in glyph_range.clone() {
+            let x = self.pen_x(glyph_index);
+            left = left.min(x);
+            right = right.max(x + self.glyph_advances[glyph_index]);
+        }
+
+        for glyph_index in glyph_range.clone() {
+            let glyph_position = &mut self.glyph_positions[glyph_index];
+            let pen_x = glyph_position.x - glyph_position.x_offset;
+            glyph_position.x = left + right - pen_x - self.glyph_advances[glyph_index] +
+                glyph_position.x_offset;
+        }
+
+        let word_start = self.current_line().word_start;
+        for word in &mut self.words[word_start..] {
+            if word.glyph_start >= glyph_range.start && word.glyph_end <= glyph_range.end {
+                let (word_left, word_right) = (word.left, word.right);
+                word.left = left + right - word_right;
+                word.right = left + right - word_left;
+            }
+        }
+    }
+
+    /// Moves the glyphs on the current line horizontally by the given amount.
+    fn offset_current_line(&mut self, offset: f32) {
+        if offset == 0.0 {
+            return
+        }
+
+        let (glyph_start, word_start) = (self.current_line().glyph_start,
+                                         self.current_line().word_start);
+        for glyph_position in &mut self.glyph_positions[glyph_start..] {
+            glyph_position.x += offset
+        }
+        for word in &mut self.words[word_start..] {
+            word.left += offset;
+            word.right += offset;
+        }
+    }
+
+    #[inline]
+    fn current_line(&self) -> &Line {
+        self.lines.last().unwrap()
+    }
+
+    #[inline]
+    fn current_line_mut(&mut self) -> &mut Line {
+        self.lines.last_mut().unwrap()
+    }
+
+    /// Returns the range of indices into `glyph_positions` of the glyphs on the given line.
+    fn line_glyph_range(&self, line_index: usize) -> Range<usize> {
+        let glyph_end = match self.lines.get(line_index + 1) {
+            Some(next_line) => next_line.glyph_start,
+            None => self.glyph_positions.len(),
+        };
+        self.lines[line_index].glyph_start..glyph_end
+    }
+
+    /// Returns the range of indices into `words` of the words on the given line.
+    fn line_word_range(&self, line_index: usize) -> Range<usize> {
+        let word_end = match self.lines.get(line_index + 1) {
+            Some(next_line) => next_line.word_start,
+            None => self.words.len(),
+        };
+        self.lines[line_index].word_start..word_end
+    }
+
+    /// Returns the x position of the margin at which lines start in the current direction.
+    #[inline]
+    fn page_start(&self) -> f32 {
+        match self.direction {
+            Direction::LeftToRight => self.origin.x + self.left_margin,
+            Direction::RightToLeft => self.origin.x + self.page_width - self.right_margin,
+        }
+    }
+
+    /// Returns the x position at which the current line starts, taking its indent into account.
+    #[inline]
+    fn line_start(&self) -> f32 {
+        self.page_start() + self.direction.sign() * self.current_line().indent
+    }
+
+    /// Moves the cursor to the start of the current line, recording on the line where that is.
+    fn move_to_line_start(&mut self) {
+        let (start, direction) = (self.line_start(), self.direction);
+        let line = self.current_line_mut();
+        line.start = start;
+        line.direction = direction;
+        self.cursor.x = start
+    }
+
+    /// Returns true if a word of the given advance fits on the current line.
+    ///
+    /// Text after a right or decimal tab stop can move back toward the tab to make room.
+    #[inline]
+    fn fits_on_line(&self, advance: f32) -> bool {
+        let advance = match self.tab_column {
+            Some(tab_column) if !tab_column.anchored => advance - tab_column.shift,
+            _ => advance,
+        };
+        match self.direction {
+            Direction::LeftToRight => {
+                self.cursor.x + advance <= self.origin.x + self.page_width - self.right_margin
+            }
+            Direction::RightToLeft => self.cursor.x - advance >= self.origin.x + self.left_margin,
+        }
+    }
+
+    pub fn glyph_positions(&self) -> &[GlyphPosition] {
+        &self.glyph_positions
+    }
+
+    /// Returns the advance of each glyph in `glyph_positions`, in pixels, as it was laid out.
+    ///
+    /// This is the distance that the glyph moved the pen, not counting letter spacing or the
+    /// stretching of justified lines. In the vertical writing modes, it is the vertical advance.
+    #[inline]
+    pub fn glyph_advances(&self) -> &[f32] {
+        &self.glyph_advances
+    }
+
+    /// Returns the position of the pen, in pixels, where the next glyph would be placed on the
+    /// current line.
+    ///
+    /// In horizontal text, the y coordinate is the baseline of the current line, and the x
+    /// coordinate is the left edge of the next glyph, or its right edge in right-to-left text. In
+    /// the vertical writing modes, it is the center of the current column and the top of the next
+    /// glyph instead. The pen rests past the space that follows the last word, and it is moved
+    /// along with the current line when that line is aligned, so it lines up with the glyphs
+    /// already laid out. Adding more text to the line may realign it.
+    #[inline]
+    pub fn cursor_position(&self) -> Point2D<f32> {
+        Point2D::new(self.cursor.x + self.line_offset, self.cursor.y)
+    }
+
+    /// Returns the text that produced the glyph at the given index into `glyph_positions`.
+    ///
+    /// The byte range covers the extended grapheme cluster that the glyph's character is part of,
+    /// so a base character and the combining marks on it share a range. Returns `None` if there
+    /// is no such glyph or if the glyph is an ellipsis inserted by `set_max_lines()`.
+    pub fn glyph_source(&self, glyph_index: usize) -> Option<GlyphSource> {
+        if glyph_index >= self.glyph_positions.len() {
+            return None
+        }
+        if let Some(ellipsis_word) = self.ellipsis_word {
+            if glyph_index >= self.words[ellipsis_word].glyph_start {
+                return None
+            }
+        }
+
+        let run_index = self.words[self.word_index_for_glyph(glyph_index)].run;
+        Some(GlyphSource {
+            text_index: self.runs[run_index].text_index,
+            byte_range: self.glyph_byte_ranges[glyph_index].clone(),
+        })
+    }
+
+    /// Returns the range of indices into `glyph_positions` of each word laid out so far, as a
+    /// start index and an end index one past its last glyph, in the order that the text was
+    /// added, for selecting a word by double-clicking it.
+    ///
+    /// Words are the pieces of text between whitespace, tabs, and line breaks. A word broken
+    /// across lines, at a hyphen, a soft hyphen, or because it was too long for a line, is still
+    /// one word, and a hyphen inserted at the break belongs to it. Punctuation is part of the
+    /// word it touches. An ellipsis inserted by `set_max_lines()` is a word of its own.
+    pub fn word_ranges(&self) -> Vec<(usize, usize)> {
+        let mut word_ranges: Vec<(usize, usize)> = vec![];
+        for (word_index, word) in self.words.iter().enumerate() {
+            if word.glyph_start == word.glyph_end {
+                continue
+            }
+            let separate = word.joint == Joint::Space || self.ellipsis_word == Some(word_index) ||
+                self.ellipsis_word == Some(word_index.wrapping_sub(1));
+            match word_ranges.last_mut() {
+                Some(word_range) if !separate => word_range.1 = word.glyph_end,
+                _ => word_ranges.push((word.glyph_start, word.glyph_end)),
+            }
+        }
+        word_ranges
+    }
+
+    /// Returns the index into `words` of the word containing the given glyph.
+    fn word_index_for_glyph(&self, glyph_index: usize) -> usize {
+        match self.words.binary_search_by(|word| word.glyph_start.cmp(&glyph_index)) {
+            Ok(word_index) => word_index,
+            Err(word_index) => word_index - 1,
+        }
+    }
+
+    /// Returns an iterator over the lines laid out so far, in order from top to bottom.
+    ///
+    /// The line that the cursor is on is always included, even if it is empty.
+    #[inline]
+    pub fn lines(&self) -> Lines {
+        Lines {
+            typesetter: self,
+            line_index: 0,
+        }
+    }
+
+    /// Returns the number of lines laid out so far, without visiting them.
+    ///
+    /// As with `lines()`, the line that the cursor is on is always counted, so this is never zero.
+    /// Blank lines produced by consecutive newlines count too.
+    #[inline]
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Returns the line with the given index, counting from the top.
+    pub fn line(&self, line_index: usize) -> Option<LayoutLine> {
+        let line = match self.lines.get(line_index) {
+            None => return None,
+            Some(line) => line,
+        };
+
+        let words = &self.words[self.line_word_range(line_index)];
+        let (left, right) = if words.is_empty() {
+            (0.0, 0.0)
+        } else {
+            (words.iter().map(|word| word.left).fold(f32::INFINITY, f32::min),
+             words.iter().map(|word| word.right).fold(f32::NEG_INFINITY, f32::max))
+        };
+
+        Some(LayoutLine {
+            glyph_start: line.glyph_start,
+            glyph_positions: &self.glyph_positions[self.line_glyph_range(line_index)],
+            left: left,
+            right: right,
+            baseline: line.baseline,
+        })
+    }
+
+    /// Returns the metrics of every line laid out so far, in order from top to bottom, for drawing
+    /// line backgrounds, underlines, and the like.
+    ///
+    /// As with `lines()`, the line that the cursor is on is always included.
+    pub fn line_metrics(&self) -> Vec<LineMetrics> {
+        self.lines().zip(self.lines.iter()).map(|(layout_line, line)| {
+            LineMetrics {
+                baseline: layout_line.baseline,
+                ascent: line.ascent,
+                descent: line.descent,
+                line_gap: line.line_gap,
+                left: layout_line.left,
+                right: layout_line.right,
+            }
+        }).collect()
+    }
+
+    /// Returns each glyph laid out so far together with the index of the line that it is on,
+    /// with its `y` measured from the baseline of that line rather than from the top of the page,
+    /// for renderers that draw each line in its own coordinate space and pass the line's offset
+    /// separately.
+    ///
+    /// Glyphs sitting on the baseline get a `y` of zero; baseline shifts and glyph offsets are
+    /// kept, so raised glyphs have a negative `y`. In the vertical writing modes, `x` is measured
+    /// from the center line of the column instead, and `y` is left as it is. Line indices are
+    /// those of `lines()`.
+    pub fn positions_line_relative(&self) -> Vec<(usize, GlyphPosition)> {
+        let mut positions = Vec::with_capacity(self.glyph_positions.len());
+        for (line_index, line) in self.lines.iter().enumerate() {
+            for glyph_position in &self.glyph_positions[self.line_glyph_range(line_index)] {
+                let mut glyph_position = *glyph_position;
+                if self.writing_mode.is_vertical() {
+                    glyph_position.x -= line.baseline
+                } else {
+                    glyph_position.y -= line.baseline
+                }
+                positions.push((line_index, glyph_position))
+            }
+        }
+        positions
+    }
+
+    /// Returns the smallest rectangle, in pixels, that encloses the outlines of every glyph laid
+    /// out so far.
+    ///
+    /// These are the ink bounds of the text: each glyph contributes the bounds of its outline at
+    /// its position, so side bearings and the space between words don't count, while glyphs that
+    /// overshoot their advances or the line do. For bounds made of advances instead, use
+    /// `layout_bounds()`.
+    ///
+    /// Glyphs missing from the glyph store or its outlines are ignored. If there are no glyphs,
+    /// this returns a zero rectangle.
+    pub fn ink_bounds(&self, glyph_store: &GlyphStore, point_size: f32) -> Rect<f32> {
+        let mut ink_bounds: Option<Rect<f32>> = None;
+        for glyph_position in &self.glyph_positions {
+            let glyph_index = match glyph_store.glyph_index(glyph_position.glyph_id) {
+                None => continue,
+                Some(glyph_index) => glyph_index,
+            };
+
+            // Glyph outlines are y-up, while layout is y-down.
+            let glyph_point_size = point_size * glyph_position.scale;
+            let glyph_subpixel_bounds =
+                match glyph_store.outlines.try_glyph_subpixel_bounds(glyph_index,
+                                                                     glyph_point_size) {
+                    None => continue,
+                    Some(glyph_subpixel_bounds) => glyph_subpixel_bounds,
+                };
+            let glyph_rect = Rect::new(Point2D::new(glyph_position.x + glyph_subpixel_bounds.left,
+                                                    glyph_position.y - glyph_subpixel_bounds.top),
+                                       glyph_subpixel_bounds.size());
+
+            ink_bounds = match ink_bounds {
+                None => Some(glyph_rect),
+                Some(ink_bounds) => Some(ink_bounds.union(&glyph_rect)),
+            }
+        }
+
+        ink_bounds.unwrap_or(Rect::zero())
+    }
+
+    /// Returns the smallest rectangle, in pixels, that encloses the line boxes of every line with
+    /// glyphs on it laid out so far.
+    ///
+    /// Each line contributes the span of its words' advance boxes, from `left` to `right` in
+    /// `line_metrics()`, by its ascent above and descent below the baseline, so unlike
+    /// `ink_bounds()` this includes side bearings and leaves out glyphs that overshoot the line.
+    /// If there are no glyphs, this returns a zero rectangle. The typesetter must lay out
+    /// horizontal text.
+    pub fn layout_bounds(&self) -> Rect<f32> {
+        let mut layout_bounds: Option<Rect<f32>> = None;
+        for (layout_line, line) in self.lines().zip(self.line_metrics()) {
+            if layout_line.glyph_positions.is_empty() {
+                continue
+            }
+
+            let line_rect = Rect::new(Point2D::new(line.left, line.baseline - line.ascent),
+                                      Size2D::new(line.right - line.left,
+                                                  line.ascent + line.descent));
+            layout_bounds = match layout_bounds {
+                None => Some(line_rect),
+                Some(layout_bounds) => Some(layout_bounds.union(&line_rect)),
+            }
+        }
+
+        layout_bounds.unwrap_or(Rect::zero())
+    }
+
+    /// Returns the distance, in pixels, from the top of the page to the bottom of the current
+    /// line: that is, the current baseline plus the largest descent of the fonts on the line.
+    #[inline]
+    pub fn content_height(&self) -> f32 {
+        self.cursor.y + self.current_line().descent
+    }
+
+    /// Returns the width, in pixels, of the widest line laid out so far, from the outer edge of
+    /// the advance box of its first word to that of its last. In the vertical writing modes, this
+    /// is the length of the longest column instead.
+    ///
+    /// Spaces after the last word on a line don't count, and neither do indents, so together with
+    /// `content_height()` this gives the tight size of the text. It can exceed the text width
+    /// between the margins only where a word too long for a line was left unsplit.
+    pub fn max_line_width(&self) -> f32 {
+        self.lines().map(|line| line.right - line.left).fold(0.0, f32::max)
+    }
+
+    /// Returns the index into `glyph_positions` of the glyph whose advance box contains the given
+    /// point, if any.
+    ///
+    /// The point is first clamped vertically to the nearest line, so points above the first line
+    /// or below the last one hit glyphs on those lines.
+    ///
+    /// With tight line spacing, lines can overlap. Because later lines are drawn over earlier
+    /// ones, a point within several lines hits the glyph on the latest line that has one there.
+    pub fn glyph_at_point(&self, point: Point2D<f32>) -> Option<usize> {
+        let mut within_line = false;
+        for (line_index, line) in self.lines.iter().enumerate().rev() {
+            if point.y < line.baseline - line.ascent || point.y > line.baseline + line.descent {
+                continue
+            }
+            within_line = true;
+            if let Some(glyph_index) = self.glyph_at_x_on_line(line_index, point.x) {
+                return Some(glyph_index)
+            }
+        }
+        if within_line {
+            return None
+        }
+        self.glyph_at_x_on_line(self.line_index_at_y(point.y), point.x)
+    }
+
+    /// Returns the glyph in the given cell of a grid of monospaced text, as a terminal lays it
+    /// out, if there is one.
+    ///
+    /// Cells are counted from the start of the line, taking its indent into account, in units of
+    /// the advance of the glyph found there, so this assumes a fixed-pitch font, start alignment,
+    /// and no letter spacing. Each line starts where the margins, page width, and direction that
+    /// it was laid out with put it. Preserved spaces (see `set_whitespace_mode()`) and tabs occupy
+    /// cells without glyphs. Cells out of range, and all cells of vertical text, have no glyph.
+    pub fn glyph_at_cell(&self, line_index: usize, column: usize) -> Option<&GlyphPosition> {
+        if line_index >= self.lines.len() || self.writing_mode.is_vertical() {
+            return None
+        }
+
+        let line = &self.lines[line_index];
+        for glyph_index in self.line_glyph_range(line_index) {
+            let (position, advance) = (&self.glyph_positions[glyph_index],
+                                       self.glyph_advances[glyph_index]);
+            if advance <= 0.0 {
+                continue
+            }
+            let pen_x = position.x - position.x_offset;
+            let distance = match line.direction {
+                Direction::LeftToRight => pen_x - line.start,
+                Direction::RightToLeft => line.start - (pen_x + advance),
+            };
+            if (distance / advance).round() == column as f32 {
+                return Some(position)
+            }
+        }
+        None
+    }
+
+    /// Returns the index of the glyph on the given line whose advance box spans the given x
+    /// position, if any.
+    fn glyph_at_x_on_line(&self, line_index: usize, x: f32) -> Option<usize> {
+        for word in &self.words[self.line_word_range(line_index)] {
+            if x < word.left || x >= word.right {
+                continue
+            }
+            for glyph_index in word.glyph_start..word.glyph_end {
+                let (left, right) = self.glyph_extent(word, glyph_index);
+                if x >= left && x < right {
+                    return Some(glyph_index)
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the caret position nearest to the given point, as an index into `glyph_positions`
+    /// before which text would be inserted.
+    ///
+    /// The point is clamped vertically to the nearest line, or to the latest of the lines that
+    /// contain it where lines overlap. Within a glyph, the caret goes on the side of the glyph
+    /// nearest to the point; between or beyond words, it snaps to the nearest word boundary. The
+    /// caret never goes between glyphs of the same grapheme cluster, such as an emoji sequence or
+    /// a letter and its combining marks.
+    pub fn caret_index_at_point(&self, point: Point2D<f32>) -> usize {
+        let line_index = self.line_index_at_y(point.y);
+        let word_range = self.line_word_range(line_index);
+        if word_range.start == word_range.end {
+            return self.lines[line_index].glyph_start
+        }
+
+        let (mut best_distance, mut best_caret_index) = (f32::INFINITY, 0);
+        for word in &self.words[word_range] {
+            if point.x >= word.left && point.x < word.right {
+                for glyph_index in word.glyph_start..word.glyph_end {
+                    let (left, right) = self.glyph_extent(word, glyph_index);
+                    if point.x < left || point.x >= right {
+                        continue
+                    }
+
+                    // The caret never goes inside a grapheme cluster, so it goes on the side of
+                    // the whole cluster nearest to the point instead.
+                    let cluster = self.glyph_positions[glyph_index].cluster;
+                    let mut cluster_start = glyph_index;
+                    while cluster_start > word.glyph_start &&
+                            self.glyph_positions[cluster_start - 1].cluster == cluster {
+                        cluster_start -= 1
+                    }
+                    let mut cluster_end = glyph_index + 1;
+                    while cluster_end < word.glyph_end &&
+                            self.glyph_positions[cluster_end].cluster == cluster {
+                        cluster_end += 1
+                    }
+                    let (mut left, mut right) = (f32::INFINITY, f32::NEG_INFINITY);
+                    for cluster_glyph_index in cluster_start..cluster_end {
+                        let (glyph_left, glyph_right) = self.glyph_extent(word,
+                                                                          cluster_glyph_index);
+                        left = left.min(glyph_left);
+                        right = right.max(glyph_right);
+                    }
+
+                    let on_left_half = point.x < (left + right) * 0.5;
+                    return match (self.runs[word.run].direction, on_left_half) {
+                        (Direction::LeftToRight, true) |
+                        (Direction::RightToLeft, false) => cluster_start,
+                        (Direction::LeftToRight, false) |
+                        (Direction::RightToLeft, true) => cluster_end,
+                    }
+                }
+            }
+
+            let (left_caret_index, right_caret_index) = match self.runs[word.run].direction {
+                Direction::LeftToRight => (word.glyph_start, word.glyph_end),
+                Direction::RightToLeft => (word.glyph_end, word.glyph_start),
+            };
+            for &(edge, caret_index) in &[(word.left, left_caret_index),
+                                          (word.right, right_caret_index)] {
+                let distance = (point.x - edge).abs();
+                if distance < best_distance {
+                    best_distance = distance;
+                    best_caret_index = caret_index;
+                }
+            }
+        }
+
+        best_caret_index
+    }
+
+    /// Returns a rectangle for the caret at each position where text could be inserted, from
+    /// before the first glyph in `glyph_positions` to after the last one.
+    ///
+    /// The rectangle at index `i` marks the position before the glyph at index `i`. Each is
+    /// centered on the edge between glyphs and spans the ascent and descent of its line around
+    /// the line's baseline, so carets on a line with a taller font or a drop cap are taller too.
+    /// Where a line was wrapped, the caret stays at the end of that line instead of moving to the
+    /// start of the next; after a forced line break, it moves to the start of the new line.
+    pub fn caret_rects(&self) -> Vec<Rect<f32>> {
+        let glyph_count = self.glyph_positions.len();
+        let mut caret_rects = Vec::with_capacity(glyph_count + 1);
+        let mut line_index = 0;
+        for caret_index in 0..(glyph_count + 1) {
+            // Find the last line that starts at or before this glyph, skipping empty lines.
+            while self.lines.get(line_index + 1)
+                            .map_or(false, |line| line.glyph_start <= caret_index) {
+                line_index += 1
+            }
+
+            let line = &self.lines[line_index];
+            let wrapped = line_index > 0 && !line.starts_paragraph &&
+                caret_index == line.glyph_start && caret_index > 0;
+            let (line, x) = if wrapped {
+                (&self.lines[line_index - 1], self.glyph_edge(caret_index - 1, true))
+            } else if caret_index < glyph_count {
+                (line, self.glyph_edge(caret_index, false))
+            } else if caret_index > line.glyph_start {
+                (line, self.glyph_edge(caret_index - 1, true))
+            } else {
+                // Nothing has been placed on the current line yet.
+                (line, self.cursor_position().x)
+            };
+
+            let top = line.baseline - line.ascent;
+            caret_rects.push(Rect::new(Point2D::new(x - CARET_WIDTH * 0.5, top),
+                                       Size2D::new(CARET_WIDTH, line.ascent + line.descent)))
+        }
+        caret_rects
+    }
+
+    /// Returns rectangles for a line drawn under or through the text added by the `add_text()`
+    /// call with the given index (see `GlyphSource::text_index`), one for each line that the
+    /// text is on, from top to bottom.
+    ///
+    /// Each rectangle spans the advance boxes of the text's words on its line and is placed by
+    /// `metrics` relative to the text's baseline, following any baseline shift. Pass
+    /// `font.underline_metrics(point_size)` to underline the text or
+    /// `font.strikeout_metrics(point_size)` to strike it out. Vertical text has no decoration
+    /// lines, so the result is empty, as it is if there is no text with the given index.
+    pub fn decoration_rects(&self, text_index: usize, metrics: &DecorationMetrics)
+                            -> Vec<Rect<f32>> {
+        let mut rects = vec![];
+        if self.writing_mode.is_vertical() {
+            return rects
+        }
+
+        let baseline_shift = match self.runs.iter().find(|run| run.text_index == text_index) {
+            None => return rects,
+            Some(run) => run.baseline_shift,
+        };
+        for (line_index, line) in self.lines.iter().enumerate() {
+            let (mut left, mut right) = (f32::INFINITY, f32::NEG_INFINITY);
+            for word in &self.words[self.line_word_range(line_index)] {
+                if self.runs[word.run].text_index == text_index {
+                    left = left.min(word.left);
+                    right = right.max(word.right);
+                }
+            }
+
+            if left < right {
+                let top = line.baseline - baseline_shift - metrics.position;
+                rects.push(Rect::new(Point2D::new(left, top),
+                                     Size2D::new(right - left, metrics.thickness)))
+            }
+        }
+        rects
+    }
+
+    /// Returns the x position of the edge of the advance box of the given glyph that comes first
+    /// in the direction of its run, or of the edge that comes last if `end` is true.
+    fn glyph_edge(&self, glyph_index: usize, end: bool) -> f32 {
+        let word_index = self.word_index_for_glyph(glyph_index);
+        let x = self.pen_x(glyph_index);
+        let advance = self.glyph_advances[glyph_index];
+        match (self.runs[self.words[word_index].run].direction, end) {
+            (Direction::LeftToRight, false) | (Direction::RightToLeft, true) => x,
+            (Direction::LeftToRight, true) | (Direction::RightToLeft, false) => x + advance,
+        }
+    }
+
+    /// Returns the x position of the pen when the given glyph was placed, before its offset from
+    /// the shaper was applied.
+    #[inline]
+    fn pen_x(&self, glyph_index: usize) -> f32 {
+        let glyph_position = &self.glyph_positions[glyph_index];
+        glyph_position.x - glyph_position.x_offset
+    }
+
+    /// Returns the index of the line whose vertical extent is nearest to the given y coordinate.
+    ///
+    /// Ties, as where lines overlap, go to the later line, which is drawn on top.
+    fn line_index_at_y(&self, y: f32) -> usize {
+        let (mut best_distance, mut best_line_index) = (f32::INFINITY, 0);
+        for (line_index, line) in self.lines.iter().enumerate() {
+            let (top, bottom) = (line.baseline - line.ascent, line.baseline + line.descent);
+            let distance = if y < top {
+                top - y
+            } else if y > bottom {
+                y - bottom
+            } else {
+                0.0
+            };
+
+            if distance <= best_distance {
+                best_distance = distance;
+                best_line_index = line_index;
+            }
+        }
+        best_line_index
+    }
+
+    /// Returns the horizontal extent of the advance box of the given glyph within its word,
+    /// including any letter spacing after it.
+    fn glyph_extent(&self, word: &Word, glyph_index: usize) -> (f32, f32) {
+        let left = self.pen_x(glyph_index);
+        let right = match self.runs[word.run].direction {
+            Direction::LeftToRight if glyph_index + 1 < word.glyph_end => {
+                self.pen_x(glyph_index + 1)
+            }
+            Direction::RightToLeft if glyph_index > word.glyph_start => {
+                self.pen_x(glyph_index - 1)
+            }
+            Direction::LeftToRight | Direction::RightToLeft => word.right,
+        };
+        (left, right)
+    }
+
+    pub fn create_glyph_store(&self, font: &Font) -> Result<GlyphStore, GlyphStoreCreationError> {
+        GlyphStore::from_glyph_positions(&self.glyph_positions, font)
+    }
+
+    /// Returns the positions of the glyphs that intersect the given pixel rectangle.
+    ///
+    /// Layout always happens in logical pixels: the point sizes passed to `add_text()` and all of
+    /// the typesetter's positions and distances are logical. `device_scale`, the number of device
+    /// pixels per logical pixel (2.0 on a typical high-DPI display), is applied only here, so the
+    /// same layout can be drawn at any scale. `point_size` is the logical point size that the
+    /// text was laid out at. `bounding_rect` and the bounds of the returned glyphs are in device
+    /// pixels. See `GlyphPosition::device_rect()`.
+    ///
+    /// Requires a `GlyphStore` to be created first. Glyphs that are not in the store are skipped;
+    /// use `positioned_glyphs_in_rect_reporting_missing()` to find out which ones.
+    #[inline]
+    pub fn positioned_glyphs_in_rect(&self,
+                                     bounding_rect: &Rect<f32>,
+                                     glyph_store: &GlyphStore,
+                                     point_size: f32,
+                                     device_scale: f32,
+                                     subpixel_granularity: f32)
+                                     -> Vec<PositionedGlyph> {
+        positioned_glyphs_in_rect(&self.glyph_positions,
+                                  Some(bounding_rect),
+                                  glyph_store,
+                                  point_size,
+                                  device_scale,
+                                  subpixel_granularity,
+                                  GlyphCulling::none())
+    }
+
+    /// Like `positioned_glyphs_in_rect()`, but also appends the IDs of glyphs that were skipped
+    /// because they are not in the glyph store to `missing_glyph_ids`.
+    ///
+    /// Because the bounds of a missing glyph are unknown, every missing glyph is reported, whether
+    /// or not it would have intersected the rectangle. An ID is appended once for each glyph
+    /// position that uses it. The missing glyphs can be added with `GlyphStore::add_glyph_ids()`
+    /// before querying again.
+    #[inline]
+    pub fn positioned_glyphs_in_rect_reporting_missing(&self,
+                                                       bounding_rect: &Rect<f32>,
+                                                       glyph_store: &GlyphStore,
+                                                       point_size: f32,
+                                                       device_scale: f32,
+                                                       subpixel_granularity: f32,
+                                                       missing_glyph_ids: &mut Vec<u16>)
+                                                       -> Vec<PositionedGlyph> {
+        positioned_glyphs_in_rect(&self.glyph_positions,
+                                  Some(bounding_rect),
+                                  glyph_store,
+                                  point_size,
+                                  device_scale,
+                                  subpixel_granularity,
+                                  GlyphCulling {
+                                      missing_glyph_ids: Some(missing_glyph_ids),
+                                      ..GlyphCulling::none()
+                                  })
+    }
+
+    /// Like `positioned_glyphs_in_rect()`, but also skips glyphs whose bounds are smaller than
+    /// `min_size` device pixels in both dimensions, returning the number of glyphs skipped that
+    /// way alongside the others.
+    ///
+    /// Text too small to read, such as map labels or thumbnails seen from far away, can be culled
+    /// this way to save rendering time. A `min_size` of zero skips nothing.
+    pub fn positioned_glyphs_in_rect_above_size(&self,
+                                                bounding_rect: &Rect<f32>,
+                                                glyph_store: &GlyphStore,
+                                                point_size: f32,
+                                                device_scale: f32,
+                                                subpixel_granularity: f32,
+                                                min_size: f32)
+                                                -> (Vec<PositionedGlyph>, usize) {
+        let mut small_glyph_count = 0;
+        let positioned_glyphs = {
+            let culling = GlyphCulling {
+                min_size: min_size,
+                small_glyph_count: Some(&mut small_glyph_count),
+                ..GlyphCulling::none()
+            };
+            positioned_glyphs_in_rect(&self.glyph_positions,
+                                      Some(bounding_rect),
+                                      glyph_store,
+                                      point_size,
+                                      device_scale,
+                                      subpixel_granularity,
+                                      culling)
+        };
+        (positioned_glyphs, small_glyph_count)
+    }
+
+    /// Returns the positions of the glyphs on the line with the given index, counting from the
+    /// top, for rendering one line at a time.
+    ///
+    /// Glyphs are positioned exactly as `positioned_glyphs_in_rect()` positions them, and those
+    /// that are not in the glyph store are skipped. If there is no such line, the result is empty.
+    pub fn positioned_glyphs_on_line(&self,
+                                     line_index: usize,
+                                     glyph_store: &GlyphStore,
+                                     point_size: f32,
+                                     device_scale: f32,
+                                     subpixel_granularity: f32)
+                                     -> Vec<PositionedGlyph> {
+        if line_index >= self.lines.len() {
+            return vec![]
+        }
+        positioned_glyphs_in_rect(&self.glyph_positions[self.line_glyph_range(line_index)],
+                                  None,
+                                  glyph_store,
+                                  point_size,
+                                  device_scale,
+                                  subpixel_granularity,
+                                  GlyphCulling::none())
+    }
+
+    /// Returns a vertex for every glyph that is in the glyph store, positioned as
+    /// `positioned_glyphs_in_rect()` positions it and then moved by the given transform, for
+    /// uploading to a vertex buffer. See `GlyphVertex`.
+    pub fn glyph_vertices(&self,
+                          glyph_store: &GlyphStore,
+                          point_size: f32,
+                          device_scale: f32,
+                          subpixel_granularity: f32,
+                          transform: &Matrix2D<f32>)
+                          -> Vec<GlyphVertex> {
+        let positioned_glyphs = positioned_glyphs_in_rect(&self.glyph_positions,
+                                                          None,
+                                                          glyph_store,
+                                                          point_size,
+                                                          device_scale,
+                                                          subpixel_granularity,
+                                                          GlyphCulling::none());
+        GlyphVertex::from_positioned_glyphs(&positioned_glyphs, subpixel_granularity, transform)
+    }
+
+    /// Returns every glyph that is in the glyph store, positioned as `positioned_glyphs_in_rect()`
+    /// positions it, grouped by glyph index so that each group can be drawn with one instanced
+    /// draw call. See `GlyphInstances::group()`.
+    pub fn glyph_instances(&self,
+                           glyph_store: &GlyphStore,
+                           point_size: f32,
+                           device_scale: f32,
+                           subpixel_granularity: f32,
+                           by_subpixel_offset: bool)
+                           -> Vec<GlyphInstances> {
+        let positioned_glyphs = positioned_glyphs_in_rect(&self.glyph_positions,
+                                                          None,
+                                                          glyph_store,
+                                                          point_size,
+                                                          device_scale,
+                                                          subpixel_granularity,
+                                                          GlyphCulling::none());
+        GlyphInstances::group(&positioned_glyphs, subpixel_granularity, by_subpixel_offset)
+    }
+
+    /// Returns a copy of the glyph positions, along with the page width and cursor, in a form
+    /// that can be serialized when the `serde-serialization` feature is enabled.
+    pub fn layout(&self) -> TypesetterLayout {
+        TypesetterLayout {
+            page_width: self.page_width,
+            cursor: self.cursor,
+            glyph_positions: self.glyph_positions.clone(),
+        }
+    }
+}
+
+/// Summarizes the typesetter as its glyph and line counts, page width, and cursor.
+///
+/// The alternate form, `{:#?}`, also lists every glyph position.
+impl fmt::Debug for Typesetter {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let verbose = formatter.alternate();
+        let mut debug_struct = formatter.debug_struct("Typesetter");
+        debug_struct.field("glyph_count", &self.glyph_positions.len())
+                    .field("line_count", &self.lines.len())
+                    .field("page_width", &self.page_width)
+                    .field("cursor", &self.cursor);
+        if verbose {
+            debug_struct.field("glyph_positions", &self.glyph_positions);
+        }
+        debug_struct.finish()
+    }
+}
+
+/// Configures a `Typesetter` before creating it.
+///
+/// Every setting defaults to the same value as in a typesetter created with `Typesetter::new()`.
+#[derive(Clone, Copy, Debug)]
+pub struct TypesetterBuilder {
+    page_width: f32,
+    left_margin: f32,
+    right_margin: f32,
+    writing_mode: WritingMode,
+    direction: Direction,
+    alignment: Option<Alignment>,
+    line_spacing: LineSpacing,
+    line_box: Option<LineBox>,
+    break_policy: BreakPolicy,
+    line_break_strategy: LineBreakStrategy,
+    whitespace_mode: WhitespaceMode,
+    letter_spacing: f32,
+    word_spacing: f32,
+    justification_limits: (f32, f32),
+    tab_width: f32,
+    fallback_space_width: f32,
+    baseline_shift: f32,
+    glyph_scale: f32,
+    kerning: bool,
+    first_line_indent: f32,
+    hanging_indent: f32,
+    max_lines: Option<usize>,
+    page_height: Option<f32>,
+    pen_snapping: PenSnapping,
+    overflow_policy: OverflowPolicy,
+    notdef_policy: NotdefPolicy,
+    origin: Point2D<f32>,
+}
+
+impl TypesetterBuilder {
+    /// Creates a builder for a typesetter with the given page width, in pixels.
+    #[inline]
+    pub fn new(page_width: f32) -> TypesetterBuilder {
+        TypesetterBuilder {
+            page_width: page_width,
+            left_margin: 0.0,
+            right_margin: 0.0,
+            writing_mode: WritingMode::Horizontal,
+            direction: Direction::LeftToRight,
+            alignment: None,
+            line_spacing: LineSpacing::Multiple(1.0),
+            line_box: None,
+            break_policy: BreakPolicy::Whitespace,
+            line_break_strategy: LineBreakStrategy::Greedy,
+            whitespace_mode: WhitespaceMode::Collapse,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+            justification_limits: (1.0, f32::INFINITY),
+            tab_width: 0.0,
+            fallback_space_width: DEFAULT_FALLBACK_SPACE_WIDTH,
+            baseline_shift: 0.0,
+            glyph_scale: 1.0,
+            kerning: true,
+            first_line_indent: 0.0,
+            hanging_indent: 0.0,
+            max_lines: None,
+            page_height: None,
+            pen_snapping: PenSnapping::None,
+            overflow_policy: OverflowPolicy::Allow,
+            notdef_policy: NotdefPolicy::Show,
+            origin: Point2D::zero(),
+        }
+    }
+
+    /// See `Typesetter::set_margins()`.
+    #[inline]
+    pub fn margins(mut self, left: f32, right: f32) -> TypesetterBuilder {
+        self.left_margin = left;
+        self.right_margin = right;
+        self
+    }
+
+    /// See `Typesetter::set_writing_mode()`.
+    #[inline]
+    pub fn writing_mode(mut self, writing_mode: WritingMode) -> TypesetterBuilder {
+        self.writing_mode = writing_mode;
+        self
+    }
+
+    /// See `Typesetter::set_direction()`.
+    #[inline]
+    pub fn direction(mut self, direction: Direction) -> TypesetterBuilder {
+        self.direction = direction;
+        self
+    }
+
+    /// See `Typesetter::set_alignment()`.
+    #[inline]
+    pub fn alignment(mut self, alignment: Alignment) -> TypesetterBuilder {
+        self.alignment = Some(alignment);
+        self
+    }
+
+    /// See `Typesetter::set_line_spacing()`.
+    #[inline]
+    pub fn line_spacing(mut self, line_spacing: LineSpacing) -> TypesetterBuilder {
+        self.line_spacing = line_spacing;
+        self
+    }
+
+    /// See `Typesetter::set_line_box()`.
+    #[inline]
+    pub fn line_box(mut self, line_box: Option<LineBox>) -> TypesetterBuilder {
+        self.line_box = line_box;
+        self
+    }
+
+    /// See `Typesetter::set_break_policy()`.
+    #[inline]
+    pub fn break_policy(mut self, break_policy: BreakPolicy) -> TypesetterBuilder {
+        self.break_policy = break_policy;
+        self
+    }
+
+    /// See `Typesetter::set_line_break_strategy()`.
+    #[inline]
+    pub fn line_break_strategy(mut self, line_break_strategy: LineBreakStrategy)
+                               -> TypesetterBuilder {
+        self.line_break_strategy = line_break_strategy;
+        self
+    }
+
+    /// See `Typesetter::set_whitespace_mode()`.
+    #[inline]
+    pub fn whitespace_mode(mut self, whitespace_mode: WhitespaceMode) -> TypesetterBuilder {
+        self.whitespace_mode = whitespace_mode;
+        self
+    }
+
+    /// See `Typesetter::set_letter_spacing()`.
+    #[inline]
+    pub fn letter_spacing(mut self, letter_spacing: f32) -> TypesetterBuilder {
+        self.letter_spacing = letter_spacing;
+        self
+    }
+
+    /// See `Typesetter::set_word_spacing()`.
+    #[inline]
+    pub fn word_spacing(mut self, word_spacing: f32) -> TypesetterBuilder {
+        self.word_spacing = word_spacing;
+        self
+    }
+
+    /// See `Typesetter::set_justification_limits()`.
+    #[inline]
+    pub fn justification_limits(mut self, min_space: f32, max_space: f32) -> TypesetterBuilder {
+        self.justification_limits = (min_space, max_space);
+        self
+    }
+
+    /// See `Typesetter::set_tab_width()`.
+    #[inline]
+    pub fn tab_width(mut self, tab_width: f32) -> TypesetterBuilder {
+        self.tab_width = tab_width;
+        self
+    }
+
+    /// See `Typesetter::set_fallback_space_width()`.
+    #[inline]
+    pub fn fallback_space_width(mut self, fallback_space_width: f32) -> TypesetterBuilder {
+        self.fallback_space_width = fallback_space_width;
+        self
+    }
+
+    /// See `Typesetter::set_baseline_shift()`.
+    #[inline]
+    pub fn baseline_shift(mut self, baseline_shift: f32) -> TypesetterBuilder {
+        self.baseline_shift = baseline_shift;
+        self
+    }
+
+    /// See `Typesetter::set_glyph_scale()`.
+    #[inline]
+    pub fn glyph_scale(mut self, glyph_scale: f32) -> TypesetterBuilder {
+        self.glyph_scale = glyph_scale;
+        self
+    }
+
+    /// See `Typesetter::set_kerning()`.
+    #[inline]
+    pub fn kerning(mut self, kerning: bool) -> TypesetterBuilder {
+        self.kerning = kerning;
+        self
+    }
+
+    /// See `Typesetter::set_first_line_indent()`.
+    #[inline]
+    pub fn first_line_indent(mut self, first_line_indent: f32) -> TypesetterBuilder {
+        self.first_line_indent = first_line_indent;
+        self
+    }
+
+    /// See `Typesetter::set_hanging_indent()`.
+    #[inline]
+    pub fn hanging_indent(mut self, hanging_indent: f32) -> TypesetterBuilder {
+        self.hanging_indent = hanging_indent;
+        self
+    }
+
+    /// See `Typesetter::set_max_lines()`.
+    #[inline]
+    pub fn max_lines(mut self, max_lines: Option<usize>) -> TypesetterBuilder {
+        self.max_lines = max_lines;
+        self
+    }
+
+    /// See `Typesetter::set_page_height()`.
+    #[inline]
+    pub fn page_height(mut self, page_height: Option<f32>) -> TypesetterBuilder {
+        self.page_height = page_height;
+        self
+    }
+
+    /// See `Typesetter::set_pen_snapping()`.
+    #[inline]
+    pub fn pen_snapping(mut self, pen_snapping: PenSnapping) -> TypesetterBuilder {
+        self.pen_snapping = pen_snapping;
+        self
+    }
+
+    /// See `Typesetter::set_overflow_policy()`.
+    #[inline]
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy) -> TypesetterBuilder {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// See `Typesetter::set_notdef_policy()`.
+    #[inline]
+    pub fn notdef_policy(mut self, notdef_policy: NotdefPolicy) -> TypesetterBuilder {
+        self.notdef_policy = notdef_policy;
+        self
+    }
+
+    /// See `Typesetter::new_at()`.
+    #[inline]
+    pub fn origin(mut self, origin: Point2D<f32>) -> TypesetterBuilder {
+        self.origin = origin;
+        self
+    }
+
+    /// Creates the typesetter. The initial font and point size determine the position of the
+    /// first baseline, as in `Typesetter::new()`.
+    pub fn build(&self, initial_font: &Font, initial_point_size: f32) -> Typesetter {
+        let mut typesetter = Typesetter::new_at(self.page_width,
+                                                initial_font,
+                                                initial_point_size,
+                                                self.origin);
+        typesetter.set_writing_mode(self.writing_mode);
+        typesetter.set_margins(self.left_margin, self.right_margin);
+        typesetter.set_direction(self.direction);
+        typesetter.alignment = self.alignment;
+        typesetter.set_line_spacing(self.line_spacing);
+        typesetter.set_line_box(self.line_box);
+        typesetter.set_break_policy(self.break_policy);
+        typesetter.set_line_break_strategy(self.line_break_strategy);
+        typesetter.set_whitespace_mode(self.whitespace_mode);
+        typesetter.set_letter_spacing(self.letter_spacing);
+        typesetter.set_word_spacing(self.word_spacing);
+        let (min_space, max_space) = self.justification_limits;
+        typesetter.set_justification_limits(min_space, max_space);
+        typesetter.set_tab_width(self.tab_width);
+        typesetter.set_fallback_space_width(self.fallback_space_width);
+        typesetter.set_baseline_shift(self.baseline_shift);
+        typesetter.set_glyph_scale(self.glyph_scale);
+        typesetter.set_kerning(self.kerning);
+        typesetter.set_first_line_indent(self.first_line_indent);
+        typesetter.set_hanging_indent(self.hanging_indent);
+        typesetter.set_max_lines(self.max_lines);
+        typesetter.set_page_height(self.page_height);
+        typesetter.set_pen_snapping(self.pen_snapping);
+        typesetter.set_overflow_policy(self.overflow_policy);
+        typesetter.set_notdef_policy(self.notdef_policy);
+        typesetter
+    }
+}
+
+/// The laid-out glyphs of a typesetter, detached from it so that they can be stored or sent
+/// elsewhere.
+///
+/// With the `serde-serialization` feature enabled, this can be serialized and deserialized; a
+/// deserialized layout positions glyphs exactly as the typesetter that it came from did.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
+pub struct TypesetterLayout {
+    /// The width of the page, in pixels.
+    pub page_width: f32,
+    /// The position of the typesetter's cursor.
+    pub cursor: Point2D<f32>,
+    /// The positions of the glyphs, as in `Typesetter::glyph_positions`.
+    pub glyph_positions: Vec<GlyphPosition>,
+}
+
+impl TypesetterLayout {
+    /// Creates a glyph store containing every glyph in this layout. See
+    /// `Typesetter::create_glyph_store()`.
+    #[inline]
+    pub fn create_glyph_store(&self, font: &Font) -> Result<GlyphStore, GlyphStoreCreationError> {
+        GlyphStore::from_glyph_positions(&self.glyph_positions, font)
+    }
+
+    /// Returns the positions of the glyphs that intersect the given pixel rectangle. See
+    /// `Typesetter::positioned_glyphs_in_rect()`.
+    #[inline]
+    pub fn positioned_glyphs_in_rect(&self,
+                                     bounding_rect: &Rect<f32>,
+                                     glyph_store: &GlyphStore,
+                                     point_size: f32,
+                                     device_scale: f32,
+                                     subpixel_granularity: f32)
+                                     -> Vec<PositionedGlyph> {
+        positioned_glyphs_in_rect(&self.glyph_positions,
+                                  Some(bounding_rect),
+                                  glyph_store,
+                                  point_size,
+                                  device_scale,
+                                  subpixel_granularity,
+                                  GlyphCulling::none())
+    }
+}
+
+/// Which glyphs the free function `positioned_glyphs_in_rect()` skips besides those outside the
+/// rectangle, and where it reports them.
+struct GlyphCulling<'a> {
+    /// If present, the IDs of glyphs missing from the glyph store are appended to this.
+    missing_glyph_ids: Option<&'a mut Vec<u16>>,
+    /// Glyphs smaller than this in both dimensions, in device pixels, are skipped.
+    min_size: f32,
+    /// If present, the number of glyphs skipped for being too small is added to this.
+    small_glyph_count: Option<&'a mut usize>,
+}
+
+impl<'a> GlyphCulling<'a> {
+    /// Skips only the glyphs missing from the glyph store, without reporting them.
+    #[inline]
+    fn none() -> GlyphCulling<'a> {
+        GlyphCulling {
+            missing_glyph_ids: None,
+            min_size: 0.0,
+            small_glyph_count: None,
+        }
+    }
+}
+
+/// Returns the positions of the glyphs that intersect the given pixel rectangle, or of all of them
+/// if there is none, optionally culling small glyphs and reporting those skipped.
+fn positioned_glyphs_in_rect(glyph_positions: &[GlyphPosition],
+                             bounding_rect: Option<&Rect<f32>>,
+                             glyph_store: &GlyphStore,
+                             point_size: f32,
+                             device_scale: f32,
+                             subpixel_granularity: f32,
+                             mut culling: GlyphCulling)
+                             -> Vec<PositionedGlyph> {
+    let mut positioned_glyphs = vec![];
+    for glyph_position in glyph_positions {
+        // If this glyph is not in the glyph store, or its index is stale, skip it, telling the
+        // caller if they asked.
+        let glyph_index = match glyph_store.glyph_index(glyph_position.glyph_id) {
+            Some(glyph_index) if (glyph_index as usize) < glyph_store.outlines.glyph_count() => {
+                glyph_index
+            }
+            _ => {
+                if let Some(ref mut missing_glyph_ids) = culling.missing_glyph_ids {
+                    missing_glyph_ids.push(glyph_position.glyph_id)
+                }
+                continue
+            }
+        };
+
+        let units_per_em = glyph_store.outlines.glyph_units_per_em(glyph_index) as u16;
+        let glyph_snapped_rect =
+            glyph_position.device_rect(&glyph_store.outlines.glyph_bounds(glyph_index as u32),
+                                       units_per_em,
+                                       point_size * glyph_position.scale,
+                                       device_scale,
+                                       subpixel_granularity);
+        let glyph_snapped_origin = glyph_snapped_rect.origin;
+
+        if let Some(bounding_rect) = bounding_rect {
+            if !glyph_snapped_rect.intersects(bounding_rect) {
+                continue
+            }
+        }
+
+        if glyph_snapped_rect.size.width < culling.min_size &&
+                glyph_snapped_rect.size.height < culling.min_size {
+            if let Some(ref mut small_glyph_count) = culling.small_glyph_count {
+                **small_glyph_count += 1
+            }
+            continue
+        }
+
+        let subpixel_x = if glyph_snapped_origin.x >= 0.0 {
+            glyph_snapped_origin.x.fract()
+        } else {
+            1.0 + glyph_snapped_origin.x.fract()
+        };
+
+        positioned_glyphs.push(PositionedGlyph {
+            bounds: glyph_snapped_rect,
+            subpixel_x: subpixel_x,
+            glyph_index: glyph_index,
+            style: glyph_position.style,
+            scale: glyph_position.scale,
+        })
+    }
+
+    positioned_glyphs
+}
+
+/// A line of laid-out text, as returned by `Typesetter::lines()`.
+#[derive(Clone, Copy, Debug)]
+pub struct LayoutLine<'a> {
+    /// The index of the first glyph on this line in `Typesetter::glyph_positions`.
+    pub glyph_start: usize,
+    /// The glyphs on this line.
+    pub glyph_positions: &'a [GlyphPosition],
+    /// The x position of the left edge of the line's content.
+    ///
+    /// For lines with no glyphs, this is zero.
+    pub left: f32,
+    /// The x position of the right edge of the line's content.
+    ///
+    /// For lines with no glyphs, this is zero.
+    pub right: f32,
+    /// The y position of the line's baseline.
+    pub baseline: f32,
+}
+
+/// The metrics of a line of laid-out text, as returned by `Typesetter::line_metrics()`.
+///
+/// All values are in pixels. Where fonts are mixed on a line, the ascent, descent, and line gap are
+/// the largest of those of the fonts used on it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LineMetrics {
+    /// The y position of the line's baseline.
+    pub baseline: f32,
+    /// The distance from the baseline to the top of the line.
+    pub ascent: f32,
+    /// The distance from the baseline to the bottom of the line.
+    pub descent: f32,
+    /// The recommended extra gap below the line.
+    pub line_gap: f32,
+    /// The x position of the left edge of the line's content, as in `LayoutLine`.
+    pub left: f32,
+    /// The x position of the right edge of the line's content, as in `LayoutLine`.
+    pub right: f32,
+}
+
+/// An iterator over the lines laid out by a typesetter.
+#[derive(Clone)]
+pub struct Lines<'a> {
+    typesetter: &'a Typesetter,
+    line_index: usize,
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = LayoutLine<'a>;
+
+    #[inline]
+    fn next(&mut self) -> Option<LayoutLine<'a>> {
+        let line = self.typesetter.line(self.line_index);
+        if line.is_some() {
+            self.line_index += 1
+        }
+        line
+    }
+}
+
+/// The codepoint-to-glyph mapping of a font, covering every character laid out in it so far.
+#[derive(Clone)]
+struct CachedGlyphMapping {
+    /// The ID of the font, from `Font::id()`.
+    font_id: usize,
+    /// The sorted, deduplicated characters that the mapping covers.
+    chars: Vec<char>,
+    glyph_mapping: GlyphMapping,
+    /// The advance width shared by every glyph, in font units, if the font is fixed-pitch.
+    fixed_advance: Option<u16>,
+    /// The space glyph and its advance, once `cached_space_advance()` has looked them up.
+    space_glyph: Option<CachedSpaceGlyph>,
+}
+
+/// The space glyph of a font, as remembered in its glyph mapping cache entry.
+#[derive(Clone, Copy, Debug)]
+struct CachedSpaceGlyph {
+    /// The glyph that the space character maps to, or 0 if the font has none.
+    glyph_id: u16,
+    /// The advance of the space glyph along the writing direction, in font units.
+    advance: u16,
+    /// True if `advance` is the vertical advance, for the vertical writing modes.
+    vertical: bool,
+}
+
+/// A line of laid-out text.
+#[derive(Clone, Copy, Debug)]
+struct Line {
+    /// The index of the first glyph on this line in `glyph_positions`.
+    glyph_start: usize,
+    /// The index of the first word on this line in `words`.
+    word_start: usize,
+    /// The y position of the baseline, or the x position of the center line of a column of
+    /// vertical text.
+    baseline: f32,
+    /// The largest distance above the baseline, in pixels, of the fonts used on this line.
+    ascent: f32,
+    /// The largest distance below the baseline, in pixels, of the fonts used on this line.
+    descent: f32,
+    /// The largest recommended gap between lines, in pixels, of the fonts used on this line.
+    line_gap: f32,
+    /// True if this line is the first line of a paragraph.
+    starts_paragraph: bool,
+    /// The distance, in pixels, from the start margin at which this line starts.
+    indent: f32,
+    /// The x position at which this line starts, past its indent, and the direction that it was
+    /// laid out in, as they were when the cursor was last moved to the start of the line. These
+    /// are zero and the typesetter's direction in vertical text.
+    start: f32,
+    direction: Direction,
+    /// The index of the page that this line is on. See `Typesetter::set_page_height()`.
+    page: usize,
+}
+
+impl Line {
+    #[inline]
+    fn grow(&mut self, ascent: f32, descent: f32, line_gap: f32) {
+        self.ascent = self.ascent.max(ascent);
+        self.descent = self.descent.max(descent);
+        self.line_gap = self.line_gap.max(line_gap);
+    }
+}
+
+/// A run of glyphs laid out without breaks.
+#[derive(Clone, Copy, Debug)]
+struct Word {
+    /// The index of the first glyph of this word in `glyph_positions`.
+    glyph_start: usize,
+    /// The index just past the last glyph of this word in `glyph_positions`.
+    glyph_end: usize,
+    /// The x position of the left edge of the word's advance box, or the y position of its top
+    /// edge in vertical text.
+    left: f32,
+    /// The x position of the right edge of the word's advance box, or the y position of its
+    /// bottom edge in vertical text.
+    right: f32,
+    /// The index of the run that this word belongs to in `runs`.
+    run: usize,
+    /// How this word is joined to the one before it.
+    joint: Joint,
+}
+
+/// How a word is joined to the word before it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Joint {
+    /// The words are separated by a space.
+    Space,
+    /// The words are adjacent, and were split at a break opportunity such as a hyphen.
+    Direct,
+    /// The words are adjacent, and were split because the whole was too long for a line.
+    Split,
+    /// The words are adjacent, and were split at a soft hyphen.
+    SoftHyphen,
+    /// This word is the hyphen inserted at the end of a line broken at a soft hyphen, adjacent to
+    /// the word before it. It is not laid out again by `reflow()`.
+    InsertedHyphen,
+}
+
+/// A part of a word that can be laid out on its own.
+#[derive(Clone, Debug)]
+struct WordPiece {
+    /// The range of the glyphs of this piece within the shaped word.
+    glyph_range: Range<usize>,
+    /// How this piece is joined to the one before it.
+    joint: Joint,
+}
+
+/// The settings and font metrics in effect for a single call to `add_text()`.
+///
+/// All values are in pixels.
+#[derive(Clone, Copy, Debug)]
+struct Run {
+    direction: Direction,
+    space_advance: f32,
+    letter_spacing: f32,
+    tab_width: f32,
+    /// The distance by which glyphs are raised above the baseline, in pixels.
+    baseline_shift: f32,
+    break_policy: BreakPolicy,
+    /// The ellipsis glyph in the font of this run, if it has one.
+    ellipsis: Option<WordGlyph>,
+    /// The hyphen glyph in the font of this run, if it has one.
+    hyphen: Option<WordGlyph>,
+    /// The ID of the glyph for the decimal point in the font of this run, if it has one.
+    decimal_point: Option<u16>,
+    line_spacing: f32,
+    style: u32,
+    ascent: f32,
+    descent: f32,
+    line_gap: f32,
+    /// The glyph scale, as set by `Typesetter::set_glyph_scale()`.
+    scale: f32,
+    /// The number of lines spanned by this run if it is a drop cap, or zero otherwise.
+    drop_cap_lines: usize,
+    /// The index of the text that this run is part of. See `GlyphSource::text_index`.
+    text_index: usize,
+}
+
+/// A drop cap laid out by `add_drop_cap()`, together with the lines indented past it.
+#[derive(Clone, Copy, Debug)]
+struct DropCap {
+    /// The index in `words` of the drop cap, or `None` once it has been streamed out.
+    word: Option<usize>,
+    /// The index of the line that the drop cap starts on.
+    line: usize,
+    /// The index of the line after the last one that the drop cap spans.
+    line_end: usize,
+    /// The advance of the drop cap, by which the lines it spans are indented, in pixels.
+    advance: f32,
+}
+
+/// A snapshot of the layout of a typesetter, returned by `Typesetter::snapshot()`.
+#[derive(Clone, Debug)]
+pub struct LayoutState {
+    run_count: usize,
+    run_start: RunStart,
+    line_offset: f32,
+    line_stretch: f32,
+    line_reordered: bool,
+}
+
+/// The layout state just before a run was laid out, saved so that `pop_last_run()` can return
+/// to it.
+#[derive(Clone, Debug)]
+struct RunStart {
+    cursor: Point2D<f32>,
+    /// The number of lines, including the one that the run started on.
+    line_count: usize,
+    line: Line,
+    /// Copies of the glyphs and words already on the line that the run started on.
+    line_glyph_positions: Vec<GlyphPosition>,
+    line_glyphs: Vec<WordGlyph>,
+    line_words: Vec<Word>,
+    control_count: usize,
+    trailing_space: f32,
+    tab_column: Option<TabColumn>,
+    line_limit_reached: bool,
+    truncated: bool,
+    ellipsis_word: Option<usize>,
+    drop_cap: Option<DropCap>,
+    paragraph_start: Option<Box<RunStart>>,
+}
+
+impl RunStart {
+    /// Moves the saved cursor, line, glyphs, and words by the given offsets, as
+    /// `Typesetter::translate()` does.
+    fn translate(&mut self, delta: Point2D<f32>, line_delta: f32, word_delta: f32) {
+        self.cursor = self.cursor + delta;
+        self.line.baseline += line_delta;
+        self.line.start += word_delta;
+        for glyph_position in &mut self.line_glyph_positions {
+            glyph_position.x += delta.x;
+            glyph_position.y += delta.y;
+        }
+        for word in &mut self.line_words {
+            word.left += word_delta;
+            word.right += word_delta;
+        }
+        if let Some(ref mut paragraph_start) = self.paragraph_start {
+            paragraph_start.translate(delta, line_delta, word_delta)
+        }
+    }
+
+    /// Adjusts the saved line, words, and controls after `Typesetter::add_text_streaming()`
+    /// removed the given numbers of glyphs, words, and controls on the lines before the one with
+    /// the given index, which must be a line at or before the one that the run started on.
+    ///
+    /// The saved paragraph start is dropped if its paragraph started on a streamed line.
+    fn discard_streamed(&mut self,
+                        line_index: usize,
+                        glyph_count: usize,
+                        word_count: usize,
+                        control_count: usize) {
+        self.line.glyph_start -= glyph_count;
+        self.line.word_start -= word_count;
+        for word in &mut self.line_words {
+            word.glyph_start -= glyph_count;
+            word.glyph_end -= glyph_count;
+        }
+        self.control_count -= control_count;
+        self.ellipsis_word = self.ellipsis_word
+                                 .and_then(|word_index| word_index.checked_sub(word_count));
+        if let Some(ref mut drop_cap) = self.drop_cap {
+            drop_cap.word = drop_cap.word.and_then(|word_index| word_index.checked_sub(word_count))
+        }
+        self.paragraph_start = match self.paragraph_start.take() {
+            Some(mut paragraph_start) if paragraph_start.line_count > line_index => {
+                paragraph_start.discard_streamed(line_index,
+                                                 glyph_count,
+                                                 word_count,
+                                                 control_count);
+                Some(paragraph_start)
+            }
+            _ => None,
+        };
+    }
+}
+
+/// Words, glyphs, and controls copied from a layout so that `reflow()` and `relayout_from()` can
+/// lay them out again.
+struct RetainedLayout {
+    /// The index in `words` of the first word copied.
+    word_start: usize,
+    /// The index in `glyph_positions` of the first glyph copied.
+    glyph_start: usize,
+    words: Vec<Word>,
+    controls: Vec<Control>,
+    glyphs: Vec<WordGlyph>,
+    ellipsis_word: Option<usize>,
+    /// Whether each word, with split words put back together, must start a new line. Words past
+    /// the end are placed wherever they fit.
+    line_breaks: Vec<bool>,
+}
+
+/// A word as seen by `optimal_line_breaks()`.
+#[derive(Clone, Copy, Debug)]
+struct BreakItem {
+    /// The advance of the word, in pixels.
+    width: f32,
+    /// The advance of the space between this word and the previous one, or zero if they are
+    /// adjacent.
+    space_before: f32,
+    /// The advance of the hyphen that ends the previous line if a line is broken before this
+    /// word.
+    hyphen_advance: f32,
+    /// True if this word is separated from the previous one by a space.
+    at_space: bool,
+}
+
+/// A forced line break, tab, or preserved space, recorded so that it can be replayed by
+/// `reflow()`.
+#[derive(Clone, Copy, Debug)]
+struct Control {
+    /// The index in `words` of the word that follows this control character.
+    word_index: usize,
+    /// The index of the run that this control character belongs to in `runs`.
+    run: usize,
+    kind: ControlKind,
+}
+
+/// The text after a right or decimal tab stop on the current line, which is moved toward the start
+/// of the line as it grows by `Typesetter::align_tab_column()`.
+#[derive(Clone, Copy, Debug)]
+struct TabColumn {
+    /// The distance of the tab stop from the start margin, in pixels.
+    stop: f32,
+    alignment: TabAlign,
+    /// The numbers of glyphs and words on the current line before the text.
+    line_glyph_start: usize,
+    line_word_start: usize,
+    /// The distance, in pixels, by which the text has been moved away from the tab.
+    shift: f32,
+    /// True once the decimal point of a decimal-aligned column has been placed.
+    anchored: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ControlKind {
+    LineBreak,
+    Tab,
+    Space,
+}
 
-#[derive(Clone)]
-pub struct Typesetter {
+/// The optional parts of a call to `add_text()` or one of its variants.
+#[derive(Clone, Copy, Default)]
+struct RunOptions<'a> {
+    /// The style passed to `add_text_with_style()`.
+    style: u32,
+    /// The bidi embedding levels passed to `add_text_with_levels()`, or an empty slice.
+    levels: &'a [u8],
+    hyphenator: Option<&'a dyn Hyphenator>,
+    substitutions: Option<&'a HashMap<u16, u16>>,
+    /// The OpenType features passed to `add_text_with_features()`, or an empty slice.
+    features: &'a [(u32, u32)],
+    /// The script and language tags passed to `add_text_with_language()`.
+    language: Option<(u32, u32)>,
+    /// True if the string continues the word laid out last, as when `add_text_with_fallback()`
+    /// switches fonts partway through a word.
+    joined: bool,
+    /// True if the string continues the text laid out last, as when `add_text_with_fallback()`
+    /// switches fonts, so that it shares that text's index.
+    continues_text: bool,
+    /// The byte offset of the string within the text passed to `add_text_with_fallback()`,
+    /// which is added to the byte ranges of its glyphs.
+    byte_offset: usize,
+}
+
+/// A shaped glyph waiting to be placed.
+#[derive(Clone, Copy, Debug)]
+struct WordGlyph {
+    glyph_id: u16,
+    /// The advance in pixels.
+    advance: f32,
+    /// The position of the glyph origin relative to the pen, in pixels. This is zero in
+    /// horizontal text.
+    offset: Point2D<f32>,
+    /// The byte range within the string passed to `add_text()` of the character that produced
+    /// this glyph.
+    byte_start: usize,
+    byte_end: usize,
+    /// The bidi embedding level of that character, if levels were passed to
+    /// `add_text_with_levels()`.
+    level: Option<u8>,
+}
+
+/// A word shaped by `Typesetter::shape_words()`, ready to be positioned by the caller.
+#[derive(Clone, Debug)]
+pub struct WordMetrics {
+    /// The byte range of the word within the string that was shaped.
+    pub byte_range: Range<usize>,
+    /// The positions of the word's glyphs, relative to the pen at the start of the word.
     pub glyph_positions: Vec<GlyphPosition>,
-    page_width: f32,
-    cursor: Point2D<f32>,
+    /// The total advance of the word, in pixels, including letter spacing.
+    pub advance: f32,
 }
 
-impl Typesetter {
-    pub fn new(page_width: f32, initial_font: &Font, initial_point_size: f32) -> Typesetter {
-        let pixels_per_unit = initial_point_size / initial_font.units_per_em() as f32;
-        let initial_position = initial_font.ascender() as f32 * pixels_per_unit;
+/// A glyph shaped outside the typesetter, to be laid out with `Typesetter::add_shaped_run()`.
+///
+/// All distances are in font units.
+#[derive(Clone, Copy, Debug)]
+pub struct ShapedGlyph {
+    pub glyph_id: u16,
+    /// How far to move the pen after placing this glyph.
+    pub advance: i32,
+    /// How far to the right of the pen to draw this glyph.
+    pub x_offset: i32,
+    /// How far above the pen to draw this glyph.
+    pub y_offset: i32,
+    /// The byte offset, within the shaped text, of the first character of the cluster that
+    /// produced this glyph.
+    pub cluster: u32,
+}
 
-        Typesetter {
-            glyph_positions: vec![],
-            page_width: page_width,
-            cursor: Point2D::new(0.0, initial_position),
+/// The text that produced a glyph. See `Typesetter::glyph_source()`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GlyphSource {
+    /// The index of the call to `add_text()`, or one of its variants, that laid out the glyph,
+    /// counting from zero since the typesetter was created or last cleared. Text appended with
+    /// `append_block()` is numbered after the text already laid out, in its original order.
+    pub text_index: usize,
+    /// The range of bytes within the string passed to that call.
+    pub byte_range: Range<usize>,
+}
+
+/// How lines are positioned between the margins.
+///
+/// Lines are aligned by the advance boxes of their words, so spaces at the end of a line don't
+/// move it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Alignment {
+    /// Lines are flush with the left margin.
+    Left,
+    /// Lines are flush with the right margin.
+    Right,
+    /// Lines are centered between the margins.
+    Center,
+    /// Inter-word spacing is expanded so that lines fill the page. The last line of each
+    /// paragraph is instead flush with the edge at which it starts.
+    Justify,
+}
+
+/// A position that tabs move the cursor to, set by `Typesetter::set_tab_stops()`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TabStop {
+    /// The distance of the stop from the start margin (the left margin in left-to-right text), in
+    /// pixels.
+    pub position: f32,
+    pub alignment: TabAlign,
+}
+
+/// How the text after a tab is lined up with its tab stop.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TabAlign {
+    /// The text starts at the stop.
+    Left,
+    /// The text ends at the stop.
+    Right,
+    /// The first decimal point (`.`) in the text is at the stop. Text without one ends at the
+    /// stop, as with `Right`.
+    Decimal,
+}
+
+/// Gives shaped glyphs their vertical advances, and offsets them so that each is centered on the
+/// pen horizontally with its top side bearing below the pen.
+fn apply_vertical_metrics(font: &Font, pixels_per_unit: f32, glyphs: &mut [WordGlyph]) {
+    for glyph in glyphs {
+        let metrics = match font.vertical_metrics_for_glyph(glyph.glyph_id) {
+            Err(_) => VerticalMetrics::default(),
+            Ok(metrics) => metrics,
+        };
+        let top = font.glyph_bounds(glyph.glyph_id).map(|bounds| bounds.top).unwrap_or(0);
+
+        // Glyph outlines are y-up, so the horizontal baseline is the top of the glyph plus its
+        // top side bearing below the pen.
+        glyph.offset = Point2D::new(-glyph.advance * 0.5,
+                                    (top + metrics.tsb as i32) as f32 * pixels_per_unit);
+        glyph.advance = metrics.advance_height as f32 * pixels_per_unit;
+    }
+}
+
+/// Replaces each glyph of a shaped word that is a key of the given map with the glyph it maps to,
+/// giving it its own advance.
+fn substitute_glyphs(font: &Font,
+                     pixels_per_unit: f32,
+                     substitutions: &HashMap<u16, u16>,
+                     word_glyphs: &mut [WordGlyph]) {
+    for glyph in word_glyphs {
+        let glyph_id = match substitutions.get(&glyph.glyph_id) {
+            None => continue,
+            Some(&glyph_id) => glyph_id,
+        };
+        glyph.glyph_id = glyph_id;
+        if let Ok(metrics) = font.metrics_for_glyph(glyph_id) {
+            glyph.advance = metrics.advance_width as f32 * pixels_per_unit
         }
     }
+}
 
-    pub fn add_text(&mut self, font: &Font, point_size: f32, string: &str) {
-        // TODO(pcwalton): Cache this mapping.
-        let mut chars: Vec<char> = string.chars().collect();
-        chars.push(' ');
-        chars.sort();
-        let codepoint_ranges = CodepointRanges::from_sorted_chars(&chars);
-        let glyph_mapping = font.glyph_mapping_for_codepoint_ranges(&codepoint_ranges.ranges)
-                                .unwrap();
+/// Returns the total advance of the glyphs of a word, including letter spacing, in pixels.
+fn word_advance(glyphs: &[WordGlyph], letter_spacing: f32) -> f32 {
+    let advance = glyphs.iter().map(|glyph| glyph.advance).sum::<f32>();
+    let spaced_glyph_count = glyphs.iter().skip(1).filter(|glyph| glyph.advance != 0.0).count();
+    advance + letter_spacing * spaced_glyph_count as f32
+}
 
-        // All of these values are in pixels.
-        let pixels_per_unit = point_size / font.units_per_em() as f32;
-        let space_advance = font.metrics_for_glyph(glyph_mapping.glyph_for(' ' as u32).unwrap())
-                                .unwrap()
-                                .advance_width as f32 * pixels_per_unit;
-        let line_spacing = (font.ascender() as f32 - font.descender() as f32 +
-                            font.line_gap() as f32) * pixels_per_unit;
+/// Widens the byte range of each glyph of a shaped word to the extended grapheme cluster that
+/// contains it, so that all glyphs of what a reader sees as one character share a cluster.
+fn merge_grapheme_clusters(word: &str, word_glyphs: &mut [WordGlyph]) {
+    if word.is_ascii() {
+        return
+    }
 
-        for word in string.split_whitespace() {
-            let shaped_glyph_positions = shaper::shape_text(&font, &glyph_mapping, word);
-            let total_advance = pixels_per_unit *
-                shaped_glyph_positions.iter().map(|p| p.advance as f32).sum::<f32>();
-            if self.cursor.x + total_advance > self.page_width {
-                self.cursor.x = 0.0;
-                self.cursor.y += line_spacing;
-            }
-
-            for glyph_position in &shaped_glyph_positions {
-                self.glyph_positions.push(GlyphPosition {
-                    x: self.cursor.x,
-                    y: self.cursor.y,
-                    glyph_id: glyph_position.glyph_id,
-                });
-                self.cursor.x += glyph_position.advance as f32 * pixels_per_unit;
+    let cluster_starts: Vec<usize> = word.grapheme_indices(true)
+                                         .map(|(byte_start, _)| byte_start)
+                                         .collect();
+    for glyph in word_glyphs {
+        let cluster_index = match cluster_starts.binary_search(&glyph.byte_start) {
+            Ok(cluster_index) => cluster_index,
+            Err(cluster_index) => cluster_index - 1,
+        };
+        glyph.byte_start = cluster_starts[cluster_index];
+        glyph.byte_end = cluster_starts.get(cluster_index + 1).cloned().unwrap_or(word.len());
+    }
+}
+
+/// Returns true if the glyph at the given index of a shaped word starts a new cluster, so that
+/// the word may be split before it.
+///
+/// Zero-advance glyphs, such as combining marks, never start a cluster.
+fn starts_cluster(glyphs: &[WordGlyph], glyph_index: usize) -> bool {
+    glyph_index == 0 ||
+        (glyphs[glyph_index].advance != 0.0 &&
+         glyphs[glyph_index].byte_start != glyphs[glyph_index - 1].byte_start)
+}
+
+/// Chooses where to break a paragraph made of the given words into lines so as to minimize its
+/// total demerits, and returns, for each word, whether it starts a new line.
+///
+/// `line_widths` holds the available width of each line of the paragraph in turn, the last of
+/// which applies to all lines after it as well.
+fn optimal_line_breaks(items: &[BreakItem], line_widths: &[f32]) -> Vec<bool> {
+    // The best way found to start a line with each word, as the number of that line, clamped to
+    // the last entry in `line_widths`.
+    let line_classes = line_widths.len();
+    let min_stretch = items.iter()
+                           .map(|item| item.space_before * SPACE_STRETCH)
+                           .fold(1.0, f32::max);
+    let mut best: Vec<Option<(f32, usize)>> = vec![None; (items.len() + 1) * line_classes];
+    best[0] = Some((0.0, 0));
+    for start in 0..items.len() {
+        for line_class in 0..line_classes {
+            let demerits = match best[start * line_classes + line_class] {
+                Some((demerits, _)) => demerits,
+                None => continue,
+            };
+
+            let (mut width, mut stretch) = (0.0, 0.0);
+            for end in (start + 1)..(items.len() + 1) {
+                if end > start + 1 {
+                    width += items[end - 1].space_before;
+                    stretch += items[end - 1].space_before * SPACE_STRETCH
+                }
+                width += items[end - 1].width;
+
+                // A word too long for a line by itself gets a line of its own.
+                if width > line_widths[line_class] && end > start + 1 {
+                    break
+                }
+
+                // Lines can't break before a word with no advance, such as a lone combining mark.
+                if end < items.len() && items[end].width == 0.0 {
+                    continue
+                }
+
+                let hyphen_advance = items.get(end).map_or(0.0, |item| item.hyphen_advance);
+                let slack = line_widths[line_class] - width - hyphen_advance;
+                if slack < 0.0 && end > start + 1 {
+                    continue
+                }
+
+                // Lines without spaces are treated as though they had one, so that they aren't
+                // infinitely bad.
+                let badness = if end == items.len() {
+                    0.0
+                } else if slack < 0.0 {
+                    INFINITE_BADNESS
+                } else {
+                    100.0 * (slack / stretch.max(min_stretch)).powi(3)
+                };
+                let mut line_demerits = (1.0 + badness) * (1.0 + badness);
+                if end < items.len() && !items[end].at_space {
+                    line_demerits += HYPHEN_PENALTY * HYPHEN_PENALTY
+                }
+
+                let next_class = cmp::min(line_class + 1, line_classes - 1);
+                let entry = &mut best[end * line_classes + next_class];
+                let total_demerits = demerits + line_demerits;
+                if entry.map_or(true, |(best_demerits, _)| total_demerits < best_demerits) {
+                    *entry = Some((total_demerits, start * line_classes + line_class))
+                }
             }
+        }
+    }
 
-            self.cursor.x += space_advance
+    // Follow the best path back from the end of the paragraph.
+    let mut line_breaks = vec![false; items.len()];
+    let end_states = (items.len() * line_classes)..((items.len() + 1) * line_classes);
+    let mut state = end_states.filter(|&state| best[state].is_some()).min_by(|&a, &b| {
+        best[a].unwrap().0.partial_cmp(&best[b].unwrap().0).unwrap_or(cmp::Ordering::Equal)
+    });
+    while let Some(current_state) = state {
+        let line_start = current_state / line_classes;
+        if line_start == 0 {
+            break
+        }
+        if line_start < items.len() {
+            line_breaks[line_start] = true
         }
+        state = best[current_state].map(|(_, previous_state)| previous_state)
     }
+    line_breaks
+}
 
-    pub fn glyph_positions(&self) -> &[GlyphPosition] {
-        &self.glyph_positions
+/// Chooses where to break a paragraph made of the given words into lines so that they are as
+/// even as possible without adding any, and returns, for each word, whether it starts a new line.
+///
+/// This searches for the narrowest width, in proportion to `line_widths`, at which greedy line
+/// breaking yields no more lines than it does at the full widths.
+fn balanced_line_breaks(items: &[BreakItem], line_widths: &[f32]) -> Vec<bool> {
+    let line_breaks = greedy_line_breaks(items, line_widths, 1.0);
+    let line_count = line_breaks.iter().filter(|&&line_break| line_break).count();
+    if line_count == 0 {
+        return line_breaks
     }
 
-    pub fn create_glyph_store(&self, font: &Font) -> Result<GlyphStore, GlyphStoreCreationError> {
-        let glyph_ids = self.glyph_positions
-                            .iter()
-                            .map(|glyph_position| glyph_position.glyph_id)
-                            .collect();
-        GlyphStore::from_glyph_ids(glyph_ids, font)
+    let (mut low, mut high, mut best) = (0.0, 1.0, line_breaks);
+    for _ in 0..BALANCE_ITERATIONS {
+        let scale = (low + high) * 0.5;
+        let line_breaks = greedy_line_breaks(items, line_widths, scale);
+        if line_breaks.iter().filter(|&&line_break| line_break).count() > line_count {
+            low = scale
+        } else {
+            high = scale;
+            best = line_breaks
+        }
+    }
+    best
+}
+
+/// Breaks a paragraph made of the given words into lines by filling each line in turn, as
+/// `Typesetter::place_word()` does, with every line width multiplied by `scale`.
+fn greedy_line_breaks(items: &[BreakItem], line_widths: &[f32], scale: f32) -> Vec<bool> {
+    let mut line_breaks = vec![false; items.len()];
+    let (mut line_index, mut width) = (0, 0.0);
+    for (item_index, item) in items.iter().enumerate() {
+        if item_index == 0 {
+            width = item.width;
+            continue
+        }
+
+        let line_width = line_widths[cmp::min(line_index, line_widths.len() - 1)] * scale;
+        let hyphen_advance = items.get(item_index + 1).map_or(0.0, |next_item| {
+            next_item.hyphen_advance
+        });
+        let extended_width = width + item.space_before + item.width;
+        if item.width == 0.0 || extended_width + hyphen_advance <= line_width {
+            width = extended_width;
+            continue
+        }
+
+        line_breaks[item_index] = true;
+        line_index += 1;
+        width = item.width
     }
+    line_breaks
+}
 
-    /// Returns the positions of the glyphs that intersect the given pixel rectangle.
-    ///
-    /// Requires a `GlyphStore` to be created first.
-    pub fn positioned_glyphs_in_rect(&self,
-                                     bounding_rect: &Rect<f32>,
-                                     glyph_store: &GlyphStore,
-                                     point_size: f32,
-                                     scale: f32,
-                                     subpixel_granularity: f32)
-                                     -> Vec<PositionedGlyph> {
-        let subpixel_inv_granularity = 1.0 / subpixel_granularity;
+/// Splits a word into the pieces between which the line may be broken, leaving out soft hyphens.
+///
+/// The first piece is joined to the word before by a space. Pieces are returned as ranges of
+/// character indices, which are also glyph indices. A word consisting only of soft hyphens has no
+/// pieces. The word is also split at the given byte offsets, which are treated as soft hyphens.
+fn word_pieces(word: &str, break_policy: BreakPolicy, hyphenation_points: &[usize])
+               -> Vec<WordPiece> {
+    let break_opportunities = word_break_opportunities(word, break_policy);
 
-        let mut positioned_glyphs = vec![];
-        for glyph_position in &self.glyph_positions {
-            // If this glyph is not in the glyph store, just skip it.
-            //
-            // TODO(pcwalton): Notify the caller somehow?
-            let glyph_index = match glyph_store.glyph_index(glyph_position.glyph_id) {
-                None => continue,
-                Some(glyph_index) => glyph_index,
-            };
+    // Words are never broken inside a grapheme cluster.
+    let cluster_starts: Vec<usize> = if word.is_ascii() {
+        vec![]
+    } else {
+        word.grapheme_indices(true).map(|(byte_start, _)| byte_start).collect()
+    };
+    let inside_cluster = |byte_index: usize| {
+        !cluster_starts.is_empty() && cluster_starts.binary_search(&byte_index).is_err()
+    };
+
+    let mut pieces = vec![];
+    let (mut piece_start, mut joint) = (0, Joint::Space);
+    for (char_index, (byte_index, ch)) in word.char_indices().enumerate() {
+        let next_joint = if ch == SOFT_HYPHEN {
+            Joint::SoftHyphen
+        } else if inside_cluster(byte_index) {
+            continue
+        } else if break_opportunities.contains(&char_index) {
+            Joint::Direct
+        } else if hyphenation_points.contains(&byte_index) {
+            Joint::SoftHyphen
+        } else {
+            continue
+        };
 
-            let mut glyph_subpixel_bounds = glyph_store.outlines.glyph_subpixel_bounds(glyph_index,
-                                                                                       point_size);
-            glyph_subpixel_bounds.scale(scale);
-            let glyph_pixel_bounds = glyph_subpixel_bounds.round_out();
+        if char_index > piece_start {
+            pieces.push(WordPiece {
+                glyph_range: piece_start..char_index,
+                joint: joint,
+            });
+            joint = next_joint
+        } else if !pieces.is_empty() && next_joint == Joint::SoftHyphen {
+            joint = next_joint
+        }
+        piece_start = if ch == SOFT_HYPHEN { char_index + 1 } else { char_index };
+    }
 
-            // Snap the rect to the nearest granule.
-            let glyph_snapped_origin =
-                Point2D::new((glyph_position.x * scale * subpixel_inv_granularity).round() *
-                             subpixel_granularity,
-                             ((glyph_position.y * scale).round() - glyph_pixel_bounds.top as f32));
-            let glyph_snapped_rect = Rect::new(glyph_snapped_origin, glyph_subpixel_bounds.size());
+    let char_count = word.chars().count();
+    if char_count > piece_start {
+        pieces.push(WordPiece {
+            glyph_range: piece_start..char_count,
+            joint: joint,
+        })
+    }
+    pieces
+}
 
-            debug_assert!(glyph_snapped_rect.origin.y == glyph_snapped_rect.origin.y.round());
+/// Returns the character indices within the given word, in increasing order, at which the break
+/// policy allows the line to be broken.
+fn word_break_opportunities(word: &str, break_policy: BreakPolicy) -> Vec<usize> {
+    let mut break_opportunities = vec![];
+    if break_policy == BreakPolicy::Whitespace {
+        return break_opportunities
+    }
 
-            if !glyph_snapped_rect.intersects(bounding_rect) {
-                continue
+    let char_count = word.chars().count();
+    for (char_index, ch) in word.chars().enumerate() {
+        // Breaks are allowed after hyphens and both before and after slashes, but never at the
+        // very start or end of the word.
+        if ch == '/' && char_index > 0 && break_opportunities.last() != Some(&char_index) {
+            break_opportunities.push(char_index)
+        }
+        if (ch == '-' || ch == '/') && char_index > 0 && char_index + 1 < char_count {
+            break_opportunities.push(char_index + 1)
+        }
+    }
+    break_opportunities
+}
+
+/// Chooses where words may be hyphenated. See `Typesetter::add_text_with_hyphenator()`.
+///
+/// The typesetter doesn't come with any hyphenation dictionaries; implement this to use one.
+pub trait Hyphenator {
+    /// Returns the byte offsets within the word at which it may be broken, with a hyphen
+    /// inserted before the offset. Offsets of zero, offsets at or past the end of the word, and
+    /// offsets that aren't character boundaries are ignored.
+    fn break_points(&self, word: &str) -> Vec<usize>;
+}
+
+/// Where lines may be broken.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BreakPolicy {
+    /// Lines break only at whitespace. Words too long for a line overflow it.
+    Whitespace,
+    /// Lines also break after hyphens and before and after slashes.
+    Punctuation,
+    /// Like `Punctuation`, but words that are too long for a line by themselves are also split
+    /// after the last glyph that fits.
+    Anywhere,
+}
+
+/// How paragraphs are broken into lines.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineBreakStrategy {
+    /// Each line is filled with as many words as fit before moving on to the next.
+    Greedy,
+    /// Each paragraph is broken so as to make its lines as even as possible, as TeX does. Spaces
+    /// are treated as able to stretch by half their width, though they are only actually
+    /// stretched in justified text, and never shrink.
+    OptimalFit,
+    /// Each paragraph is broken into as many lines as greedy breaking gives it, but at the
+    /// narrowest width that needs no more lines, so that the lines are close to the same length,
+    /// as CSS's `text-wrap: balance` does. This suits short blocks such as captions and tooltips.
+    Balanced,
+}
+
+/// How runs of spaces between words are laid out.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WhitespaceMode {
+    /// Each run of whitespace is replaced by a single space, and whitespace at the start of a
+    /// line is dropped.
+    Collapse,
+    /// Each whitespace character other than a newline or tab advances by one space. The space
+    /// that follows a word counts as the first, so whitespace at the start of a string adds to
+    /// the space after the last word of the previous one.
+    Preserve,
+}
+
+/// The distance between the baselines of successive lines.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineSpacing {
+    /// A multiple of the font's line height, `ascender - descender + line_gap`.
+    Multiple(f32),
+    /// A fixed distance in pixels.
+    Fixed(f32),
+}
+
+/// Fixed line metrics that replace those of fonts. See `Typesetter::set_line_box()`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LineBox {
+    /// The distance from the baseline to the top of the line, in pixels.
+    pub ascent: f32,
+    /// The distance from the baseline to the bottom of the line, in pixels.
+    pub descent: f32,
+}
+
+/// What happens to a word too wide for a line of its own. See
+/// `Typesetter::set_overflow_policy()`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OverflowPolicy {
+    /// The word is laid out in full and extends past the end margin.
+    Allow,
+    /// The glyphs of the word that would extend past the end margin are dropped.
+    Clip,
+}
+
+/// What happens to characters that a font has no glyph for. See
+/// `Typesetter::set_notdef_policy()`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NotdefPolicy {
+    /// The font's missing glyph is laid out in their place.
+    Show,
+    /// They are left out and recorded.
+    Skip,
+    /// The font's missing glyph is laid out in their place, and they are recorded.
+    Report,
+}
+
+/// How the x positions of glyphs are rounded during layout.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PenSnapping {
+    /// Glyphs are placed at exact fractional positions.
+    None,
+    /// Glyphs are placed at whole pixels.
+    Integer,
+    /// Glyphs are placed at multiples of the given fraction of a pixel. Use the same granularity
+    /// as is passed to `positioned_glyphs_in_rect()` so that layout and rendering agree.
+    Subpixel(f32),
+}
+
+impl PenSnapping {
+    #[inline]
+    fn snap(self, x: f32) -> f32 {
+        match self {
+            PenSnapping::Subpixel(granularity) if granularity > 0.0 => {
+                (x / granularity).round() * granularity
             }
+            PenSnapping::None | PenSnapping::Subpixel(_) => x,
+            PenSnapping::Integer => x.round(),
+        }
+    }
+}
 
-            let subpixel_x = if glyph_snapped_origin.x >= 0.0 {
-                glyph_snapped_origin.x.fract()
-            } else {
-                1.0 + glyph_snapped_origin.x.fract()
-            };
+/// The orientation in which lines of text are laid out.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WritingMode {
+    /// Glyphs advance along horizontal lines, which stack downward.
+    Horizontal,
+    /// Glyphs stack downward in columns, and each column is to the left of the one before it, as
+    /// is traditional for Chinese and Japanese text.
+    VerticalRightToLeft,
+    /// Glyphs stack downward in columns, and each column is to the right of the one before it, as
+    /// for Mongolian text.
+    VerticalLeftToRight,
+}
 
-            positioned_glyphs.push(PositionedGlyph {
-                bounds: glyph_snapped_rect,
-                subpixel_x: subpixel_x,
-                glyph_index: glyph_index,
-            })
+impl WritingMode {
+    #[inline]
+    fn is_vertical(self) -> bool {
+        self != WritingMode::Horizontal
+    }
+
+    /// Returns the sign of the direction in which lines advance: down for horizontal text, and
+    /// left or right for vertical text.
+    #[inline]
+    fn column_sign(self) -> f32 {
+        match self {
+            WritingMode::Horizontal | WritingMode::VerticalLeftToRight => 1.0,
+            WritingMode::VerticalRightToLeft => -1.0,
+        }
+    }
+}
+
+/// The direction in which glyphs advance along a line.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    /// Lines start at the left margin, and the cursor moves rightward.
+    LeftToRight,
+    /// Lines start at the right margin, and the cursor moves leftward.
+    RightToLeft,
+}
+
+impl Direction {
+    /// Returns the bidi embedding level of a paragraph in this direction.
+    #[inline]
+    fn level(self) -> u8 {
+        match self {
+            Direction::LeftToRight => 0,
+            Direction::RightToLeft => 1,
         }
+    }
 
-        positioned_glyphs
+    #[inline]
+    fn sign(self) -> f32 {
+        match self {
+            Direction::LeftToRight => 1.0,
+            Direction::RightToLeft => -1.0,
+        }
     }
 }
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
 pub struct GlyphPosition {
+    /// The x position, in pixels, of the glyph's origin, where it meets its baseline.
     pub x: f32,
+    /// The y position, in pixels, of the glyph's baseline, not of the top of the glyph or its
+    /// line. See `top_left()`.
     pub y: f32,
+    /// How far `x` is to the right of the pen position at which the glyph was placed, from the
+    /// glyph's offset reported by the shaper. This is zero unless the shaper positions the glyph
+    /// specially, as it might a combining mark.
+    ///
+    /// In the vertical writing modes, it is the offset that centers the glyph on its column.
+    pub x_offset: f32,
+    /// How far `y` is below the pen position at which the glyph was placed. See `x_offset`.
+    pub y_offset: f32,
     pub glyph_id: u16,
+    /// The style passed to `Typesetter::add_text_with_style()`.
+    pub style: u32,
+    /// The byte offset, within the string passed to `Typesetter::add_text()`, of the cluster of
+    /// characters that produced this glyph. See `shaper::GlyphPos::cluster`. Clusters span whole
+    /// grapheme clusters, so every glyph of an emoji sequence or of a letter and its combining
+    /// marks has the offset of its first character.
+    ///
+    /// Selection and cursor movement should treat glyphs with the same cluster as one unit. The
+    /// ellipsis inserted by `Typesetter::set_max_lines()` has cluster zero.
+    pub cluster: u32,
+    /// The index of the page that this glyph is on. See `Typesetter::set_page_height()`.
+    pub page: u32,
+    /// The factor by which to scale the glyph when drawing it. See
+    /// `Typesetter::set_glyph_scale()`.
+    pub scale: f32,
 }
 
 impl GlyphPosition {
+    /// Returns the glyph's origin, on its baseline.
     #[inline]
     pub fn position(&self) -> Point2D<f32> {
         Point2D::new(self.x, self.y)
     }
+
+    /// Returns the top left corner of a box with the given ascent above the glyph's baseline,
+    /// for callers that position glyphs or other elements by their top edge.
+    ///
+    /// Pass the ascent of the font, or that of the glyph's line from `Typesetter::line_metrics()`
+    /// to line up with the top of the line.
+    #[inline]
+    pub fn top_left(&self, ascent: f32) -> Point2D<f32> {
+        Point2D::new(self.x, self.y - ascent)
+    }
+
+    /// Returns the rectangle, in device pixels, that a glyph with the given bounds in font units
+    /// covers at this position, exactly as `Typesetter::positioned_glyphs_in_rect()` computes it.
+    ///
+    /// The glyph is sized for the logical point size times the device scale, and its origin is
+    /// this position times the device scale, snapped horizontally to the nearest multiple of
+    /// `subpixel_granularity` and vertically so that the top of the glyph falls on a whole pixel.
+    pub fn device_rect(&self,
+                       glyph_bounds: &GlyphBounds,
+                       units_per_em: u16,
+                       point_size: f32,
+                       device_scale: f32,
+                       subpixel_granularity: f32)
+                       -> Rect<f32> {
+        let mut glyph_subpixel_bounds = glyph_bounds.subpixel_bounds(units_per_em, point_size);
+        glyph_subpixel_bounds.scale(device_scale);
+        let glyph_pixel_bounds = glyph_subpixel_bounds.round_out();
+
+        // Snap the rect to the nearest granule.
+        let subpixel_inv_granularity = 1.0 / subpixel_granularity;
+        let glyph_snapped_origin =
+            Point2D::new((self.x * device_scale * subpixel_inv_granularity).round() *
+                         subpixel_granularity,
+                         ((self.y * device_scale).round() - glyph_pixel_bounds.top as f32));
+        let glyph_snapped_rect = Rect::new(glyph_snapped_origin, glyph_subpixel_bounds.size());
+
+        debug_assert!(glyph_snapped_rect.origin.y == glyph_snapped_rect.origin.y.round());
+        glyph_snapped_rect
+    }
 }
 
 pub struct GlyphStore {
-    pub outlines: Outlines,
+    /// The outlines of the glyphs, which may be shared with other glyph stores. See
+    /// `GlyphStore::from_shared_outlines()`.
+    pub outlines: Arc<Outlines>,
     pub glyph_id_to_glyph_index: Vec<u16>,
+    pub glyph_index_to_glyph_id: Vec<u16>,
     pub all_glyph_indices: Vec<u16>,
+    /// The builder that the outlines came from, or `None` if they were borrowed from another
+    /// store.
+    outline_builder: Option<OutlineBuilder>,
 }
 
 impl GlyphStore {
-    fn from_glyph_ids(mut glyph_ids: Vec<u16>, font: &Font)
+    /// Creates a glyph store of the given glyphs, uploading their outlines to the GPU if `upload`
+    /// is true.
+    fn from_glyph_ids(glyph_ids: Vec<u16>, font: &Font, upload: bool)
                       -> Result<GlyphStore, GlyphStoreCreationError> {
+        let outline_builder = OutlineBuilder::new();
+        let outlines = if upload {
+            try!(outline_builder.create_buffers().map_err(GlyphStoreCreationError::GlError))
+        } else {
+            outline_builder.create_cpu_outlines()
+        };
+
+        let mut glyph_store = GlyphStore {
+            outlines: Arc::new(outlines),
+            glyph_id_to_glyph_index: vec![],
+            glyph_index_to_glyph_id: vec![],
+            all_glyph_indices: vec![],
+            outline_builder: Some(outline_builder),
+        };
+        try!(glyph_store.add_glyph_ids(&glyph_ids, font));
+        Ok(glyph_store)
+    }
+
+    /// Creates an empty glyph store whose outlines stay on the CPU, for measuring glyphs where
+    /// there's no GL context.
+    ///
+    /// Glyphs are added with `add_glyph_ids()`, and can be looked up and measured like those of
+    /// any other store, but not rasterized. See `Outlines::is_uploaded()`.
+    pub fn new_on_cpu() -> GlyphStore {
+        let outline_builder = OutlineBuilder::new();
+        GlyphStore {
+            outlines: Arc::new(outline_builder.create_cpu_outlines()),
+            glyph_id_to_glyph_index: vec![],
+            glyph_index_to_glyph_id: vec![],
+            all_glyph_indices: vec![],
+            outline_builder: Some(outline_builder),
+        }
+    }
+
+    /// Creates a glyph store for the given subset of the glyphs in another store, sharing that
+    /// store's outlines instead of building and uploading them again.
+    ///
+    /// Each glyph keeps the index that it has in `source`, so atlases and other data indexed by
+    /// glyph index can be shared too. Glyphs that aren't in `source` are left out. No glyphs can
+    /// be added to the new store, and none can be added to `source` while the new store exists.
+    pub fn from_shared_outlines(source: &GlyphStore, glyph_ids: &[u16]) -> GlyphStore {
+        let mut glyph_store = GlyphStore {
+            outlines: source.outlines.clone(),
+            glyph_id_to_glyph_index: vec![],
+            glyph_index_to_glyph_id: vec![],
+            all_glyph_indices: vec![],
+            outline_builder: None,
+        };
+        for &glyph_id in glyph_ids {
+            let glyph_index = match source.glyph_index(glyph_id) {
+                None => continue,
+                Some(glyph_index) => glyph_index,
+            };
+            if glyph_store.glyph_id_to_glyph_index.len() <= glyph_id as usize {
+                glyph_store.glyph_id_to_glyph_index.resize(glyph_id as usize + 1, u16::MAX)
+            }
+            glyph_store.glyph_id_to_glyph_index[glyph_id as usize] = glyph_index;
+            if glyph_store.glyph_index_to_glyph_id.len() <= glyph_index as usize {
+                glyph_store.glyph_index_to_glyph_id.resize(glyph_index as usize + 1, u16::MAX)
+            }
+            glyph_store.glyph_index_to_glyph_id[glyph_index as usize] = glyph_id;
+            glyph_store.all_glyph_indices.push(glyph_index);
+        }
+
+        glyph_store.all_glyph_indices.sort();
+        glyph_store.all_glyph_indices.dedup();
+        glyph_store
+    }
+
+    /// Adds the outlines of the given glyphs to this store and uploads them to the GPU.
+    ///
+    /// Glyphs already in the store are skipped, and the indices of existing glyphs don't change.
+    /// The store keeps a copy of all outlines on the CPU so that they need not be reread from the
+    /// font. With the `rayon` feature enabled, the outlines are read in parallel, which speeds up
+    /// building stores of many glyphs. See `OutlineBuilder::add_glyphs()`. If any of the glyphs
+    /// can't be read, none of them are added, so trying again gives the same glyph indices as
+    /// adding them all at once would have.
+    ///
+    /// Returns `GlyphStoreCreationError::OutlinesShared` if any glyphs would have to be added
+    /// while the outlines are shared with another store. See `from_shared_outlines()`.
+    pub fn add_glyph_ids(&mut self, glyph_ids: &[u16], font: &Font)
+                         -> Result<(), GlyphStoreCreationError> {
+        let mut glyph_ids = glyph_ids.to_vec();
         glyph_ids.sort();
         glyph_ids.dedup();
+        glyph_ids.retain(|&glyph_id| self.glyph_index(glyph_id).is_none());
+        if glyph_ids.is_empty() {
+            return Ok(())
+        }
 
-        let last_glyph_id = match glyph_ids.last() {
-            Some(&id) => id + 1,
-            None => 0,
+        let outline_builder = match self.outline_builder {
+            Some(ref mut outline_builder) => outline_builder,
+            None => return Err(GlyphStoreCreationError::OutlinesShared),
+        };
+        let outlines = match Arc::get_mut(&mut self.outlines) {
+            Some(outlines) => outlines,
+            None => return Err(GlyphStoreCreationError::OutlinesShared),
         };
 
-        let mut outline_builder = OutlineBuilder::new();
-        let mut glyph_id_to_glyph_index = vec![u16::MAX; last_glyph_id as usize];
-        let mut all_glyph_indices = vec![];
-        for glyph_id in glyph_ids {
-            let glyph_index = try!(outline_builder.add_glyph(font, glyph_id)
-                                                  .map_err(GlyphStoreCreationError::FontError));
-            glyph_id_to_glyph_index[glyph_id as usize] = glyph_index;
-            all_glyph_indices.push(glyph_index);
+        let glyph_indices = try!(outline_builder.add_all_glyphs(font, &glyph_ids)
+                                                .map_err(GlyphStoreCreationError::FontError));
+        let last_glyph_id = *glyph_ids.last().unwrap() as usize + 1;
+        if self.glyph_id_to_glyph_index.len() < last_glyph_id {
+            self.glyph_id_to_glyph_index.resize(last_glyph_id, u16::MAX)
+        }
+        for (glyph_id, glyph_index) in glyph_ids.into_iter().zip(glyph_indices) {
+            self.glyph_id_to_glyph_index[glyph_id as usize] = glyph_index;
+            if self.glyph_index_to_glyph_id.len() <= glyph_index as usize {
+                self.glyph_index_to_glyph_id.resize(glyph_index as usize + 1, u16::MAX)
+            }
+            self.glyph_index_to_glyph_id[glyph_index as usize] = glyph_id;
+            self.all_glyph_indices.push(glyph_index);
         }
 
-        let outlines = try!(outline_builder.create_buffers()
-                                           .map_err(GlyphStoreCreationError::GlError));
+        try!(outline_builder.update_buffers(outlines).map_err(GlyphStoreCreationError::GlError));
 
-        all_glyph_indices.sort();
-        all_glyph_indices.dedup();
+        self.all_glyph_indices.sort();
+        self.all_glyph_indices.dedup();
+        Ok(())
+    }
 
-        Ok(GlyphStore {
-            outlines: outlines,
-            glyph_id_to_glyph_index: glyph_id_to_glyph_index,
-            all_glyph_indices: all_glyph_indices,
-        })
+    /// Rebuilds the store with only those of its glyphs whose IDs are in `live_glyph_ids`,
+    /// dropping the outlines of the rest.
+    ///
+    /// Glyph IDs that aren't already in the store are ignored. The outlines are built from the
+    /// font again, and uploaded to new buffers if the store's were, so stores sharing the old
+    /// outlines keep them. The surviving glyphs get new indices: the returned vector maps each old
+    /// glyph index to the new one, or to `None` if the glyph was dropped, so that glyph indices
+    /// cached elsewhere, such as in `PositionedGlyph`s, can be updated. If an error is returned,
+    /// the store is left as it was.
+    pub fn compact(&mut self, live_glyph_ids: &[u16], font: &Font)
+                   -> Result<Vec<Option<u16>>, GlyphStoreCreationError> {
+        let glyph_ids = live_glyph_ids.iter()
+                                      .cloned()
+                                      .filter(|&glyph_id| self.glyph_index(glyph_id).is_some())
+                                      .collect();
+        let glyph_store = try!(GlyphStore::from_glyph_ids(glyph_ids,
+                                                          font,
+                                                          self.outlines.is_uploaded()));
+
+        let glyph_index_map = self.glyph_index_to_glyph_id.iter().map(|&glyph_id| {
+            if glyph_id == u16::MAX {
+                None
+            } else {
+                glyph_store.glyph_index(glyph_id)
+            }
+        }).collect();
+        *self = glyph_store;
+        Ok(glyph_index_map)
+    }
+
+    fn from_glyph_positions(glyph_positions: &[GlyphPosition], font: &Font)
+                            -> Result<GlyphStore, GlyphStoreCreationError> {
+        let glyph_ids = glyph_positions.iter()
+                                       .map(|glyph_position| glyph_position.glyph_id)
+                                       .collect();
+        GlyphStore::from_glyph_ids(glyph_ids, font, true)
     }
 
     pub fn from_codepoints(codepoints: &CodepointRanges, font: &Font)
@@ -209,7 +6021,13 @@ impl GlyphStore {
         let mapping = try!(font.glyph_mapping_for_codepoint_ranges(&codepoints.ranges)
                                .map_err(GlyphStoreCreationError::FontError));
         let glyph_ids = mapping.iter().map(|(_, glyph_id)| glyph_id).collect();
-        GlyphStore::from_glyph_ids(glyph_ids, font)
+        GlyphStore::from_glyph_ids(glyph_ids, font, true)
+    }
+
+    /// Creates a glyph store containing every glyph in the font, for example to build a complete
+    /// atlas for a small font.
+    pub fn from_all_glyphs(font: &Font) -> Result<GlyphStore, GlyphStoreCreationError> {
+        GlyphStore::from_glyph_ids((0..font.num_glyphs()).collect(), font, true)
     }
 
     #[inline]
@@ -219,12 +6037,235 @@ impl GlyphStore {
             Some(&index) => Some(index),
         }
     }
+
+    /// Returns an estimate of the number of device pixels that an atlas of the glyphs in this
+    /// store would occupy, for choosing the size of an atlas or deciding whether to evict glyphs
+    /// from one before rendering.
+    ///
+    /// This is the sum of the areas of the bounding boxes of each glyph in the store, at the
+    /// logical point size times the device scale, rounded out to whole pixels. Glyphs are counted
+    /// once however many times they're laid out, and padding and packing losses aren't included,
+    /// so an atlas needs at least this much room. Glyphs missing from the store's outlines are
+    /// ignored.
+    pub fn estimated_atlas_area(&self, point_size: f32, device_scale: f32) -> u64 {
+        self.all_glyph_indices.iter().filter_map(|&glyph_index| {
+            self.outlines.try_glyph_subpixel_bounds(glyph_index, point_size)
+        }).map(|mut glyph_subpixel_bounds| {
+            glyph_subpixel_bounds.scale(device_scale);
+            let glyph_size = glyph_subpixel_bounds.round_out().size();
+            glyph_size.width as u64 * glyph_size.height as u64
+        }).sum()
+    }
+
+    /// Returns true if this store contains the glyphs for every character in the string, so that
+    /// text laid out from it renders without any glyphs being skipped.
+    ///
+    /// Whitespace is ignored, since the typesetter doesn't position glyphs for it. Characters that
+    /// the font can't map need its missing glyph, glyph 0. If the font's character map can't be
+    /// read, this returns false.
+    pub fn covers(&self, font: &Font, string: &str) -> bool {
+        match self.missing_chars(font, string) {
+            Ok(missing_chars) => missing_chars.is_empty(),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the characters in the string whose glyphs are not in this store, sorted and with
+    /// duplicates removed. See `covers()`.
+    ///
+    /// The glyphs can then be added with `add_glyph_ids()` before laying out the text.
+    pub fn missing_chars(&self, font: &Font, string: &str) -> Result<Vec<char>, FontError> {
+        let mut chars: Vec<char> = string.chars().filter(|ch| !ch.is_whitespace()).collect();
+        chars.sort();
+        chars.dedup();
+        if chars.is_empty() {
+            return Ok(chars)
+        }
+
+        let codepoint_ranges = CodepointRanges::from_sorted_chars(&chars);
+        let glyph_mapping = try!(font.glyph_mapping_for_codepoint_ranges(&codepoint_ranges.ranges));
+        chars.retain(|&ch| {
+            let glyph_id = glyph_mapping.glyph_for(ch as u32).unwrap_or(0);
+            self.glyph_index(glyph_id).is_none()
+        });
+        Ok(chars)
+    }
+
+    /// Returns the ID of the glyph with the given index in this store, if there is one.
+    #[inline]
+    pub fn glyph_id(&self, glyph_index: u16) -> Option<u16> {
+        match self.glyph_index_to_glyph_id.get(glyph_index as usize) {
+            None | Some(&u16::MAX) => None,
+            Some(&glyph_id) => Some(glyph_id),
+        }
+    }
+
+    /// Returns an iterator over the `(glyph_id, glyph_index)` pairs of the glyphs in this store, in
+    /// increasing order of glyph ID.
+    #[inline]
+    pub fn glyphs(&self) -> Glyphs {
+        Glyphs {
+            glyph_id_to_glyph_index: self.glyph_id_to_glyph_index.iter().enumerate(),
+        }
+    }
+}
+
+/// An iterator over the glyphs in a glyph store.
+#[derive(Clone)]
+pub struct Glyphs<'a> {
+    glyph_id_to_glyph_index: Enumerate<Iter<'a, u16>>,
+}
+
+impl<'a> Iterator for Glyphs<'a> {
+    type Item = (u16, u16);
+
+    #[inline]
+    fn next(&mut self) -> Option<(u16, u16)> {
+        while let Some((glyph_id, &glyph_index)) = self.glyph_id_to_glyph_index.next() {
+            if glyph_index != u16::MAX {
+                return Some((glyph_id as u16, glyph_index))
+            }
+        }
+        None
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde-serialization", derive(Serialize, Deserialize))]
 pub struct PositionedGlyph {
     pub bounds: Rect<f32>,
     pub subpixel_x: f32,
     pub glyph_index: u16,
+    /// The style of the glyph, copied from its `GlyphPosition`.
+    pub style: u32,
+    /// The factor by which the glyph, rasterized at the point size that it was positioned with,
+    /// is scaled to fill `bounds`. This is copied from its `GlyphPosition`.
+    pub scale: f32,
+}
+
+impl PositionedGlyph {
+    /// Returns which of the `1 / subpixel_granularity` horizontal subpixel offsets this glyph was
+    /// snapped to, from zero up to but not including that count.
+    ///
+    /// `subpixel_granularity` must be the one that the glyph was positioned with, so that a
+    /// renderer can look up coverage rasterized at the same offset. An offset of a whole pixel
+    /// falls into bucket zero.
+    #[inline]
+    pub fn subpixel_bucket(&self, subpixel_granularity: f32) -> u32 {
+        let bucket_count = cmp::max((1.0 / subpixel_granularity).round() as u32, 1);
+        (self.subpixel_x / subpixel_granularity).round() as u32 % bucket_count
+    }
+
+    /// Returns a vertex for this glyph at the top left corner of its bounds, moved by the given
+    /// transform. `subpixel_granularity` must be the one that the glyph was positioned with.
+    #[inline]
+    pub fn vertex(&self, subpixel_granularity: f32, transform: &Matrix2D<f32>) -> GlyphVertex {
+        let position = transform.transform_point(&self.bounds.origin);
+        GlyphVertex {
+            x: position.x,
+            y: position.y,
+            glyph_index: self.glyph_index as u32,
+            subpixel_bucket: self.subpixel_bucket(subpixel_granularity),
+        }
+    }
+}
+
+/// All the placements of one glyph in a layout, returned by `Typesetter::glyph_instances()`.
+#[derive(Clone, Debug)]
+pub struct GlyphInstances {
+    /// The index of the glyph in the glyph store.
+    pub glyph_index: u16,
+    /// The subpixel offset that the glyphs were snapped to, if they were grouped by it, or zero
+    /// otherwise. See `PositionedGlyph::subpixel_bucket()`.
+    pub subpixel_bucket: u32,
+    /// The glyphs, in layout order.
+    pub glyphs: Vec<PositionedGlyph>,
+}
+
+impl GlyphInstances {
+    /// Groups the given glyphs by glyph index, and also by subpixel offset if
+    /// `by_subpixel_offset` is true, so that a renderer that rasterizes each offset separately
+    /// can draw each group from one rasterization. Groups are sorted by glyph index and then by
+    /// subpixel offset. `subpixel_granularity` must be the one that the glyphs were positioned
+    /// with.
+    pub fn group(positioned_glyphs: &[PositionedGlyph],
+                 subpixel_granularity: f32,
+                 by_subpixel_offset: bool)
+                 -> Vec<GlyphInstances> {
+        let mut keyed_glyphs: Vec<(u16, u32, PositionedGlyph)> =
+            positioned_glyphs.iter().map(|positioned_glyph| {
+                let subpixel_bucket = if by_subpixel_offset {
+                    positioned_glyph.subpixel_bucket(subpixel_granularity)
+                } else {
+                    0
+                };
+                (positioned_glyph.glyph_index, subpixel_bucket, *positioned_glyph)
+            }).collect();
+
+        // The sort is stable, so each group stays in layout order.
+        keyed_glyphs.sort_by_key(|&(glyph_index, subpixel_bucket, _)| {
+            (glyph_index, subpixel_bucket)
+        });
+
+        let mut groups: Vec<GlyphInstances> = vec![];
+        for (glyph_index, subpixel_bucket, positioned_glyph) in keyed_glyphs {
+            match groups.last_mut() {
+                Some(group) if group.glyph_index == glyph_index &&
+                        group.subpixel_bucket == subpixel_bucket => {
+                    group.glyphs.push(positioned_glyph);
+                    continue
+                }
+                _ => {}
+            }
+            groups.push(GlyphInstances {
+                glyph_index: glyph_index,
+                subpixel_bucket: subpixel_bucket,
+                glyphs: vec![positioned_glyph],
+            })
+        }
+        groups
+    }
+}
+
+/// A positioned glyph in a form that can be copied straight into a vertex buffer, one vertex per
+/// glyph, for example for instanced rendering.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GlyphVertex {
+    /// The transformed x position of the top left corner of the glyph's bounds, in device pixels.
+    pub x: f32,
+    /// The transformed y position of the top left corner of the glyph's bounds, in device pixels.
+    pub y: f32,
+    /// The index of the glyph in the glyph store.
+    pub glyph_index: u32,
+    /// The subpixel offset that the glyph was snapped to. See `PositionedGlyph::subpixel_bucket()`.
+    pub subpixel_bucket: u32,
+}
+
+impl GlyphVertex {
+    /// Returns a vertex for each of the given glyphs, in order. See `PositionedGlyph::vertex()`.
+    pub fn from_positioned_glyphs(positioned_glyphs: &[PositionedGlyph],
+                                  subpixel_granularity: f32,
+                                  transform: &Matrix2D<f32>)
+                                  -> Vec<GlyphVertex> {
+        positioned_glyphs.iter().map(|positioned_glyph| {
+            positioned_glyph.vertex(subpixel_granularity, transform)
+        }).collect()
+    }
+
+    /// Like `from_positioned_glyphs()`, but writes the vertices into the given slice, for example
+    /// a mapped buffer, and returns how many were written.
+    ///
+    /// If the slice is too short, the glyphs that don't fit are left out.
+    pub fn fill_from_positioned_glyphs(positioned_glyphs: &[PositionedGlyph],
+                                       subpixel_granularity: f32,
+                                       transform: &Matrix2D<f32>,
+                                       vertices: &mut [GlyphVertex])
+                                       -> usize {
+        for (vertex, positioned_glyph) in vertices.iter_mut().zip(positioned_glyphs) {
+            *vertex = positioned_glyph.vertex(subpixel_granularity, transform)
+        }
+        cmp::min(vertices.len(), positioned_glyphs.len())
+    }
 }
 